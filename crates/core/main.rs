@@ -6,7 +6,9 @@ use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, Env
 
 use lib_core::server::config::{init_config, CONFIG_SERVER};
 use services::handler::{
-    playurl::PlayurlRouter, test::RouterTest, test_intercept::TestInterceptRouter, InterceptHandler,
+    capture::CaptureRouter, errors::ErrorCatalogRouter, playurl::PlayurlRouter,
+    proxy::ProxyHandler, purify::PurifyRouter, status::StatusRouter, test::RouterTest,
+    test_intercept::TestInterceptRouter,
 };
 
 #[tokio::main]
@@ -19,21 +21,42 @@ async fn main() {
 
     init_config();
 
+    services::init_error_mapper();
+
+    services::init_device_profiles();
+    services::init_concurrency_limit();
+    services::init_proxy_pools();
+
     if cfg!(test) || cfg!(debug_assertions) {
         tracing::warn!("Running in test/debug mode, will IGNORE invalid certificates!!! For safety, please run in release mode.")
     }
 
     let app = axum::Router::new()
         .merge(PlayurlRouter::new())
+        .merge(PurifyRouter::new())
         .merge(TestInterceptRouter::new())
+        .merge(CaptureRouter::new())
+        .merge(StatusRouter::new())
+        .merge(ErrorCatalogRouter::new())
         .nest("/test", RouterTest::new())
-        .fallback::<_, ()>(InterceptHandler::default())
+        .fallback::<_, ()>(ProxyHandler::from_config())
+        .layer(axum::middleware::from_fn(
+            services::status_policy_middleware,
+        ))
+        .layer(axum::middleware::from_fn(services::rate_limit_middleware))
+        .layer(axum::middleware::from_fn(services::capture_middleware))
+        .layer(axum::middleware::from_fn(services::locale_middleware))
         .layer(OtelInResponseLayer::default())
         .layer(OtelAxumLayer::default());
 
     let listen = CONFIG_SERVER.get().unwrap().listen;
     let listener = tokio::net::TcpListener::bind(listen).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+    )
+    .await
+    .unwrap();
 
     opentelemetry::global::shutdown_tracer_provider();
 }
@@ -1 +1,3 @@
 pub mod buvid;
+pub mod fp;
+pub mod profile;
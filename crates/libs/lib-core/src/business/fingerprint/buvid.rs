@@ -1,5 +1,116 @@
-pub struct Buvid {
+use serde::{Deserialize, Serialize};
 
+use std::fmt;
+
+use lib_utils::calc_md5_uppercase;
+
+/// Device ID a local `buvid` is generated from, which decides its prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuvidSource {
+    /// `XY`, from MAC address
+    Mac,
+    /// `XX`, from `Settings.Secure.ANDROID_ID`
+    AndroidId,
+    /// `XU`, from `MediaDrm` device unique ID
+    DrmId,
+}
+
+impl BuvidSource {
+    pub const fn prefix(self) -> &'static str {
+        match self {
+            Self::Mac => "XY",
+            Self::AndroidId => "XX",
+            Self::DrmId => "XU",
+        }
+    }
+
+    pub fn from_prefix(prefix: &str) -> Option<Self> {
+        match prefix {
+            "XY" => Some(Self::Mac),
+            "XX" => Some(Self::AndroidId),
+            "XU" => Some(Self::DrmId),
+            _ => None,
+        }
+    }
+}
+
+/// Local `buvid` of APP, generated on device.
+///
+/// Like `XU` + `md5[2]` + `md5[12]` + `md5[22]` + `md5`, where `md5` is the
+/// uppercase MD5 of the device ID.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Buvid(String);
+
+impl Buvid {
+    /// Length of a local `buvid`
+    pub const LEN: usize = 37;
+
+    /// Generate from given device ID, the same ID always gives the same one.
+    pub fn new(source: BuvidSource, device_id: &str) -> Self {
+        let md5 = calc_md5_uppercase!(device_id);
+
+        let mut buvid = String::with_capacity(Self::LEN);
+        buvid.push_str(source.prefix());
+        for i in [2, 12, 22] {
+            buvid.push_str(&md5[i..i + 1]);
+        }
+        buvid.push_str(&md5);
+
+        Self(buvid)
+    }
+
+    /// Parse a local `buvid`, `None` if not a valid one.
+    pub fn parse(buvid: &str) -> Option<Self> {
+        if buvid.len() != Self::LEN || !buvid.is_ascii() {
+            return None;
+        }
+
+        BuvidSource::from_prefix(&buvid[0..2])?;
+        let md5 = &buvid[5..];
+        if !md5
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'A'..=b'F').contains(&b))
+        {
+            return None;
+        }
+        let picked = [2, 12, 22].map(|i| md5.as_bytes()[i]);
+        if buvid.as_bytes()[2..5] != picked {
+            return None;
+        }
+
+        Some(Self(buvid.to_owned()))
+    }
+
+    #[inline]
+    pub fn source(&self) -> BuvidSource {
+        BuvidSource::from_prefix(&self.0[0..2]).expect("Buvid should be valid")
+    }
+
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Buvid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl TryFrom<String> for Buvid {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value).ok_or_else(|| format!("Invalid buvid: {}", value))
+    }
+}
+
+impl From<Buvid> for String {
+    fn from(value: Buvid) -> Self {
+        value.0
+    }
 }
 
 // API: app.bilibili.com/x/polymer/buvid/get
@@ -14,3 +125,28 @@ pub mod x_polymer_buvid_get {
         pub match_device: String,
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_buvid() {
+        let buvid = Buvid::new(BuvidSource::DrmId, "drm_id");
+        assert_eq!(buvid.as_str().len(), Buvid::LEN);
+        assert!(buvid.as_str().starts_with("XU"));
+        assert_eq!(buvid.source(), BuvidSource::DrmId);
+        assert_eq!(buvid, Buvid::new(BuvidSource::DrmId, "drm_id"));
+        assert_eq!(Buvid::parse(buvid.as_str()), Some(buvid.clone()));
+
+        let mac = Buvid::new(BuvidSource::Mac, "02:00:00:00:00:00");
+        assert!(mac.as_str().starts_with("XY"));
+        assert_ne!(&mac.as_str()[2..], &buvid.as_str()[2..]);
+
+        // Picked chars not matching
+        let mut invalid = buvid.to_string();
+        invalid.replace_range(2..5, "ZZZ");
+        assert_eq!(Buvid::parse(&invalid), None);
+        assert_eq!(Buvid::parse("XU1234"), None);
+    }
+}
@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+
+use std::fmt::{self, Write};
+
+use lib_utils::{calc_md5, str_concat};
+
+use super::buvid::Buvid;
+
+/// Offset of Beijing time, in which the time in `fp` is
+const UTC_OFFSET_SECS: u64 = 8 * 60 * 60;
+
+/// `fp_local` of APP, which is also `fp_remote` before synced with upstream.
+///
+/// Like `md5(buvid + model + radio_ver)` + `yyyyMMddHHmmss` + 16 random hex
+/// chars + 2 hex chars of checksum.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Fp(String);
+
+impl Fp {
+    /// Length of a `fp`
+    pub const LEN: usize = 64;
+
+    /// Generate `fp` of the device, `generated_at` in seconds since
+    /// UNIX_EPOCH and `random` of 16 hex chars.
+    pub fn new(
+        buvid: &Buvid,
+        model: &str,
+        radio_ver: &str,
+        generated_at: u64,
+        random: &str,
+    ) -> Self {
+        debug_assert!(random.len() == 16, "random of fp should be 16 hex chars");
+
+        let mut fp = String::with_capacity(Self::LEN);
+        fp.push_str(&calc_md5!(str_concat!(buvid.as_str(), model, radio_ver)));
+        fp.push_str(&format_time(generated_at));
+        fp.push_str(random);
+
+        let checksum = checksum(&fp);
+        write!(fp, "{:02x}", checksum).unwrap();
+
+        Self(fp)
+    }
+
+    /// Whether given `fp` is valid, i.e. with the checksum matched.
+    pub fn is_valid(fp: &str) -> bool {
+        fp.len() == Self::LEN
+            && fp.bytes().all(|b| b.is_ascii_hexdigit())
+            && u8::from_str_radix(&fp[62..], 16).is_ok_and(|c| c == checksum(&fp[..62]))
+    }
+
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Fp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Sum of bytes in hex of the first 62 chars, wrapped.
+#[inline]
+fn checksum(fp: &str) -> u8 {
+    let len = fp.len().min(62) & !1;
+    (0..len)
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&fp[i..i + 2], 16).unwrap_or_default())
+        .fold(0u8, u8::wrapping_add)
+}
+
+/// Format as `yyyyMMddHHmmss`, in Beijing time.
+fn format_time(ts: u64) -> String {
+    let ts = ts + UTC_OFFSET_SECS;
+    let (days, secs) = (ts / 86400, ts % 86400);
+
+    // Days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z % 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::buvid::BuvidSource;
+    use super::*;
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(0), "19700101080000");
+        assert_eq!(format_time(951782400), "20000229080000");
+        assert_eq!(format_time(1709222399), "20240229235959");
+    }
+
+    #[test]
+    fn test_fp() {
+        let buvid = Buvid::new(BuvidSource::DrmId, "drm_id");
+        let fp = Fp::new(&buvid, "NOH-AN01", "", 1700000000, "0123456789abcdef");
+
+        assert_eq!(fp.as_str().len(), Fp::LEN);
+        assert_eq!(&fp.as_str()[32..46], "20231115061320");
+        assert_eq!(&fp.as_str()[46..62], "0123456789abcdef");
+        assert!(Fp::is_valid(fp.as_str()));
+        assert_eq!(
+            fp,
+            Fp::new(&buvid, "NOH-AN01", "", 1700000000, "0123456789abcdef")
+        );
+
+        let mut invalid = fp.to_string();
+        invalid.replace_range(46..47, if &invalid[46..47] == "0" { "1" } else { "0" });
+        assert!(!Fp::is_valid(&invalid));
+    }
+}
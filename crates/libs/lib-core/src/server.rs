@@ -6,3 +6,5 @@ pub mod config;
 pub mod ctx;
 /// 健康报告组件(旧)
 pub mod health;
+/// 限流组件
+pub mod rate_limit;
@@ -0,0 +1,169 @@
+use std::{
+    borrow::Borrow,
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// A simple in-memory cache, entries expire after `ttl` and no more than
+/// `capacity` entries are kept.
+#[derive(Debug)]
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<Entries<K, V>>,
+}
+
+#[derive(Debug)]
+struct Entries<K, V> {
+    /// Values with their expiry and the sequence of insertion
+    map: HashMap<K, (Instant, u64, V)>,
+    /// Keys by the time inserted, i.e. expiring soonest first, as all entries
+    /// share the same `ttl`.
+    ///
+    /// Ones replaced are left until popped, skipped as their sequence differs
+    /// from the one in `map`.
+    order: VecDeque<(Instant, u64, K)>,
+    seq: u64,
+}
+
+impl<K: Eq + Hash, V> Entries<K, V> {
+    #[inline]
+    fn is_current(&self, seq: u64, key: &K) -> bool {
+        matches!(self.map.get(key), Some((_, s, _)) if *s == seq)
+    }
+
+    /// Remove the entry expiring soonest.
+    fn pop_soonest(&mut self) {
+        while let Some((_, seq, key)) = self.order.pop_front() {
+            if self.is_current(seq, &key) {
+                self.map.remove(&key);
+                return;
+            }
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> TtlCache<K, V> {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            entries: Mutex::new(Entries {
+                map: HashMap::with_capacity(capacity.min(64)),
+                order: VecDeque::with_capacity(capacity.min(64)),
+                seq: 0,
+            }),
+        }
+    }
+
+    #[inline]
+    fn entries(&self) -> MutexGuard<'_, Entries<K, V>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Get the value cached, `None` if not found or expired.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut entries = self.entries();
+        match entries.map.get(key) {
+            Some((expire_at, _, value)) if *expire_at > Instant::now() => Some(value.clone()),
+            Some(_) => {
+                entries.map.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Cache the value.
+    ///
+    /// Expired entries are purged first, then the one expiring soonest when
+    /// full, without scanning all entries.
+    pub fn insert(&self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut entries = self.entries();
+
+        while entries
+            .order
+            .front()
+            .is_some_and(|(expire_at, _, _)| *expire_at <= now)
+        {
+            if let Some((_, seq, key)) = entries.order.pop_front() {
+                if entries.is_current(seq, &key) {
+                    entries.map.remove(&key);
+                }
+            }
+        }
+
+        if entries.map.len() >= self.capacity && !entries.map.contains_key(&key) {
+            entries.pop_soonest();
+        }
+
+        // Too many replaced ones left, rare as values are inserted once missed
+        if entries.order.len() >= self.capacity.saturating_mul(2) {
+            let mut order = std::mem::take(&mut entries.order);
+            order.retain(|(_, seq, key)| entries.is_current(*seq, key));
+            entries.order = order;
+        }
+
+        let expire_at = now + self.ttl;
+        entries.seq += 1;
+        let seq = entries.seq;
+        entries.order.push_back((expire_at, seq, key.clone()));
+        entries.map.insert(key, (expire_at, seq, value));
+    }
+
+    /// Count of entries cached, including expired ones not purged yet.
+    pub fn len(&self) -> usize {
+        self.entries().map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ttl_cache() {
+        let cache = TtlCache::new(Duration::from_secs(60), 2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("c", 3);
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("c"), Some(3));
+
+        // Replaced, the one expiring soonest is now `c`
+        cache.insert("b", 4);
+        cache.insert("d", 5);
+        assert_eq!(cache.get("c"), None);
+        assert_eq!(cache.get("b"), Some(4));
+        assert_eq!(cache.get("d"), Some(5));
+
+        // Replaced ones not piled up
+        for value in 0..10 {
+            cache.insert("d", value);
+        }
+        assert_eq!(cache.len(), 2);
+        assert!(cache.entries().order.len() <= 4);
+
+        let cache = TtlCache::new(Duration::ZERO, 2);
+        cache.insert("a", 1);
+        assert_eq!(cache.get("a"), None);
+        assert!(cache.is_empty());
+    }
+}
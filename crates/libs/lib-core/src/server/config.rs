@@ -1,6 +1,13 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use std::{net::SocketAddr, sync::OnceLock};
+use lib_utils::model::response::StatusPolicy;
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
 
 static CONFIG_VERISON: &'static str = "0.1.0";
 
@@ -12,38 +19,793 @@ pub static SERVER_VERSION: &'static str = concat!(
 
 pub static CONFIG_SERVER: OnceLock<ServerConfigServer> = OnceLock::new();
 
+pub static CONFIG_INTERCEPT: OnceLock<ServerConfigIntercept> = OnceLock::new();
+
+pub static CONFIG_ROUTES: OnceLock<Vec<RouteConfig>> = OnceLock::new();
+
+pub static CONFIG_PROXY_POOLS: OnceLock<Vec<ProxyPoolConfig>> = OnceLock::new();
+
+pub static CONFIG_RETRY: OnceLock<RetryConfig> = OnceLock::new();
+
+pub static CONFIG_TIMEOUTS: OnceLock<UpstreamTimeoutConfig> = OnceLock::new();
+
+pub static CONFIG_CAPTURE: OnceLock<CaptureConfig> = OnceLock::new();
+
+pub static CONFIG_RATE_LIMIT: OnceLock<RateLimitConfig> = OnceLock::new();
+
+pub static CONFIG_CONCURRENCY: OnceLock<ConcurrencyConfig> = OnceLock::new();
+
+pub static CONFIG_DEVICE: OnceLock<DeviceConfig> = OnceLock::new();
+
+/// Env name of the config file path, default to `config.json`
+static CONFIG_PATH_ENV: &'static str = "SERVER_CONFIG_PATH";
+
 #[tracing::instrument]
 pub fn init_config() {
     tracing::info!("Initializing server config...");
 
-    let _ = CONFIG_SERVER.set(ServerConfigServer::default());
+    let path = std::env::var(CONFIG_PATH_ENV).unwrap_or_else(|_| "config.json".to_owned());
+    let ServerConfigFile {
+        server,
+        intercept,
+        routes,
+        proxy_pools,
+        retry,
+        timeouts,
+        capture,
+        rate_limit,
+        concurrency,
+        device,
+    } = ServerConfigFile::load(&path);
+
+    let _ = CONFIG_SERVER.set(server);
+    let _ = CONFIG_INTERCEPT.set(intercept);
+    let _ = CONFIG_ROUTES.set(routes);
+    let _ = CONFIG_PROXY_POOLS.set(proxy_pools);
+    let _ = CONFIG_RETRY.set(retry);
+    let _ = CONFIG_TIMEOUTS.set(timeouts);
+    let _ = CONFIG_CAPTURE.set(capture);
+    let _ = CONFIG_RATE_LIMIT.set(rate_limit);
+    let _ = CONFIG_CONCURRENCY.set(concurrency);
+    let _ = CONFIG_DEVICE.set(device);
 }
 
 pub struct ServerConfig {
     pub config_ver: &'static str,
 }
 
+/// Layout of the config file, each section is optional.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+struct ServerConfigFile {
+    server: ServerConfigServer,
+    intercept: ServerConfigIntercept,
+    routes: Vec<RouteConfig>,
+    proxy_pools: Vec<ProxyPoolConfig>,
+    retry: RetryConfig,
+    timeouts: UpstreamTimeoutConfig,
+    capture: CaptureConfig,
+    rate_limit: RateLimitConfig,
+    concurrency: ConcurrencyConfig,
+    device: DeviceConfig,
+}
+
+impl ServerConfigFile {
+    /// Load config from given JSON file, fallback to default when not found or invalid.
+    fn load(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+
+        let content = match std::fs::read(path) {
+            Ok(content) => content,
+            Err(e) => {
+                tracing::warn!(
+                    "Config file [{}] not loaded, use default: {}",
+                    path.display(),
+                    e
+                );
+                return Self::default();
+            }
+        };
+
+        match serde_json::from_slice(&content) {
+            Ok(config) => {
+                tracing::info!("Config file [{}] loaded", path.display());
+                config
+            }
+            Err(e) => {
+                tracing::error!(
+                    "Config file [{}] is invalid, use default: {}",
+                    path.display(),
+                    e
+                );
+                Self::default()
+            }
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(default)]
 pub struct ServerConfigServer {
     /// HTTP/gRPC server addr
     pub listen: SocketAddr,
+    /// Max size in bytes of a request or response body to be buffered,
+    /// e.g. when an interceptor needs to see the whole body.
+    pub max_buffer_size: usize,
+    /// HTTP status of error responses, `"compatible"` (always `200`) by
+    /// default or `"http"`, overridden by the one of the route
+    pub status_policy: StatusPolicy,
 }
 
 impl Default for ServerConfigServer {
     fn default() -> Self {
         Self {
             listen: ([127, 0, 0, 1], 2663).into(),
+            max_buffer_size: 16 * 1024 * 1024,
+            status_policy: StatusPolicy::Compatible,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct ServerConfigIntercept {
+    /// Purification of app responses
+    pub purify: PurifyConfig,
+    /// Declarative rules rewriting JSON responses
+    pub rules: Vec<RewriteRuleConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct PurifyConfig {
+    /// Remove ad cards, splash ads and promoted search words
+    pub ad: bool,
+    /// Remove banners
+    pub banner: bool,
+    /// Remove live-room injections
+    pub live: bool,
+    /// Remove game promotions
+    pub game: bool,
+}
+
+impl PurifyConfig {
+    /// Whether any category of purification is enabled.
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.ad || self.banner || self.live || self.game
+    }
+}
+
+impl Default for PurifyConfig {
+    fn default() -> Self {
+        Self {
+            ad: true,
+            banner: true,
+            live: true,
+            game: true,
         }
     }
 }
 
+/// A rule rewriting JSON responses, e.g.
+///
+/// ```json
+/// {
+///     "path": "/x/v2/feed/*",
+///     "selector": "$.data.items",
+///     "action": "filter",
+///     "predicate": { "key": "card_goto", "op": "eq", "value": "ad_av" }
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RewriteRuleConfig {
+    /// Request path, exact one or prefix ending with `*`
+    pub path: String,
+    /// JSONPath-like selector, like `$.data.items[*].ad_info`
+    pub selector: String,
+    #[serde(flatten)]
+    pub action: RewriteAction,
+}
+
+/// Action applied to the values selected
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum RewriteAction {
+    /// Remove the selected values
+    Remove,
+    /// Replace the selected values, only when exist
+    Replace { value: Value },
+    /// Set the selected values, inserting missing object fields
+    Set { value: Value },
+    /// Remove items matching the predicate from the selected arrays
+    Filter { predicate: RewritePredicate },
+}
+
+/// Predicate on a field of an array item
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RewritePredicate {
+    /// Key of the item field, nested ones separated by `.`
+    pub key: String,
+    pub op: PredicateOp,
+    /// Value to compare with, not needed by `exists` and `truthy`
+    #[serde(default)]
+    pub value: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PredicateOp {
+    /// Field exists and is not `null`
+    Exists,
+    /// Field is `true`, non-zero number or non-empty string
+    Truthy,
+    /// Field equals the value
+    Eq,
+    /// Field exists and does not equal the value
+    Ne,
+    /// Field is a string starting with the value
+    StartsWith,
+}
+
+/// A route of the full server mode, proxying requests with path prefix to
+/// the upstream, e.g.
+///
+/// ```json
+/// {
+///     "prefix": "/x/v2/",
+///     "upstream": "app",
+///     "interceptors": ["purify", "rewrite"],
+///     "sanitize": { "request": ["x-forwarded-for"] },
+///     "cache": { "ttl": 60 },
+///     "proxy_pool": "hk",
+///     "timeouts": { "request_ms": 30000 },
+///     "capture": true,
+///     "status_policy": "http"
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RouteConfig {
+    /// Path prefix, the longest one matched is used
+    pub prefix: String,
+    pub upstream: UpstreamConfig,
+    /// Interceptors applied to JSON responses, in order
+    #[serde(default)]
+    pub interceptors: Vec<RouteInterceptorKind>,
+    #[serde(default)]
+    pub sanitize: SanitizeConfig,
+    /// Cache responses of `GET` requests, disabled by default
+    #[serde(default)]
+    pub cache: Option<RouteCacheConfig>,
+    /// Name of the proxy pool to request the upstream through
+    #[serde(default)]
+    pub proxy_pool: Option<String>,
+    /// Timeouts overriding the ones of the upstream type
+    #[serde(default)]
+    pub timeouts: TimeoutConfig,
+    /// Capture upstream requests and responses of the route, see [CaptureConfig]
+    #[serde(default)]
+    pub capture: bool,
+    /// HTTP status of error responses of the route, the server's one if not set
+    #[serde(default)]
+    pub status_policy: Option<StatusPolicy>,
+}
+
+/// Upstream of a route, `"api"`, `"app"`, `"grpc"` or `{ "custom": "https://..." }`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UpstreamConfig {
+    /// `https://api.bilibili.com`
+    Api,
+    /// `https://app.bilibili.com`
+    App,
+    /// `https://grpc.biliapi.net`
+    Grpc,
+    /// Custom upstream, starting with `https://` or `http://`
+    Custom(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteInterceptorKind {
+    /// Built-in purification, for paths supported only
+    Purify,
+    /// Rewrite rules declared in `intercept.rules`
+    Rewrite,
+}
+
+/// Headers removed when proxying
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct SanitizeConfig {
+    /// Request headers removed before sent to upstream
+    pub request: Vec<String>,
+    /// Response headers removed before sent to client
+    pub response: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct RouteCacheConfig {
+    /// Seconds a response is cached for
+    pub ttl: u64,
+    /// Max count of responses cached
+    pub capacity: usize,
+}
+
+impl Default for RouteCacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl: 60,
+            capacity: 1024,
+        }
+    }
+}
+
+/// A named pool of proxies, e.g. one for each area
+///
+/// ```json
+/// {
+///     "name": "hk",
+///     "proxies": [
+///         { "url": "socks5://127.0.0.1:1080", "weight": 2 },
+///         { "url": "http://127.0.0.1:8080" }
+///     ],
+///     "health_check_interval": 60,
+///     "concurrency": { "max_concurrent": 16 }
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ProxyPoolConfig {
+    pub name: String,
+    pub proxies: Vec<PoolProxyConfig>,
+    /// Consecutive failures before a proxy is ejected
+    pub max_failures: u32,
+    /// Seconds an ejected proxy waits before being re-admitted
+    pub eject_secs: u64,
+    /// Seconds between active health checks, `0` to disable
+    pub health_check_interval: u64,
+    /// Url requested through each proxy when checking, a built-in one if not set
+    pub health_check_url: Option<String>,
+    /// Limit of concurrent requests through the pool, unlimited if not set
+    pub concurrency: Option<ConcurrencyConfig>,
+}
+
+impl Default for ProxyPoolConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            proxies: Vec::new(),
+            max_failures: 3,
+            eject_secs: 30,
+            health_check_interval: 60,
+            health_check_url: None,
+            concurrency: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PoolProxyConfig {
+    /// Proxy url, `http://`, `https://` or `socks5://`
+    pub url: String,
+    /// Weight in round-robin, default to `1`
+    #[serde(default = "PoolProxyConfig::default_weight")]
+    pub weight: u32,
+}
+
+impl PoolProxyConfig {
+    #[inline]
+    fn default_weight() -> u32 {
+        1
+    }
+}
+
+/// Retry of idempotent upstream requests, i.e. `GET` ones and read-only gRPC
+/// methods, on connection errors, timeouts, 5xx responses and unavailable gRPC
+/// services
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// Max retries of a request, `0` to disable
+    pub max_retries: u32,
+    /// Millis of backoff before the first retry, doubled each time with jitter
+    pub base_delay_ms: u64,
+    /// Max millis of backoff
+    pub max_delay_ms: u64,
+    /// Pick another proxy from the pool between attempts
+    pub switch_proxy: bool,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            base_delay_ms: 100,
+            max_delay_ms: 2000,
+            switch_proxy: true,
+        }
+    }
+}
+
+/// Timeouts of upstream requests by upstream type, e.g.
+///
+/// ```json
+/// {
+///     "default": { "connect_ms": 10000, "request_ms": 15000, "idle_ms": 3600000 },
+///     "grpc": { "request_ms": 10000 }
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(default)]
+pub struct UpstreamTimeoutConfig {
+    /// Fallback of all upstream types, built-in ones if not set
+    pub default: TimeoutConfig,
+    /// `https://api.bilibili.com`
+    pub api: TimeoutConfig,
+    /// `https://app.bilibili.com`
+    pub app: TimeoutConfig,
+    /// `https://grpc.biliapi.net`
+    pub grpc: TimeoutConfig,
+    /// Custom upstreams
+    pub custom: TimeoutConfig,
+}
+
+/// Timeouts in millis, the ones not set fall back to the upper level
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct TimeoutConfig {
+    /// Timeout of connecting, including handshakes with the proxy
+    pub connect_ms: Option<u64>,
+    /// Timeout of a whole request, also the deadline of gRPC requests
+    pub request_ms: Option<u64>,
+    /// Pooled connections idle for this long are closed
+    pub idle_ms: Option<u64>,
+}
+
+impl TimeoutConfig {
+    /// Fill the ones not set with those of `other`.
+    #[inline]
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            connect_ms: self.connect_ms.or(other.connect_ms),
+            request_ms: self.request_ms.or(other.request_ms),
+            idle_ms: self.idle_ms.or(other.idle_ms),
+        }
+    }
+}
+
+/// Capture of upstream request/response pairs for debugging, e.g.
+///
+/// ```json
+/// {
+///     "dir": "captures",
+///     "max_files": 1000,
+///     "admin_tokens": ["a-long-random-token"],
+///     "redact": ["x-bili-ticket"]
+/// }
+/// ```
+///
+/// Requests are captured for all routes when `enabled`, for routes with
+/// `capture` set, or when header `x-roamingh-capture` is one of `admin_tokens`.
+/// Captures are browsed at `/admin/captures` with the same header.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CaptureConfig {
+    /// Capture requests of all routes
+    pub enabled: bool,
+    /// Directory captures are saved to, one JSON file each
+    pub dir: String,
+    /// Max count of captures kept, the oldest ones are removed first
+    pub max_files: usize,
+    /// Max size in bytes of a body to be decoded, larger ones are omitted
+    pub max_body_size: usize,
+    /// Tokens of admins allowed to trigger captures and browse them
+    pub admin_tokens: Vec<String>,
+    /// Headers and query keys whose values are redacted, besides built-in
+    /// ones like `cookie` and `access_key`
+    pub redact: Vec<String>,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: "captures".to_owned(),
+            max_files: 1000,
+            max_body_size: 1024 * 1024,
+            admin_tokens: Vec::new(),
+            redact: Vec::new(),
+        }
+    }
+}
+
+/// Rate limiting by route groups, e.g.
+///
+/// ```json
+/// {
+///     "trusted_proxies": ["127.0.0.1"],
+///     "groups": [
+///         {
+///             "prefix": "/x/v2/",
+///             "key": "access_key",
+///             "hit": { "rate": 10, "burst": 50 },
+///             "miss": { "rate": 1, "burst": 10 },
+///             "ip_miss": { "rate": 5, "burst": 50 }
+///         }
+///     ]
+/// }
+/// ```
+///
+/// Cache hits and misses are limited by separate buckets, as only misses
+/// reach the upstream.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// Proxies in front of the server, whose `X-Forwarded-For` is trusted
+    pub trusted_proxies: Vec<IpAddr>,
+    /// Groups of routes, the one with the longest prefix matched is used
+    pub groups: Vec<RateLimitGroupConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RateLimitGroupConfig {
+    /// Path prefix of the group
+    pub prefix: String,
+    #[serde(default)]
+    pub key: RateLimitKey,
+    /// Limit of responses from cache, unlimited if not set
+    #[serde(default)]
+    pub hit: Option<BucketConfig>,
+    /// Limit of requests to the upstream, unlimited if not set
+    #[serde(default)]
+    pub miss: Option<BucketConfig>,
+    /// Limit of responses from cache by client IP along with the user, only
+    /// for [RateLimitKey::AccessKey], `hit` if not set.
+    ///
+    /// Users behind the same IP share it, so it should be looser.
+    #[serde(default)]
+    pub ip_hit: Option<BucketConfig>,
+    /// Limit of requests to the upstream by client IP along with the user,
+    /// only for [RateLimitKey::AccessKey], `miss` if not set.
+    #[serde(default)]
+    pub ip_miss: Option<BucketConfig>,
+}
+
+/// What requests are limited by
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitKey {
+    /// The user, by `x-bili-mid` or `mid` in query, or `access_key` if not
+    /// given, along with client IP, since neither is verified when limiting,
+    /// or client IP only if not logged in
+    AccessKey,
+    /// Client IP, IPv6 ones by /64
+    #[default]
+    Ip,
+    /// All requests of the group together
+    Global,
+}
+
+/// A token bucket
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct BucketConfig {
+    /// Tokens refilled per second
+    pub rate: f64,
+    /// Max tokens, i.e. requests allowed in a burst
+    pub burst: u32,
+}
+
+/// Limit of concurrent upstream requests, the exceeding ones queued, so that
+/// bursts won't trip risk control of the upstream
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct ConcurrencyConfig {
+    /// Max concurrent requests, `0` for unlimited
+    pub max_concurrent: usize,
+    /// Max requests waiting in queue, the others fail at once
+    pub max_queued: usize,
+    /// Millis a request waits in queue at most
+    pub queue_timeout_ms: u64,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 0,
+            max_queued: 1024,
+            queue_timeout_ms: 10000,
+        }
+    }
+}
+
+/// Devices presented to upstream, one for each account, see
+/// [DeviceProfileStore](crate::business::fingerprint::profile::DeviceProfileStore)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DeviceConfig {
+    /// JSON file device profiles are persisted in, kept in memory only and
+    /// generated again after restarted if not set
+    pub profiles: Option<PathBuf>,
+    /// Max count of profiles kept besides those of `accounts`, the oldest
+    /// ones are removed first
+    pub max_profiles: usize,
+    /// Upstream accounts, whose profiles are keyed by the id given
+    pub accounts: Vec<DeviceAccount>,
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        Self {
+            profiles: None,
+            max_profiles: 10000,
+            accounts: Vec::new(),
+        }
+    }
+}
+
+/// An upstream account, see [DeviceConfig]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeviceAccount {
+    pub id: String,
+    pub access_key: String,
+}
+
 #[cfg(test)]
 mod test {
-    use crate::server::config::SERVER_VERSION;
+    use crate::server::config::{
+        PredicateOp, RateLimitKey, RewriteAction, RouteInterceptorKind, ServerConfigFile,
+        TimeoutConfig, UpstreamConfig, SERVER_VERSION,
+    };
+
+    use lib_utils::model::response::StatusPolicy;
 
     #[test]
     fn test() {
         println!("{}", SERVER_VERSION)
     }
+
+    #[test]
+    fn test_config_file() {
+        let config: ServerConfigFile = serde_json::from_str(
+            r#"{
+                "intercept": {
+                    "purify": { "live": false },
+                    "rules": [
+                        { "path": "/x/v2/feed/*", "selector": "$.data.items[*].ad_info", "action": "remove" },
+                        {
+                            "path": "/x/v2/feed/index",
+                            "selector": "$.data.items",
+                            "action": "filter",
+                            "predicate": { "key": "card_goto", "op": "starts_with", "value": "ad" }
+                        }
+                    ]
+                },
+                "routes": [
+                    { "prefix": "/x/", "upstream": "api" },
+                    {
+                        "prefix": "/x/v2/",
+                        "upstream": { "custom": "https://app.example.com" },
+                        "interceptors": ["purify", "rewrite"],
+                        "cache": { "ttl": 30 },
+                        "proxy_pool": "hk",
+                        "timeouts": { "request_ms": 30000 },
+                        "capture": true,
+                        "status_policy": "http"
+                    }
+                ],
+                "proxy_pools": [
+                    {
+                        "name": "hk",
+                        "proxies": [
+                            { "url": "socks5://127.0.0.1:1080", "weight": 2 },
+                            { "url": "http://127.0.0.1:8080" }
+                        ],
+                        "health_check_interval": 0,
+                        "concurrency": { "max_concurrent": 8 }
+                    }
+                ],
+                "device": {
+                    "profiles": "device_profiles.json",
+                    "accounts": [{ "id": "main", "access_key": "ak" }]
+                },
+                "timeouts": {
+                    "default": { "connect_ms": 5000, "request_ms": 10000 },
+                    "grpc": { "request_ms": 20000 }
+                },
+                "capture": { "admin_tokens": ["token"] },
+                "rate_limit": {
+                    "trusted_proxies": ["127.0.0.1"],
+                    "groups": [
+                        { "prefix": "/x/", "miss": { "rate": 1, "burst": 10 } },
+                        { "prefix": "/x/v2/", "key": "access_key", "hit": { "rate": 0.5, "burst": 5 }, "ip_hit": { "rate": 5, "burst": 50 } }
+                    ]
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.server.listen, ([127, 0, 0, 1], 2663).into());
+        assert!(!config.intercept.purify.live && config.intercept.purify.ad);
+        assert_eq!(config.intercept.rules.len(), 2);
+        assert!(matches!(
+            config.intercept.rules[0].action,
+            RewriteAction::Remove
+        ));
+        assert!(matches!(
+            &config.intercept.rules[1].action,
+            RewriteAction::Filter { predicate } if predicate.op == PredicateOp::StartsWith
+        ));
+
+        assert_eq!(config.routes[0].upstream, UpstreamConfig::Api);
+        assert!(config.routes[0].cache.is_none());
+        assert_eq!(
+            config.routes[1].upstream,
+            UpstreamConfig::Custom("https://app.example.com".to_owned())
+        );
+        assert_eq!(
+            config.routes[1].interceptors,
+            [RouteInterceptorKind::Purify, RouteInterceptorKind::Rewrite]
+        );
+        assert_eq!(config.routes[1].cache.unwrap().capacity, 1024);
+        assert_eq!(config.routes[1].proxy_pool.as_deref(), Some("hk"));
+        assert_eq!(config.routes[0].timeouts, TimeoutConfig::default());
+        assert!(!config.routes[0].capture && config.routes[1].capture);
+        assert_eq!(config.server.status_policy, StatusPolicy::Compatible);
+        assert_eq!(config.routes[0].status_policy, None);
+        assert_eq!(config.routes[1].status_policy, Some(StatusPolicy::Http));
+
+        let pool = &config.proxy_pools[0];
+        assert_eq!(pool.max_failures, 3);
+        assert_eq!(pool.health_check_interval, 0);
+        assert_eq!(pool.concurrency.unwrap().max_concurrent, 8);
+        assert_eq!(pool.concurrency.unwrap().max_queued, 1024);
+        assert_eq!(config.concurrency.max_concurrent, 0);
+        assert_eq!(
+            pool.proxies.iter().map(|p| p.weight).collect::<Vec<_>>(),
+            [2, 1]
+        );
+
+        let timeouts = config.routes[1]
+            .timeouts
+            .or(config.timeouts.grpc)
+            .or(config.timeouts.default);
+        assert_eq!(
+            timeouts,
+            TimeoutConfig {
+                connect_ms: Some(5000),
+                request_ms: Some(30000),
+                idle_ms: None,
+            }
+        );
+        assert_eq!(
+            config.timeouts.api.or(config.timeouts.default).request_ms,
+            Some(10000)
+        );
+
+        assert_eq!(
+            config.device.profiles.as_deref(),
+            Some(std::path::Path::new("device_profiles.json"))
+        );
+        assert_eq!(config.device.max_profiles, 10000);
+        assert_eq!(config.device.accounts[0].id, "main");
+
+        assert!(!config.capture.enabled);
+        assert_eq!(config.capture.dir, "captures");
+        assert_eq!(config.capture.admin_tokens, ["token"]);
+
+        let rate_limit = &config.rate_limit;
+        assert_eq!(
+            rate_limit.trusted_proxies,
+            [std::net::IpAddr::from([127, 0, 0, 1])]
+        );
+        assert_eq!(rate_limit.groups[0].key, RateLimitKey::Ip);
+        assert!(rate_limit.groups[0].hit.is_none());
+        assert_eq!(rate_limit.groups[0].miss.unwrap().burst, 10);
+        assert_eq!(rate_limit.groups[1].key, RateLimitKey::AccessKey);
+        assert_eq!(rate_limit.groups[1].hit.unwrap().rate, 0.5);
+        assert_eq!(rate_limit.groups[1].ip_hit.unwrap().burst, 50);
+        assert!(rate_limit.groups[1].ip_miss.is_none());
+    }
 }
@@ -0,0 +1,201 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap, VecDeque},
+    hash::{BuildHasher, Hash},
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// Max count of buckets kept, the oldest ones are evicted beyond
+const MAX_BUCKETS: usize = 65536;
+
+/// Count of shards buckets are spread over by key
+const SHARDS: usize = 16;
+
+/// Token buckets keyed by `K`, each refilled with `rate` tokens per second and
+/// holding `burst` tokens at most.
+///
+/// Buckets are spread over shards, each holding [MAX_BUCKETS] / [SHARDS] at
+/// most and evicting the oldest one in constant time once full, so that
+/// flooding with new keys costs no more than limiting known ones.
+#[derive(Debug)]
+pub struct RateLimiter<K> {
+    rate: f64,
+    burst: f64,
+    hasher: RandomState,
+    shards: Box<[Mutex<Shard<K>>]>,
+}
+
+#[derive(Debug)]
+struct Shard<K> {
+    buckets: HashMap<K, Bucket>,
+    /// Keys of buckets in the order created, the oldest first
+    order: VecDeque<K>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl Bucket {
+    #[inline]
+    fn refill(&mut self, rate: f64, burst: f64, now: Instant) {
+        self.tokens = self.tokens_at(rate, burst, now);
+        self.updated_at = now;
+    }
+
+    #[inline]
+    fn tokens_at(&self, rate: f64, burst: f64, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        (self.tokens + elapsed * rate).min(burst)
+    }
+}
+
+impl<K: Eq + Hash + Clone> RateLimiter<K> {
+    pub fn new(rate: f64, burst: u32) -> Self {
+        Self {
+            rate: rate.max(0.0),
+            burst: f64::from(burst.max(1)),
+            hasher: RandomState::new(),
+            shards: (0..SHARDS)
+                .map(|_| {
+                    Mutex::new(Shard {
+                        buckets: HashMap::new(),
+                        order: VecDeque::new(),
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    #[inline]
+    fn shard(&self, key: &K) -> MutexGuard<'_, Shard<K>> {
+        let index = self.hasher.hash_one(key) as usize % SHARDS;
+        self.shards[index]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    #[inline]
+    fn wait(&self, tokens: f64) -> Result<(), Duration> {
+        if tokens >= 1.0 {
+            Ok(())
+        } else if self.rate > 0.0 {
+            Err(Duration::from_secs_f64((1.0 - tokens) / self.rate))
+        } else {
+            Err(Duration::MAX)
+        }
+    }
+
+    /// Check if there's a token of the key, without taking it, or the time to
+    /// wait for one if none left.
+    ///
+    /// Used to check all buckets a request takes tokens from before taking
+    /// any, see [acquire](Self::acquire).
+    pub fn check(&self, key: &K) -> Result<(), Duration> {
+        let now = Instant::now();
+        let tokens = self
+            .shard(key)
+            .buckets
+            .get(key)
+            .map_or(self.burst, |bucket| {
+                bucket.tokens_at(self.rate, self.burst, now)
+            });
+
+        self.wait(tokens)
+    }
+
+    /// Take a token of the key, or the time to wait for one if none left.
+    pub fn acquire(&self, key: K) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut shard = self.shard(&key);
+
+        if !shard.buckets.contains_key(&key) {
+            if shard.order.len() >= MAX_BUCKETS / SHARDS {
+                if let Some(oldest) = shard.order.pop_front() {
+                    shard.buckets.remove(&oldest);
+                }
+            }
+            shard.order.push_back(key.clone());
+        }
+
+        let bucket = shard.buckets.entry(key).or_insert(Bucket {
+            tokens: self.burst,
+            updated_at: now,
+        });
+        bucket.refill(self.rate, self.burst, now);
+
+        let tokens = bucket.tokens;
+        self.wait(tokens)?;
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+
+    /// Count of buckets, including idle ones not evicted yet.
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| {
+                shard
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .buckets
+                    .len()
+            })
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(1.0, 2);
+
+        assert!(limiter.acquire("a").is_ok());
+        assert!(limiter.acquire("a").is_ok());
+        let wait = limiter.acquire("a").unwrap_err();
+        assert!(wait > Duration::ZERO && wait <= Duration::from_secs(1));
+
+        assert!(limiter.acquire("b").is_ok());
+        assert_eq!(limiter.len(), 2);
+
+        // Checked without taking any
+        assert!(limiter.check(&"c").is_ok());
+        assert!(limiter.check(&"b").is_ok());
+        assert!(limiter.acquire("b").is_ok());
+        assert!(limiter.check(&"b").is_err());
+        assert!(limiter.check(&"a").is_err());
+        assert_eq!(limiter.len(), 2);
+
+        let limiter = RateLimiter::new(0.0, 1);
+        assert!(limiter.acquire(()).is_ok());
+        assert_eq!(limiter.acquire(()), Err(Duration::MAX));
+    }
+
+    #[test]
+    fn test_rate_limiter_capped() {
+        let limiter = RateLimiter::new(0.0, 1);
+
+        assert!(limiter.acquire(0).is_ok());
+        assert!(limiter.acquire(0).is_err());
+
+        // Flooded with new keys
+        for key in 1..MAX_BUCKETS as u32 * 2 {
+            assert!(limiter.acquire(key).is_ok());
+        }
+        assert!(limiter.len() <= MAX_BUCKETS);
+        assert!(limiter.len() > MAX_BUCKETS / 2);
+
+        // The oldest ones are evicted, and the latest ones kept
+        assert!(limiter.acquire(0).is_ok());
+        assert!(limiter.acquire(MAX_BUCKETS as u32 * 2 - 1).is_err());
+    }
+}
@@ -0,0 +1,200 @@
+use anyhow::Result;
+use dashmap::DashMap;
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use super::timeout::Timeouts;
+
+/// Max count of clients cached for proxies not pinned
+pub(crate) const MAX_CLIENTS: usize = 256;
+
+/// Clients for proxies not pinned are evicted after idle for this long
+pub(crate) const CLIENT_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+#[derive(Debug)]
+struct Entry<C> {
+    client: C,
+    last_used: Instant,
+    /// Pinned ones, e.g. proxies configured, are never evicted
+    pinned: bool,
+}
+
+/// Clients cached by proxy url.
+///
+/// Clients for proxies not pinned, e.g. rotating ones, are evicted once idle for
+/// `idle_timeout` when a new one cached, or the least recently used one when
+/// `capacity` reached.
+#[derive(Debug)]
+pub(crate) struct ClientCache<C> {
+    /// Client without proxy
+    default: C,
+    capacity: usize,
+    idle_timeout: Duration,
+    clients: Mutex<HashMap<Arc<str>, Entry<C>>>,
+}
+
+impl<C: Clone> ClientCache<C> {
+    pub(crate) fn new(default: C, capacity: usize, idle_timeout: Duration) -> Self {
+        Self {
+            default,
+            capacity,
+            idle_timeout,
+            clients: Mutex::new(HashMap::with_capacity(16)),
+        }
+    }
+
+    #[inline]
+    fn clients(&self) -> MutexGuard<'_, HashMap<Arc<str>, Entry<C>>> {
+        self.clients.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Cache a client which is never evicted.
+    pub(crate) fn pin(&self, proxy: &str, client: C) {
+        self.clients().insert(
+            Arc::from(proxy),
+            Entry {
+                client,
+                last_used: Instant::now(),
+                pinned: true,
+            },
+        );
+    }
+
+    /// Get the client for given proxy, or create one with `f` and cache it.
+    pub(crate) fn get_or_try_insert_with(
+        &self,
+        proxy: Option<&str>,
+        f: impl FnOnce(&str) -> Result<C>,
+    ) -> Result<C> {
+        let Some(proxy) = proxy else {
+            tracing::debug!("proxy is None, use default client");
+            return Ok(self.default.clone());
+        };
+
+        if let Some(entry) = self.clients().get_mut(proxy) {
+            entry.last_used = Instant::now();
+            return Ok(entry.client.clone());
+        }
+
+        // Create without lock held
+        let client = f(proxy)?;
+
+        let now = Instant::now();
+        let mut clients = self.clients();
+
+        if let Some(entry) = clients.get_mut(proxy) {
+            // Created by others meanwhile
+            entry.last_used = now;
+            return Ok(entry.client.clone());
+        }
+
+        clients.retain(|_, e| e.pinned || now.duration_since(e.last_used) < self.idle_timeout);
+
+        if clients.values().filter(|e| !e.pinned).count() >= self.capacity {
+            let lru = clients
+                .iter()
+                .filter(|(_, e)| !e.pinned)
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(k, _)| k.clone());
+            if let Some(lru) = lru {
+                tracing::debug!("Evict client for proxy [{}]", lru);
+                clients.remove(&lru);
+            }
+        }
+
+        clients.insert(
+            Arc::from(proxy),
+            Entry {
+                client: client.clone(),
+                last_used: now,
+                pinned: false,
+            },
+        );
+
+        Ok(client)
+    }
+
+    /// Count of clients cached, excluding the default one.
+    pub(crate) fn len(&self) -> usize {
+        self.clients().len()
+    }
+}
+
+/// [ClientCache]s for clients built with different connect and idle timeouts.
+#[derive(Debug)]
+pub(crate) struct ClientCaches<C> {
+    caches: DashMap<(Duration, Duration), Arc<ClientCache<C>>>,
+}
+
+impl<C: Clone> ClientCaches<C> {
+    pub(crate) fn new() -> Self {
+        Self {
+            caches: DashMap::with_capacity(4),
+        }
+    }
+
+    /// Get the cache for clients built with given timeouts, or create one with
+    /// the default client built by `f`.
+    pub(crate) fn get_or_try_init(
+        &self,
+        timeouts: &Timeouts,
+        f: impl FnOnce() -> Result<C>,
+    ) -> Result<Arc<ClientCache<C>>> {
+        let key = (timeouts.connect, timeouts.idle);
+
+        if let Some(cache) = self.caches.get(&key) {
+            return Ok(cache.clone());
+        }
+
+        let cache = Arc::new(ClientCache::new(f()?, MAX_CLIENTS, CLIENT_IDLE_TIMEOUT));
+        Ok(self.caches.entry(key).or_insert(cache).clone())
+    }
+
+    /// Count of clients cached for proxies, of all caches.
+    pub(crate) fn len(&self) -> usize {
+        self.caches.iter().map(|cache| cache.len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_client_cache() {
+        let cache = ClientCache::new(0, 2, Duration::from_secs(600));
+        cache.pin("pinned", 1);
+
+        let new = |n| move |_: &str| -> Result<i32> { Ok(n) };
+
+        assert_eq!(cache.get_or_try_insert_with(None, new(-1)).unwrap(), 0);
+        assert_eq!(cache.get_or_try_insert_with(Some("a"), new(2)).unwrap(), 2);
+        assert_eq!(cache.get_or_try_insert_with(Some("b"), new(3)).unwrap(), 3);
+        // Cached
+        assert_eq!(cache.get_or_try_insert_with(Some("a"), new(-1)).unwrap(), 2);
+        assert_eq!(cache.len(), 3);
+
+        // `b` is the least recently used
+        assert_eq!(cache.get_or_try_insert_with(Some("c"), new(4)).unwrap(), 4);
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get_or_try_insert_with(Some("b"), new(5)).unwrap(), 5);
+        assert_eq!(
+            cache
+                .get_or_try_insert_with(Some("pinned"), new(-1))
+                .unwrap(),
+            1
+        );
+
+        let cache = ClientCache::new(0, 2, Duration::ZERO);
+        cache.pin("pinned", 1);
+        cache.get_or_try_insert_with(Some("a"), new(2)).unwrap();
+        // `a` is idle and evicted
+        cache.get_or_try_insert_with(Some("b"), new(3)).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get_or_try_insert_with(Some("a"), new(4)).unwrap(), 4);
+    }
+}
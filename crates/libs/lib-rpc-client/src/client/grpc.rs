@@ -1,7 +1,5 @@
-// Compatibility for Tonic using crate http@0.2
-pub(super) mod connect_http02;
-// Compatibility for Tonic using crate http@0.2
-pub mod client_http02;
+pub mod client;
+pub(super) mod connect;
 pub mod proxy;
 #[cfg(feature = "__tls")]
 mod tls;
@@ -0,0 +1,692 @@
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use http::{
+    response::Parts as HttpResponseParts, HeaderMap as HttpHeaderMap, Method as HttpMethod,
+    Request as HttpRequest, Response as HttpResponse, Uri,
+};
+use http_body_util::BodyExt as _;
+use hyper::body::{Body as HttpBody, Incoming};
+use hyper_util::{
+    client::legacy::Client as HyperClient,
+    rt::{TokioExecutor, TokioTimer},
+};
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, OnceLock},
+    task::Poll,
+    time::Duration,
+};
+
+use super::{connect::Connector, proxy::Proxy};
+use crate::{
+    client::{
+        cache::ClientCaches,
+        limit,
+        pool::{ProxyPool, MAX_FAILOVER},
+        timeout::Timeouts,
+    },
+    CrateError,
+};
+use lib_utils::{b64_decode, error::ServerError, headers::GrpcReservedHeaders};
+
+type GrpcClient = HyperClient<Connector, tonic::body::BoxBody>;
+
+static CLIENTS: OnceLock<ClientCaches<GrpcClient>> = OnceLock::new();
+
+/// Init Clients with given proxies url and default timeouts, which are never
+/// evicted.
+///
+/// Return error if CLIENTS is already inited.
+#[tracing::instrument(level = "debug", name = "RpcClient.grpc.init_grpc_client", err)]
+pub fn init_grpc_client(proxies: Vec<&str>) -> Result<()> {
+    let timeouts = Timeouts::default();
+
+    // Default client without proxy
+    let clients = ClientCaches::new();
+    let cache = clients.get_or_try_init(&timeouts, || gen_client(None, &timeouts))?;
+
+    for p in proxies {
+        let rp = Proxy::new(p)?;
+        cache.pin(p, gen_client(Some(rp), &timeouts)?);
+    }
+
+    CLIENTS.set(clients).map_err(|_| {
+        tracing::error!("CLIENTS should be initialized only once");
+        anyhow!("CLIENTS should be initialized only once")
+    })?;
+
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", name = "RpcClient.grpc.gen_client", err)]
+fn gen_client(proxies: Option<Proxy>, timeouts: &Timeouts) -> Result<GrpcClient> {
+    let proxies = proxies.map_or(vec![], |p| vec![p]);
+
+    let mut connector = Connector::new(proxies, None);
+    connector.set_connect_timeout(Some(timeouts.connect));
+
+    let client = HyperClient::builder(TokioExecutor::new())
+        .timer(TokioTimer::new())
+        .pool_timer(TokioTimer::new())
+        .pool_idle_timeout(timeouts.idle)
+        .http2_keep_alive_interval(Some(Duration::from_secs(18)))
+        .http2_keep_alive_while_idle(true)
+        .http2_keep_alive_timeout(Duration::from_secs(16))
+        .http2_initial_connection_window_size(Some((1 << 28) - 1))
+        .http2_initial_stream_window_size(Some((1 << 28) - 1))
+        .build(connector);
+
+    Ok(client)
+}
+
+/// Get GrpcClient from CLIENTS cache or new one with given proxy and default
+/// timeouts
+#[inline]
+pub fn get_client(proxy: Option<&str>) -> Result<GrpcClient> {
+    get_client_with(proxy, &Timeouts::default())
+}
+
+/// Get GrpcClient from CLIENTS cache or new one with given proxy and timeouts
+#[tracing::instrument(
+    level = "debug",
+    name = "RpcClient.grpc.get_client",
+    fields(clients),
+    err
+)]
+pub fn get_client_with(proxy: Option<&str>, timeouts: &Timeouts) -> Result<GrpcClient> {
+    let clients = CLIENTS.get_or_init(|| {
+        tracing::warn!("CLIENTS should be initialized before get_client!!!");
+        ClientCaches::new()
+    });
+
+    let client = clients
+        .get_or_try_init(timeouts, || gen_client(None, timeouts))?
+        .get_or_try_insert_with(proxy, |proxy| {
+            tracing::debug!("Unknown given proxy, new GrpcClient generated");
+
+            gen_client(Some(Proxy::new(proxy)?), timeouts)
+        });
+
+    tracing::Span::current().record("clients", clients.len());
+
+    client
+}
+
+/// Count of GrpcClient cached for proxies, for metrics.
+#[inline]
+pub fn client_count() -> usize {
+    CLIENTS.get().map_or(0, ClientCaches::len)
+}
+
+/// Body of a raw gRPC request.
+#[derive(Debug)]
+pub enum RawBody {
+    /// Buffered, can be sent again when failed over
+    Full(Bytes),
+    /// Piped as is, sent once only
+    Stream(tonic::body::BoxBody),
+}
+
+impl From<Bytes> for RawBody {
+    #[inline]
+    fn from(body: Bytes) -> Self {
+        Self::Full(body)
+    }
+}
+
+impl RawBody {
+    /// Pipe given body, errors polling it fail the request.
+    pub fn stream<B>(body: B) -> Self
+    where
+        B: HttpBody<Data = Bytes> + Send + 'static,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        Self::Stream(
+            body.map_err(|e| tonic::Status::from_error(e.into()))
+                .boxed_unsync(),
+        )
+    }
+
+    /// Whether the body can be sent again
+    #[inline]
+    pub fn is_replayable(&self) -> bool {
+        matches!(self, Self::Full(_))
+    }
+
+    /// Clone the body if buffered
+    #[inline]
+    pub fn try_clone(&self) -> Option<Self> {
+        match self {
+            Self::Full(body) => Some(Self::Full(body.clone())),
+            Self::Stream(_) => None,
+        }
+    }
+
+    /// Split into the body to send and the one left to send again, if any
+    fn split(self) -> (tonic::body::BoxBody, Option<Self>) {
+        match self {
+            Self::Full(body) => (
+                http_body_util::Full::new(body.clone())
+                    .map_err(|never: std::convert::Infallible| -> tonic::Status { match never {} })
+                    .boxed_unsync(),
+                Some(Self::Full(body)),
+            ),
+            Self::Stream(body) => (body, None),
+        }
+    }
+}
+
+/// Execute a raw gRPC request without decoding, for passing through.
+///
+/// Returns response head, data frames and trailers as is, gRPC status will
+/// **NOT** be checked.
+///
+/// The deadline is propagated to upstream by `grpc-timeout`, and a request
+/// not completed in time, including reading the response body, fails with
+/// [`ServerError::RpcGatewayTimeout`].
+#[tracing::instrument(
+    level = "debug",
+    name = "RpcClient.grpc.execute_raw",
+    skip(headers, body),
+    err
+)]
+pub async fn execute_raw(
+    proxy: Option<&str>,
+    timeouts: &Timeouts,
+    uri: Uri,
+    mut headers: HttpHeaderMap,
+    body: RawBody,
+) -> Result<(HttpResponseParts, Bytes, Option<HttpHeaderMap>)> {
+    let client = get_client_with(proxy, timeouts)?;
+    let deadline = Deadline::new(timeouts.grpc_deadline(&mut headers));
+
+    let _permits = limit::acquire(None).await?;
+    let (body, _) = body.split();
+    let response = deadline
+        .run(client.request(raw_request(uri, headers, body)?))
+        .await?
+        .map_err(|e| anyhow!(CrateError::from(e)))?;
+
+    read_raw_response(response, deadline).await
+}
+
+/// Execute a raw gRPC request like [`execute_raw`], with proxies picked from
+/// given pool, starting from the `picked` one if given.
+///
+/// Connection failures, including connect timeouts, are reported to the pool
+/// and the request is retried with another proxy picked, at most
+/// [`MAX_FAILOVER`] times, all within the same deadline. A streaming body is
+/// sent once only and not failed over.
+#[tracing::instrument(
+    level = "debug",
+    name = "RpcClient.grpc.execute_raw_with_pool",
+    skip(pool, headers, body),
+    fields(pool = pool.name()),
+    err
+)]
+pub async fn execute_raw_with_pool(
+    pool: &ProxyPool,
+    mut picked: Option<Arc<str>>,
+    timeouts: &Timeouts,
+    uri: Uri,
+    mut headers: HttpHeaderMap,
+    mut body: RawBody,
+) -> Result<(HttpResponseParts, Bytes, Option<HttpHeaderMap>)> {
+    let deadline = Deadline::new(timeouts.grpc_deadline(&mut headers));
+    let mut failover = 0;
+
+    let (response, _permits) = loop {
+        let proxy = match picked.take() {
+            Some(proxy) => proxy,
+            None => pool.pick()?,
+        };
+        let client = get_client_with(Some(&*proxy), timeouts)?;
+
+        let permits = limit::acquire(Some(pool)).await?;
+        let (sending, left) = body.split();
+        let request = raw_request(uri.clone(), headers.clone(), sending)?;
+        match deadline.run(client.request(request)).await? {
+            Ok(response) => {
+                pool.report_success(&proxy);
+                break (response, permits);
+            }
+            Err(e) if e.is_connect() => {
+                pool.report_failure(&proxy);
+
+                match left {
+                    Some(left) if failover < MAX_FAILOVER.min(pool.len() - 1) => body = left,
+                    _ => return Err(anyhow!(CrateError::from(e))),
+                }
+                failover += 1;
+
+                tracing::warn!("Proxy [{}] failed, failover: {}", proxy, e);
+            }
+            Err(e) => return Err(anyhow!(CrateError::from(e))),
+        }
+    };
+
+    read_raw_response(response, deadline).await
+}
+
+/// Deadline of a raw gRPC request, from sending it to reading the whole
+/// response.
+#[derive(Debug, Clone, Copy)]
+struct Deadline {
+    timeout: Duration,
+    expires_at: tokio::time::Instant,
+}
+
+impl Deadline {
+    #[inline]
+    fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            expires_at: tokio::time::Instant::now() + timeout,
+        }
+    }
+
+    /// Run the future, failing with [`ServerError::RpcGatewayTimeout`] once
+    /// the deadline exceeded.
+    async fn run<T>(self, future: impl Future<Output = T>) -> Result<T> {
+        tokio::time::timeout_at(self.expires_at, future)
+            .await
+            .map_err(|_| {
+                tracing::warn!("gRPC request timeout after {:?}", self.timeout);
+                anyhow!(ServerError::RpcGatewayTimeout)
+            })
+    }
+}
+
+#[inline]
+fn raw_request(
+    uri: Uri,
+    headers: HttpHeaderMap,
+    body: tonic::body::BoxBody,
+) -> Result<GrpcRequest> {
+    let mut req = HttpRequest::builder()
+        .method(HttpMethod::POST)
+        .uri(uri)
+        .body(body)?;
+    *req.headers_mut() = headers;
+
+    Ok(req)
+}
+
+/// Read data frames and trailers of the response within the deadline
+async fn read_raw_response<B>(
+    response: HttpResponse<B>,
+    deadline: Deadline,
+) -> Result<(HttpResponseParts, Bytes, Option<HttpHeaderMap>)>
+where
+    B: HttpBody<Data = Bytes>,
+    CrateError: From<B::Error>,
+{
+    let (parts, body) = response.into_parts();
+
+    let collected = deadline
+        .run(body.collect())
+        .await?
+        .map_err(|e| anyhow!(CrateError::from(e)))?;
+    let trailers = collected.trailers().cloned();
+
+    Ok((parts, collected.to_bytes(), trailers))
+}
+
+/// A ClientExt for outgoing gRPC requests, which can be cloned and reused for
+/// concurrent requests.
+///
+/// Headers are given by each request's metadata, e.g. set by
+/// `ManagedHeaderMap::apply_to_grpc_request` or by using `ManagedHeaderMap` as
+/// the interceptor. Reserved ones sanitized by tonic, like `user-agent`,
+/// are restored from [`GrpcReservedHeaders`] in request extensions.
+#[derive(Debug, Clone)]
+pub struct GrpcClientExt<'c> {
+    proxy: Option<&'c str>,
+    proxy_pool: Option<Arc<ProxyPool>>,
+    /// Proxy picked from the pool in advance, used for all requests
+    picked: Option<Arc<str>>,
+    timeouts: Timeouts,
+}
+
+impl<'c> GrpcClientExt<'c> {
+    #[inline]
+    pub fn new(proxy: Option<&'c str>) -> Self {
+        Self {
+            proxy,
+            proxy_pool: None,
+            picked: None,
+            timeouts: Timeouts::default(),
+        }
+    }
+
+    /// Create with proxies picked from given pool for each request.
+    ///
+    /// Connection failures and timeouts are reported to the pool, but not
+    /// retried since the request body may be a stream.
+    #[inline]
+    pub fn with_pool(proxy_pool: Arc<ProxyPool>) -> Self {
+        Self {
+            proxy: None,
+            proxy_pool: Some(proxy_pool),
+            picked: None,
+            timeouts: Timeouts::default(),
+        }
+    }
+
+    /// Create with given proxy picked from the pool, e.g. to keep the proxy
+    /// when retrying.
+    #[inline]
+    pub fn with_pool_picked(proxy_pool: Arc<ProxyPool>, picked: Arc<str>) -> Self {
+        Self {
+            proxy: None,
+            proxy_pool: Some(proxy_pool),
+            picked: Some(picked),
+            timeouts: Timeouts::default(),
+        }
+    }
+
+    /// Set timeouts, the deadline is propagated to upstream by `grpc-timeout`.
+    #[inline]
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+}
+
+type GrpcRequest = HttpRequest<tonic::body::BoxBody>;
+
+impl tower::Service<GrpcRequest> for GrpcClientExt<'_> {
+    type Response = HttpResponse<Incoming>;
+    type Error = tonic::Status;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Clients are got for each request, and hyper ones are always ready
+        Poll::Ready(Ok(()))
+    }
+
+    #[tracing::instrument(level = "debug", name = "RpcClient.grpc.GrpcClientExt call", skip_all)]
+    fn call(&mut self, mut req: GrpcRequest) -> Self::Future {
+        if let Some(GrpcReservedHeaders(reserved)) =
+            req.extensions_mut().remove::<GrpcReservedHeaders>()
+        {
+            req.headers_mut().extend(reserved);
+        }
+
+        let timeouts = self.timeouts;
+        let deadline = timeouts.grpc_deadline(req.headers_mut());
+
+        let (client, picked) = match &self.proxy_pool {
+            Some(pool) => match self.picked.clone().map_or_else(|| pool.pick(), Ok) {
+                Ok(proxy) => (
+                    get_client_with(Some(&*proxy), &timeouts),
+                    Some((pool.clone(), proxy)),
+                ),
+                Err(e) => (Err(e), None),
+            },
+            None => (get_client_with(self.proxy, &timeouts), None),
+        };
+
+        let execute_rpc = async move {
+            let client = client?;
+
+            let pool = picked.as_ref().map(|(pool, _)| &**pool);
+            let _permits = limit::acquire(pool)
+                .await
+                .map_err(|e| anyhow!(tonic::Status::resource_exhausted(format!("{:#}", e))))?;
+
+            let Ok(response) = tokio::time::timeout(deadline, client.request(req)).await else {
+                tracing::warn!("gRPC request timeout after {:?}", deadline);
+                return Err(anyhow!(tonic::Status::deadline_exceeded(format!(
+                    "timeout after {:?}",
+                    deadline
+                ))));
+            };
+
+            if let Some((pool, proxy)) = picked {
+                match &response {
+                    Err(e) if e.is_connect() => pool.report_failure(&proxy),
+                    _ => pool.report_success(&proxy),
+                }
+            }
+
+            // Unavailable ones are retryable
+            let mut response = response.map_err(|e| {
+                if e.is_connect() {
+                    anyhow!(tonic::Status::unavailable(e.to_string()))
+                } else {
+                    anyhow!(CrateError::from(e))
+                }
+            })?;
+
+            let headers = response.headers_mut();
+
+            // Decode `grpc-status-details-bin` here since tonic panics on invalid one. It
+            // carries Bilibili's `bilibili.rpc.Status` with the business code.
+            let details = headers.remove("grpc-status-details-bin").and_then(|value| {
+                let value = value.to_str().ok()?.trim_end_matches('=');
+                b64_decode!(value, base64::engine::general_purpose::STANDARD_NO_PAD).ok()
+            });
+
+            let status =
+                tonic::Status::from_header_map(headers).ok_or(CrateError::NotGrpcResponse)?;
+            let status = match details {
+                Some(details) => {
+                    tonic::Status::with_details(status.code(), status.message(), details.into())
+                }
+                None => status,
+            };
+
+            // Remove gRPC status headers, or tonic will complain about "protocol error:
+            // received message with compressed-flag but no grpc-encoding was specified"
+            headers.remove("grpc-status");
+            headers.remove("grpc-message");
+
+            if status.code() != tonic::Code::Ok {
+                return Err(anyhow!(status));
+            }
+
+            Ok(response)
+        };
+
+        Box::pin(async move {
+            execute_rpc.await.map_err(|e| match e.downcast() {
+                Ok(e) => e,
+                Err(e) => match e.downcast::<anyhow::Error>() {
+                    Ok(e) => tonic::Status::internal(e.to_string()),
+                    Err(e) => tonic::Status::internal(e.to_string()),
+                },
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use http_body_util::BodyExt as _;
+    use tonic::IntoRequest;
+
+    use std::{pin::Pin, task::Poll, time::Duration};
+
+    use super::{read_raw_response, Deadline, GrpcClientExt, HttpBody, HttpResponse, RawBody};
+    use lib_bilibili::bapis::{
+        app::playerunite::v1::{player_client::PlayerClient, PlayViewUniteReq},
+        metadata::device::Device,
+        playershared::VideoVod,
+    };
+    use lib_utils::{
+        av2bv,
+        headers::{BiliHeaderT, ManagedHeaderMap},
+        now,
+    };
+
+    fn request_for_test() -> tonic::Request<PlayViewUniteReq> {
+        let mut request = PlayViewUniteReq {
+            vod: Some(VideoVod {
+                aid: 80433022,
+                cid: 137649199,
+                qn: 126,
+                fnval: 16 ^ 64 ^ 128 ^ 256 ^ 512 ^ 1024 ^ 2048,
+                force_host: 2,
+                fourk: true,
+                prefer_codec_type: 2,
+                download: 2,
+                ..Default::default()
+            }),
+            bvid: av2bv!(80433022),
+            ..Default::default()
+        }
+        .into_request();
+
+        headers().apply_to_grpc_request(&mut request);
+
+        request
+    }
+
+    fn headers() -> ManagedHeaderMap {
+        let mut headers = ManagedHeaderMap::new(true, true);
+
+        headers
+            .set_user_agent(None)
+            .set_access_key("da656a29342088bbfd134af49d28ef21")
+            .set_appkey_name("android64")
+            // .set_buvid("buvid")
+            .set_device_bin(Device::default());
+
+        // tracing::debug!("{:?}", &headers);
+
+        headers
+    }
+
+    #[tracing::instrument]
+    async fn test_custom_client(proxy: Option<&str>) {
+        let uri = http::Uri::from_static("https://app.bilibili.com");
+        let mut client = PlayerClient::with_origin(GrpcClientExt::new(proxy), uri)
+            .accept_compressed(tonic::codec::CompressionEncoding::Gzip)
+            .send_compressed(tonic::codec::CompressionEncoding::Gzip);
+
+        let now = now!().as_millis();
+
+        // Reused for requests
+        for _ in 0..2 {
+            let _resp = client
+                .play_view_unite(request_for_test())
+                .await
+                .map_err(|e| {
+                    tracing::error!("error: {:?}", e);
+                    e
+                })
+                .unwrap();
+        }
+
+        println!("time: {}", now!().as_millis() - now);
+        // println!("{:?}", resp);
+    }
+
+    /// Body never yielding a frame, like a stalled upstream
+    struct PendingBody;
+
+    impl HttpBody for PendingBody {
+        type Data = bytes::Bytes;
+        type Error = hyper::Error;
+
+        fn poll_frame(
+            self: Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> Poll<Option<Result<hyper::body::Frame<Self::Data>, Self::Error>>> {
+            Poll::Pending
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_raw_response_deadline() {
+        let deadline = Deadline::new(Duration::from_millis(10));
+
+        let e = read_raw_response(HttpResponse::new(PendingBody), deadline)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<lib_utils::error::ServerError>(),
+            Some(lib_utils::error::ServerError::RpcGatewayTimeout)
+        ));
+
+        let body = http_body_util::Full::new(bytes::Bytes::from_static(b"\0\0\0\0\0"))
+            .map_err(|never: std::convert::Infallible| -> hyper::Error { match never {} });
+        let (parts, data, trailers) = read_raw_response(
+            HttpResponse::new(body),
+            Deadline::new(Duration::from_secs(1)),
+        )
+        .await
+        .unwrap();
+        assert!(parts.status.is_success());
+        assert_eq!(&data[..], b"\0\0\0\0\0");
+        assert!(trailers.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_raw_body() {
+        let body = RawBody::from(bytes::Bytes::from_static(b"\0\0\0\0\0"));
+        assert!(body.is_replayable());
+        assert!(body.try_clone().is_some());
+
+        let (sending, left) = body.split();
+        assert_eq!(
+            &sending.collect().await.unwrap().to_bytes()[..],
+            b"\0\0\0\0\0"
+        );
+        assert!(left.is_some_and(|left| left.is_replayable()));
+
+        let body = RawBody::stream(http_body_util::Full::new(bytes::Bytes::from_static(b"\0")));
+        assert!(!body.is_replayable());
+        assert!(body.try_clone().is_none());
+        assert!(body.split().1.is_none());
+    }
+
+    #[tokio::test]
+    async fn test() {
+        use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt};
+        tracing_subscriber::registry().with(fmt::layer()).init();
+
+        println!("============ Custom Client ============");
+        test_custom_client(Some("http://127.0.0.1:20033")).await;
+        tokio::time::sleep(Duration::from_secs(30)).await;
+
+        let h1 = tokio::spawn(async {
+            test_custom_client(Some("http://127.0.0.1:20033")).await;
+        });
+
+        let h2 = tokio::spawn(async {
+            test_custom_client(Some("http://127.0.0.1:20033")).await;
+        });
+
+        let h3 = tokio::spawn(async {
+            test_custom_client(Some("http://127.0.0.1:20033")).await;
+        });
+
+        let h4 = tokio::spawn(async {
+            test_custom_client(Some("http://127.0.0.1:20033")).await;
+        });
+
+        let h5 = tokio::spawn(async {
+            test_custom_client(Some("http://127.0.0.1:20033")).await;
+        });
+
+        let h6 = tokio::spawn(async {
+            test_custom_client(Some("http://127.0.0.1:20033")).await;
+        });
+
+        // test_custom_client(Some("http://127.0.0.1:20033")).await;
+        // test_custom_client(Some("http://127.0.0.1:20033")).await;
+        // test_custom_client(Some("http://127.0.0.1:20033")).await;
+        // test_custom_client(Some("http://127.0.0.1:20033")).await;
+        // test_custom_client(Some("http://127.0.0.1:20033")).await;
+        // test_custom_client(Some("http://127.0.0.1:20033")).await;
+        // test_custom_client(Some("http://127.0.0.1:20033")).await;
+        // test_custom_client(Some("http://127.0.0.1:20033")).await;
+        // test_custom_client(Some("http://127.0.0.1:20033")).await;
+
+        let _ = tokio::join!(h1, h2, h3, h4, h5, h6);
+    }
+}
@@ -1,5 +1,5 @@
 use anyhow::{anyhow, Result};
-use http_02::{uri::Authority, HeaderValue};
+use http::{uri::Authority, HeaderValue};
 use percent_encoding::percent_decode;
 use std::{borrow::Cow, net::SocketAddr};
 use url::Url;
@@ -10,8 +10,10 @@ use lib_utils::{b64_encode, str_concat};
 #[derive(Clone, Debug)]
 pub struct Proxy {
     scheme: ProxyScheme,
-    // TODO Use deadpool to maintain proxies?
-    /// Whether the proxy is available last time we used or check
+    /// Whether the proxy is available last time we used or check.
+    ///
+    /// See [ProxyPool](crate::client::pool::ProxyPool) for health management
+    /// of multiple proxies.
     available: bool,
 }
 
@@ -1,21 +1,18 @@
 use std::sync::Arc;
 
 /// Generate Rustls config
-/// 
+///
 /// Default alpn protocols are  only `h2`
 #[cfg(feature = "__rustls")]
 pub(crate) fn rustls_config(danger_ignore_invalid_certs: bool) -> rustls::ClientConfig {
-    let builder: rustls::ConfigBuilder<rustls::ClientConfig, rustls::WantsVerifier> =
-        rustls::ClientConfig::builder().with_safe_defaults();
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+    let builder = rustls::ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .expect("ring provider supports default protocol versions");
 
     let mut roots = rustls::RootCertStore::empty();
-    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
-        tokio_rustls::rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
-            ta.subject,
-            ta.spki,
-            ta.name_constraints,
-        )
-    }));
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
 
     let mut config = builder.with_root_certificates(roots).with_no_client_auth();
 
@@ -24,86 +21,52 @@ pub(crate) fn rustls_config(danger_ignore_invalid_certs: bool) -> rustls::Client
     if danger_ignore_invalid_certs {
         config
             .dangerous()
-            .set_certificate_verifier(Arc::new(Verifier));
+            .set_certificate_verifier(Arc::new(NoVerifier(provider)));
     }
 
     config
 }
 
 #[cfg(feature = "__rustls")]
-pub struct Verifier;
+use rustls::client::danger::ServerCertVerifier;
 
+/// Verifier accepting any certificate, only for test
 #[cfg(feature = "__rustls")]
-use rustls::client::ServerCertVerifier;
+#[derive(Debug)]
+pub(crate) struct NoVerifier(Arc<rustls::crypto::CryptoProvider>);
 
 #[cfg(feature = "__rustls")]
-impl ServerCertVerifier for Verifier {
+impl ServerCertVerifier for NoVerifier {
     fn verify_server_cert(
         &self,
-        _end_entity: &rustls::Certificate,
-        _intermediates: &[rustls::Certificate],
-        _server_name: &rustls::ServerName,
-        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _end_entity: &rustls::pki_types::CertificateDer<'_>,
+        _intermediates: &[rustls::pki_types::CertificateDer<'_>],
+        _server_name: &rustls::pki_types::ServerName<'_>,
         _ocsp_response: &[u8],
-        _now: std::time::SystemTime,
-    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
-        Ok(rustls::client::ServerCertVerified::assertion())
+        _now: rustls::pki_types::UnixTime,
+    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::danger::ServerCertVerified::assertion())
     }
 
     fn verify_tls12_signature(
         &self,
         _message: &[u8],
-        _cert: &rustls::Certificate,
+        _cert: &rustls::pki_types::CertificateDer<'_>,
         _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::HandshakeSignatureValid::assertion())
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
     }
 
     fn verify_tls13_signature(
         &self,
         _message: &[u8],
-        _cert: &rustls::Certificate,
+        _cert: &rustls::pki_types::CertificateDer<'_>,
         _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::HandshakeSignatureValid::assertion())
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
     }
-}
-
-// use rustls::client::danger::ServerCertVerifier;
-
-// #[derive(Debug)]
-// pub(crate) struct NoVerifier;
-// impl ServerCertVerifier for NoVerifier {
-//     fn verify_server_cert(
-//         &self,
-//         _end_entity: &rustls::pki_types::CertificateDer<'_>,
-//         _intermediates: &[rustls::pki_types::CertificateDer<'_>],
-//         _server_name: &rustls::pki_types::ServerName<'_>,
-//         _ocsp_response: &[u8],
-//         _now: rustls::pki_types::UnixTime,
-//     ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
-//         Ok(rustls::client::danger::ServerCertVerified::assertion())
-//     }
 
-//     fn verify_tls12_signature(
-//         &self,
-//         _message: &[u8],
-//         _cert: &rustls::pki_types::CertificateDer<'_>,
-//         _dss: &rustls::DigitallySignedStruct,
-//     ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-//         Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
-//     }
-
-//     fn verify_tls13_signature(
-//         &self,
-//         _message: &[u8],
-//         _cert: &rustls::pki_types::CertificateDer<'_>,
-//         _dss: &rustls::DigitallySignedStruct,
-//     ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-//         Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
-//     }
-
-//     fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
-//         Vec::new()
-//     }
-// }
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
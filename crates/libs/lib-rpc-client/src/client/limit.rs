@@ -0,0 +1,182 @@
+use anyhow::{anyhow, Result};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, OnceLock,
+    },
+    time::Duration,
+};
+
+use super::pool::ProxyPool;
+use lib_utils::error::ServerError;
+
+/// Limit of all upstream requests, see [init_global_limit]
+static GLOBAL_LIMIT: OnceLock<ConcurrencyLimit> = OnceLock::new();
+
+/// Options of [ConcurrencyLimit]
+#[derive(Debug, Clone)]
+pub struct ConcurrencyLimitOptions {
+    /// Max concurrent upstream requests
+    pub max_concurrent: usize,
+    /// Max requests waiting for a permit, the others fail at once
+    pub max_queued: usize,
+    /// How long a request waits in queue at most
+    pub queue_timeout: Duration,
+}
+
+impl Default for ConcurrencyLimitOptions {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 64,
+            max_queued: 1024,
+            queue_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Limit of concurrent upstream requests, the exceeding ones queued.
+///
+/// Requests fail with [ServerError::ReqTooFrequent] when the queue is full or
+/// they have waited too long.
+#[derive(Debug)]
+pub struct ConcurrencyLimit {
+    semaphore: Arc<Semaphore>,
+    queued: AtomicUsize,
+    options: ConcurrencyLimitOptions,
+}
+
+/// Decrease the count of queued requests when dropped, even if cancelled
+struct QueuedGuard<'l>(&'l AtomicUsize);
+
+impl Drop for QueuedGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl ConcurrencyLimit {
+    pub fn new(options: ConcurrencyLimitOptions) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(options.max_concurrent.max(1))),
+            queued: AtomicUsize::new(0),
+            options,
+        }
+    }
+
+    /// Count of permits not taken.
+    #[inline]
+    pub fn available(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+
+    /// Count of requests waiting for a permit.
+    #[inline]
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Acquire)
+    }
+
+    /// Wait for a permit, released when dropped.
+    #[tracing::instrument(level = "debug", name = "ConcurrencyLimit.acquire", skip_all, err)]
+    pub async fn acquire(&self) -> Result<OwnedSemaphorePermit> {
+        if let Ok(permit) = self.semaphore.clone().try_acquire_owned() {
+            return Ok(permit);
+        }
+
+        if self.queued.fetch_add(1, Ordering::AcqRel) >= self.options.max_queued {
+            self.queued.fetch_sub(1, Ordering::AcqRel);
+            tracing::warn!("Too many upstream requests queued");
+            return Err(anyhow!(ServerError::ReqTooFrequent));
+        }
+        let _guard = QueuedGuard(&self.queued);
+
+        match tokio::time::timeout(
+            self.options.queue_timeout,
+            self.semaphore.clone().acquire_owned(),
+        )
+        .await
+        {
+            Ok(permit) => Ok(permit?),
+            Err(_) => {
+                tracing::warn!(
+                    "Upstream request queued for over {:?}",
+                    self.options.queue_timeout
+                );
+                Err(anyhow!(ServerError::ReqTooFrequent))
+            }
+        }
+    }
+}
+
+/// Init the limit of all upstream requests, unlimited if not inited.
+///
+/// Return error if already inited.
+pub fn init_global_limit(options: ConcurrencyLimitOptions) -> Result<()> {
+    GLOBAL_LIMIT
+        .set(ConcurrencyLimit::new(options))
+        .map_err(|_| anyhow!("GLOBAL_LIMIT should be initialized only once"))
+}
+
+/// Permits of an upstream request, released when dropped
+#[derive(Debug)]
+#[must_use]
+pub struct Permits {
+    _global: Option<OwnedSemaphorePermit>,
+    _pool: Option<OwnedSemaphorePermit>,
+}
+
+/// Wait for permits of the pool's limit, if any, and the global one.
+///
+/// The pool's one is taken first, so that requests queued for a busy pool
+/// don't hold global permits other pools could have used.
+pub async fn acquire(pool: Option<&ProxyPool>) -> Result<Permits> {
+    let pool = match pool.and_then(ProxyPool::limit) {
+        Some(limit) => Some(limit.acquire().await?),
+        None => None,
+    };
+    let global = match GLOBAL_LIMIT.get() {
+        Some(limit) => Some(limit.acquire().await?),
+        None => None,
+    };
+
+    Ok(Permits {
+        _global: global,
+        _pool: pool,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_concurrency_limit() {
+        let limit = ConcurrencyLimit::new(ConcurrencyLimitOptions {
+            max_concurrent: 1,
+            max_queued: 1,
+            queue_timeout: Duration::from_millis(50),
+        });
+
+        let permit = limit.acquire().await.unwrap();
+        assert_eq!(limit.available(), 0);
+
+        // Queued and timed out
+        let e = limit.acquire().await.unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<ServerError>(),
+            Some(ServerError::ReqTooFrequent)
+        ));
+        assert_eq!(limit.queued(), 0);
+
+        // Queued and got the permit released
+        let (result, _) = tokio::join!(limit.acquire(), async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            // Queue full
+            assert!(limit.acquire().await.is_err());
+            drop(permit);
+        });
+        assert!(result.is_ok());
+        assert_eq!(limit.queued(), 0);
+    }
+}
@@ -0,0 +1,370 @@
+use anyhow::{anyhow, Result};
+use dashmap::DashMap;
+
+use std::{
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex, OnceLock, PoisonError, Weak,
+    },
+    time::{Duration, Instant},
+};
+
+use super::{
+    grpc::proxy::Proxy,
+    limit::{ConcurrencyLimit, ConcurrencyLimitOptions},
+    rest::RestRequest,
+};
+use crate::error::ProxyError;
+
+/// Max times to retry with another proxy picked from the pool, for requests
+/// with a replayable body
+pub const MAX_FAILOVER: usize = 2;
+
+/// Named proxy pools, e.g. one for each area
+static POOLS: OnceLock<DashMap<Arc<str>, Arc<ProxyPool>>> = OnceLock::new();
+
+/// Init pools with given ones.
+///
+/// Return error if POOLS is already inited.
+#[tracing::instrument(
+    level = "debug",
+    name = "RpcClient.pool.init_proxy_pools",
+    skip_all,
+    err
+)]
+pub fn init_proxy_pools(pools: Vec<ProxyPool>) -> Result<()> {
+    let map = DashMap::with_capacity(pools.len());
+
+    for pool in pools {
+        map.insert(pool.name.clone(), Arc::new(pool));
+    }
+
+    POOLS.set(map).map_err(|_| {
+        tracing::error!("POOLS should be initialized only once");
+        anyhow!("POOLS should be initialized only once")
+    })
+}
+
+/// Get the pool with given name.
+pub fn get_pool(name: &str) -> Option<Arc<ProxyPool>> {
+    POOLS.get()?.get(name).map(|p| p.clone())
+}
+
+/// Options of [ProxyPool]
+#[derive(Debug, Clone)]
+pub struct ProxyPoolOptions {
+    /// Consecutive failures before a proxy is ejected
+    pub max_failures: u32,
+    /// How long an ejected proxy waits before being re-admitted
+    pub eject_duration: Duration,
+    /// Interval of active health checks, `None` to disable
+    pub health_check_interval: Option<Duration>,
+    /// Url requested through each proxy when checking
+    pub health_check_url: String,
+    /// Timeout of each health check request
+    pub health_check_timeout: Duration,
+    /// Limit of concurrent requests through the pool, `None` for unlimited
+    pub concurrency: Option<ConcurrencyLimitOptions>,
+}
+
+impl Default for ProxyPoolOptions {
+    fn default() -> Self {
+        Self {
+            max_failures: 3,
+            eject_duration: Duration::from_secs(30),
+            health_check_interval: Some(Duration::from_secs(60)),
+            health_check_url: "https://api.bilibili.com/x/web-interface/zone".to_owned(),
+            health_check_timeout: Duration::from_secs(5),
+            concurrency: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PoolEntry {
+    url: Arc<str>,
+    weight: u32,
+    /// Consecutive failures
+    failures: AtomicU32,
+    /// Millis since the pool created when the proxy will be re-admitted, 0 if
+    /// not ejected
+    ejected_until: AtomicU64,
+}
+
+/// A pool of weighted proxies.
+///
+/// Proxies are picked by smooth weighted round-robin. A proxy failing
+/// `max_failures` times in a row is ejected for `eject_duration`, and is
+/// re-admitted after that or once an active health check succeeds. A
+/// re-admitted proxy is ejected again on its first failure until it succeeds.
+#[derive(Debug)]
+pub struct ProxyPool {
+    name: Arc<str>,
+    options: ProxyPoolOptions,
+    entries: Box<[PoolEntry]>,
+    /// Current weights of smooth weighted round-robin
+    current_weights: Mutex<Vec<i64>>,
+    created_at: Instant,
+    limit: Option<ConcurrencyLimit>,
+}
+
+impl ProxyPool {
+    /// Create a pool with given proxies url and weights.
+    ///
+    /// Return error if any proxy url is invalid.
+    pub fn new<S: AsRef<str>>(
+        name: &str,
+        proxies: impl IntoIterator<Item = (S, u32)>,
+        options: ProxyPoolOptions,
+    ) -> Result<Self> {
+        let entries = proxies
+            .into_iter()
+            .map(|(url, weight)| {
+                let url = url.as_ref();
+                Proxy::new(url)?;
+                Ok(PoolEntry {
+                    url: Arc::from(url),
+                    weight: weight.max(1),
+                    failures: AtomicU32::new(0),
+                    ejected_until: AtomicU64::new(0),
+                })
+            })
+            .collect::<Result<Box<[_]>>>()?;
+
+        Ok(Self {
+            name: Arc::from(name),
+            limit: options.concurrency.clone().map(ConcurrencyLimit::new),
+            options,
+            current_weights: Mutex::new(vec![0; entries.len()]),
+            entries,
+            created_at: Instant::now(),
+        })
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Limit of concurrent requests through the pool, if any.
+    #[inline]
+    pub fn limit(&self) -> Option<&ConcurrencyLimit> {
+        self.limit.as_ref()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    #[inline]
+    fn now_millis(&self) -> u64 {
+        // Never 0, which means not ejected
+        self.created_at.elapsed().as_millis() as u64 + 1
+    }
+
+    #[inline]
+    fn entry(&self, url: &str) -> Option<&PoolEntry> {
+        self.entries.iter().find(|e| &*e.url == url)
+    }
+
+    #[inline]
+    fn is_admitted(&self, entry: &PoolEntry, now: u64) -> bool {
+        entry.ejected_until.load(Ordering::Acquire) <= now
+    }
+
+    /// Whether the proxy is in this pool and not ejected.
+    pub fn is_available(&self, url: &str) -> bool {
+        self.entry(url)
+            .is_some_and(|entry| self.is_admitted(entry, self.now_millis()))
+    }
+
+    /// Count of proxies not ejected.
+    pub fn available_count(&self) -> usize {
+        let now = self.now_millis();
+        self.entries
+            .iter()
+            .filter(|entry| self.is_admitted(entry, now))
+            .count()
+    }
+
+    /// Pick a proxy not ejected by smooth weighted round-robin.
+    #[tracing::instrument(level = "debug", name = "ProxyPool.pick", skip(self), fields(pool = %self.name), err)]
+    pub fn pick(&self) -> Result<Arc<str>> {
+        let now = self.now_millis();
+        let mut current_weights = self
+            .current_weights
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        let mut total = 0i64;
+        let mut picked: Option<usize> = None;
+        for (index, entry) in self.entries.iter().enumerate() {
+            if !self.is_admitted(entry, now) {
+                continue;
+            }
+
+            current_weights[index] += entry.weight as i64;
+            total += entry.weight as i64;
+
+            match picked {
+                Some(p) if current_weights[p] >= current_weights[index] => {}
+                _ => picked = Some(index),
+            }
+        }
+
+        let picked = picked.ok_or_else(|| ProxyError::NoAvailableProxy(self.name.to_string()))?;
+        current_weights[picked] -= total;
+
+        Ok(self.entries[picked].url.clone())
+    }
+
+    /// Mark the proxy succeeded, re-admitting it if ejected.
+    pub fn report_success(&self, url: &str) {
+        if let Some(entry) = self.entry(url) {
+            entry.failures.store(0, Ordering::Release);
+            entry.ejected_until.store(0, Ordering::Release);
+        }
+    }
+
+    /// Mark the proxy failed, ejecting it once failed `max_failures` times in
+    /// a row.
+    pub fn report_failure(&self, url: &str) {
+        let Some(entry) = self.entry(url) else {
+            return;
+        };
+
+        let failures = entry.failures.fetch_add(1, Ordering::AcqRel) + 1;
+        if failures >= self.options.max_failures {
+            let until = self.now_millis() + self.options.eject_duration.as_millis() as u64;
+            entry.ejected_until.store(until, Ordering::Release);
+
+            tracing::warn!(
+                "Proxy [{}] of pool [{}] ejected after {} failures",
+                url,
+                self.name,
+                failures
+            );
+        }
+    }
+
+    /// Check all proxies once by requesting `health_check_url` through each.
+    #[tracing::instrument(level = "debug", name = "ProxyPool.health_check", skip(self), fields(pool = %self.name))]
+    pub async fn health_check(&self) {
+        for entry in self.entries.iter() {
+            let request = RestRequest::builder()
+                .proxy(Some(&*entry.url))
+                .url(&self.options.health_check_url)
+                .build();
+
+            let result = match request {
+                Ok(request) => {
+                    tokio::time::timeout(self.options.health_check_timeout, request.get()).await
+                }
+                Err(e) => {
+                    tracing::error!("Invalid health check url: {}", e);
+                    return;
+                }
+            };
+
+            match result {
+                Ok(Ok(_)) => self.report_success(&entry.url),
+                Ok(Err(e)) => {
+                    tracing::debug!("Proxy [{}] health check failed: {}", entry.url, e);
+                    self.report_failure(&entry.url)
+                }
+                Err(_) => {
+                    tracing::debug!("Proxy [{}] health check timeout", entry.url);
+                    self.report_failure(&entry.url)
+                }
+            }
+        }
+    }
+
+    /// Spawn a task checking the pool periodically, which stops once the pool
+    /// dropped.
+    ///
+    /// Return `None` if health checks are disabled.
+    pub fn spawn_health_check(self: &Arc<Self>) -> Option<tokio::task::JoinHandle<()>> {
+        let interval = self.options.health_check_interval?;
+        let pool: Weak<Self> = Arc::downgrade(self);
+
+        Some(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+            loop {
+                ticker.tick().await;
+
+                let Some(pool) = pool.upgrade() else {
+                    break;
+                };
+                pool.health_check().await;
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pool() -> ProxyPool {
+        ProxyPool::new(
+            "test",
+            [
+                ("http://127.0.0.1:1080", 5),
+                ("http://127.0.0.1:1081", 1),
+                ("http://127.0.0.1:1082", 1),
+            ],
+            ProxyPoolOptions {
+                max_failures: 2,
+                eject_duration: Duration::from_secs(3600),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_weighted_round_robin() {
+        let pool = pool();
+
+        let picked: String = (0..7)
+            .map(|_| match &*pool.pick().unwrap() {
+                "http://127.0.0.1:1080" => 'a',
+                "http://127.0.0.1:1081" => 'b',
+                _ => 'c',
+            })
+            .collect();
+
+        assert_eq!(picked, "aabacaa");
+    }
+
+    #[test]
+    fn test_ejection() {
+        let pool = pool();
+        let heavy = "http://127.0.0.1:1080";
+
+        pool.report_failure(heavy);
+        assert!(pool.is_available(heavy));
+        pool.report_failure(heavy);
+        assert!(!pool.is_available(heavy));
+        assert_eq!(pool.available_count(), 2);
+        assert!((0..10).all(|_| &*pool.pick().unwrap() != heavy));
+
+        pool.report_success(heavy);
+        assert!(pool.is_available(heavy));
+
+        for entry in pool.entries.iter() {
+            pool.report_failure(&entry.url);
+            pool.report_failure(&entry.url);
+        }
+        assert!(pool.pick().is_err());
+    }
+}
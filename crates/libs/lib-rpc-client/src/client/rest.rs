@@ -1,13 +1,22 @@
 use anyhow::{anyhow, Result};
-use dashmap::DashMap;
-use http_02::{HeaderMap as HttpHeaderMap, Method as HttpMethod};
+use http::{HeaderMap as HttpHeaderMap, Method as HttpMethod};
 use reqwest::{Client, Proxy};
 use url::Url;
 
-use std::{sync::OnceLock, time::Duration};
+use std::{
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
 
-use lib_utils::headers::ManagedHeaderMap;
+use lib_utils::{error::ServerError, headers::ManagedHeaderMap};
 
+use super::{
+    cache::ClientCaches,
+    limit,
+    pool::{ProxyPool, MAX_FAILOVER},
+    retry::RetryPolicy,
+    timeout::Timeouts,
+};
 use crate::{
     utils::{RawResponseExt, ResponseExt},
     CrateError,
@@ -17,42 +26,45 @@ use crate::{
 pub use reqwest::Body as ReqBody;
 
 /// Clients with or without proxy
-static CLIENTS: OnceLock<DashMap<&'static str, reqwest::Client>> = OnceLock::new();
+static CLIENTS: OnceLock<ClientCaches<reqwest::Client>> = OnceLock::new();
 
-/// Init Clients with given proxies url.
+/// Init Clients with given proxies url and default timeouts, which are never
+/// evicted.
 ///
 /// Return error if CLIENTS is already inited.
 #[tracing::instrument(level = "debug", name = "RpcClient.rest.init_reqwest_clients", err)]
-pub fn init_reqwest_clients(proxies: Vec<&'static str>) -> Result<()> {
-    let map = dashmap::DashMap::with_capacity(16);
+pub fn init_reqwest_clients(proxies: Vec<&str>) -> Result<()> {
+    let timeouts = Timeouts::default();
 
+    let caches = ClientCaches::new();
     // Default client without proxy
-    map.insert("default", gen_client(None)?);
+    let clients = caches.get_or_try_init(&timeouts, || gen_client(None, &timeouts))?;
 
     for p in proxies {
         let rp = Proxy::all(p).map_err(|e| anyhow!(CrateError::from(e)))?;
-        map.insert(p, gen_client(Some(rp))?);
+        clients.pin(p, gen_client(Some(rp), &timeouts)?);
     }
 
-    CLIENTS.set(map).map_err(|_| {
+    CLIENTS.set(caches).map_err(|_| {
         tracing::error!("CLIENTS should be initialized only once");
         anyhow!("CLIENTS should be initialized only once")
     })
 }
 
-/// Generate reqwest::Client with given proxy
+/// Generate reqwest::Client with given proxy and timeouts
+///
+/// Request timeout is set for each request instead.
 #[tracing::instrument(level = "debug", name = "RpcClient.rest.gen_client", err)]
-fn gen_client(proxy: Option<reqwest::Proxy>) -> Result<reqwest::Client> {
+fn gen_client(proxy: Option<reqwest::Proxy>, timeouts: &Timeouts) -> Result<reqwest::Client> {
     let mut builder = Client::builder()
         .use_rustls_tls()
         .gzip(true)
         .brotli(true)
         .deflate(true)
-        .connect_timeout(Duration::from_secs(10))
-        .timeout(Duration::from_secs(15))
+        .connect_timeout(timeouts.connect)
         .tcp_keepalive(Some(Duration::from_secs(3600)))
         .tcp_nodelay(true)
-        .pool_idle_timeout(Duration::from_secs(3600))
+        .pool_idle_timeout(timeouts.idle)
         // ! Should set UA separately
         // .user_agent(user_agent)
         .http2_keep_alive_interval(Some(Duration::from_secs(18)))
@@ -71,40 +83,36 @@ fn gen_client(proxy: Option<reqwest::Proxy>) -> Result<reqwest::Client> {
 }
 
 /// Get reqwest::Client from CLIENTS cache or new one with given proxy
-#[tracing::instrument(level = "debug", name = "RpcClient.rest.get_client", err)]
-fn get_client(proxy: Option<&str>) -> Result<reqwest::Client> {
-    let clients = CLIENTS.get_or_init(|| {
+#[tracing::instrument(
+    level = "debug",
+    name = "RpcClient.rest.get_client",
+    fields(clients),
+    err
+)]
+fn get_client(proxy: Option<&str>, timeouts: &Timeouts) -> Result<reqwest::Client> {
+    let caches = CLIENTS.get_or_init(|| {
         tracing::warn!("CLIENTS should be initialized before get_client!!!");
-        let map = dashmap::DashMap::with_capacity(16);
-        map.insert("default", gen_client(None).unwrap());
-        map
+        ClientCaches::new()
     });
 
-    debug_assert!(clients.get("default").is_some());
-
-    let client = clients
-        .get(proxy.unwrap_or_else(|| {
-            tracing::debug!("proxy is None, use default client");
-            "default"
-        }))
-        .map(|c| c.clone());
+    let clients = caches.get_or_try_init(timeouts, || gen_client(None, timeouts))?;
 
-    if let Some(client) = client {
-        tracing::debug!("Got reqwest::Client from cache");
-        Ok(client)
-    } else {
-        tracing::warn!("Unknown given proxy, Box::leak may cause memory leak");
+    let client = clients.get_or_try_insert_with(proxy, |proxy| {
+        tracing::debug!("Unknown given proxy, new reqwest::Client generated");
 
-        let proxy_str = proxy.unwrap();
+        let rp = Proxy::all(proxy).map_err(|e| anyhow!(CrateError::from(e)))?;
+        gen_client(Some(rp), timeouts)
+    });
 
-        let rp = Proxy::all(proxy_str).map_err(|e| anyhow!(CrateError::from(e)))?;
+    tracing::Span::current().record("clients", caches.len());
 
-        let client = gen_client(Some(rp))?;
-        clients.insert(Box::leak(Box::new(proxy_str.to_string())), client.clone());
+    client
+}
 
-        tracing::debug!("Got new reqwest::Client from given proxy");
-        Ok(client)
-    }
+/// Count of reqwest::Client cached for proxies, for metrics.
+#[inline]
+pub fn client_count() -> usize {
+    CLIENTS.get().map_or(0, ClientCaches::len)
 }
 
 /// [`RestRequest`] with ideal method, url, headers and body.
@@ -113,9 +121,15 @@ fn get_client(proxy: Option<&str>) -> Result<reqwest::Client> {
 #[derive(Debug)]
 pub struct RestRequest<'c> {
     pub proxy: Option<&'c str>,
+    /// Pool to pick proxies from, overriding `proxy` if set
+    pub proxy_pool: Option<Arc<ProxyPool>>,
     pub url: Url,
     pub headers: Option<HttpHeaderMap>,
     pub body: Option<reqwest::Body>,
+    /// Retry policy for `GET` and `HEAD` requests
+    pub retry: Option<RetryPolicy>,
+    /// Timeouts, the default ones if not set
+    pub timeouts: Option<Timeouts>,
 }
 
 impl<'c> RestRequest<'c> {
@@ -140,32 +154,118 @@ impl<'c> RestRequest<'c> {
     }
 
     /// Execute request with given method, url, headers and body.
+    ///
+    /// With a proxy pool, connection failures and timeouts are reported to the
+    /// pool, and the request is sent again through another proxy picked when
+    /// failed to connect, at most [`MAX_FAILOVER`] times.
+    ///
+    /// With a retry policy, `GET` and `HEAD` requests are retried on connection
+    /// errors, timeouts and 5xx responses. The last 5xx response is returned
+    /// as is when the retry budget runs out.
+    ///
+    /// A streaming body, see [`ReqBody::wrap_stream`], is sent once only, with
+    /// neither failover nor retry.
+    ///
+    /// Timeout errors are reported as [`ServerError::RpcGatewayTimeout`].
+    ///
+    /// Each attempt waits for permits of the concurrency limits, see
+    /// [`limit::acquire`].
     #[tracing::instrument(level = "debug", name = "RestRequest.execute", err)]
     pub async fn execute(self, method: HttpMethod) -> Result<RawResponseExt> {
-        let client = get_client(self.proxy)?;
+        let policy = self
+            .retry
+            .filter(|_| method == HttpMethod::GET || method == HttpMethod::HEAD)
+            .unwrap_or(RetryPolicy::NONE);
+        let timeouts = self.timeouts.unwrap_or_default();
 
-        let request = {
+        let mut request = {
             let mut r = reqwest::Request::new(method, self.url);
+            *r.timeout_mut() = Some(timeouts.request);
             *r.headers_mut() = self
                 .headers
                 .unwrap_or_else(|| ManagedHeaderMap::new(false, false).take_inner());
             *r.body_mut() = self.body;
             r
         };
-
-        // SAFE: body is not Stream
-        let response = client.execute(request.try_clone().unwrap()).await?;
-
-        Ok(ResponseExt::new(request, self.proxy, (), response))
+        // Taken out to be sent with the first attempt, the request left can be
+        // cloned then
+        let mut streaming = request
+            .body()
+            .is_some_and(|body| body.as_bytes().is_none())
+            .then(|| request.body_mut().take())
+            .flatten();
+        let replayable = streaming.is_none();
+
+        let (mut failover, mut retried) = (0, 0);
+        // Proxy picked from the pool
+        let mut picked: Option<Arc<str>> = None;
+
+        loop {
+            if let Some(pool) = &self.proxy_pool {
+                if picked.is_none() || policy.switch_proxy {
+                    picked = Some(pool.pick()?);
+                }
+            }
+            let proxy = picked.as_deref().or(self.proxy);
+
+            let client = get_client(proxy, &timeouts)?;
+
+            let result = {
+                let _permits = limit::acquire(self.proxy_pool.as_deref()).await?;
+                // SAFE: streaming body taken out
+                let mut attempt = request.try_clone().unwrap();
+                if let Some(body) = streaming.take() {
+                    *attempt.body_mut() = Some(body);
+                }
+                client.execute(attempt).await
+            };
+
+            if let (Some(pool), Some(proxy)) = (&self.proxy_pool, proxy) {
+                match &result {
+                    Err(e) if e.is_connect() || e.is_timeout() => pool.report_failure(proxy),
+                    _ => pool.report_success(proxy),
+                }
+
+                // Not sent at all, safe to send again
+                if replayable
+                    && matches!(&result, Err(e) if e.is_connect())
+                    && failover < MAX_FAILOVER.min(pool.len() - 1)
+                {
+                    failover += 1;
+                    tracing::warn!("Proxy [{}] failed to connect, failover", proxy);
+                    picked = None;
+                    continue;
+                }
+            }
+
+            let retryable = match &result {
+                Ok(response) => response.status().is_server_error(),
+                Err(e) => e.is_connect() || e.is_timeout(),
+            };
+            if replayable && retryable && retried < policy.max_retries {
+                retried += 1;
+                policy.wait(retried).await;
+                continue;
+            }
+
+            return match result {
+                Ok(response) => Ok(ResponseExt::new(request, proxy, (), response)),
+                Err(e) if e.is_timeout() => Err(anyhow!(e).context(ServerError::RpcGatewayTimeout)),
+                Err(e) => Err(e.into()),
+            };
+        }
     }
 }
 
 #[derive(Debug, Default)]
 pub struct RestRequestBuilder<'r> {
     proxy: Option<&'r str>,
+    proxy_pool: Option<Arc<ProxyPool>>,
     url: Option<&'r str>,
     headers: Option<HttpHeaderMap>,
     body: Option<reqwest::Body>,
+    retry: Option<RetryPolicy>,
+    timeouts: Option<Timeouts>,
 }
 
 impl<'c> RestRequestBuilder<'c> {
@@ -176,6 +276,13 @@ impl<'c> RestRequestBuilder<'c> {
         self
     }
 
+    /// Configure proxy pool for the request, overriding `proxy` if set
+    #[inline]
+    pub fn proxy_pool(mut self, proxy_pool: Option<Arc<ProxyPool>>) -> Self {
+        self.proxy_pool = proxy_pool;
+        self
+    }
+
     /// Configure url for the request
     #[inline]
     pub fn url(mut self, url: &'c str) -> Self {
@@ -201,6 +308,20 @@ impl<'c> RestRequestBuilder<'c> {
         self
     }
 
+    /// Configure retry policy for the request, only for `GET` and `HEAD` ones
+    #[inline]
+    pub fn retry(mut self, retry: Option<RetryPolicy>) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Configure timeouts for the request
+    #[inline]
+    pub fn timeouts(mut self, timeouts: Option<Timeouts>) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
     /// Build RestRequest
     #[inline]
     #[tracing::instrument(level = "debug", name = "RestRequestBuilder.build", err)]
@@ -213,9 +334,12 @@ impl<'c> RestRequestBuilder<'c> {
 
         Ok(RestRequest {
             proxy: self.proxy,
+            proxy_pool: self.proxy_pool,
             url,
             headers: self.headers,
             body: self.body,
+            retry: self.retry,
+            timeouts: self.timeouts,
         })
     }
 
@@ -224,9 +348,12 @@ impl<'c> RestRequestBuilder<'c> {
     pub fn build_with(self, url: Url) -> RestRequest<'c> {
         RestRequest {
             proxy: self.proxy,
+            proxy_pool: self.proxy_pool,
             url,
             headers: self.headers,
             body: self.body,
+            retry: self.retry,
+            timeouts: self.timeouts,
         }
     }
 }
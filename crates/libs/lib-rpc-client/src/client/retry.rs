@@ -0,0 +1,261 @@
+use rand::Rng;
+
+use std::{future::Future, time::Duration};
+
+use crate::CrateError;
+use lib_utils::error::{ServerError, ServerErrorExt};
+
+/// Policy of retrying upstream RPCs with exponential backoff and full jitter.
+///
+/// Only idempotent requests, i.e. `GET` and `HEAD` ones or read-only gRPC
+/// methods, are retried. Defaults are those of `retry` in config.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Max retries of a request, its retry budget
+    pub max_retries: u32,
+    /// Backoff before the first retry, doubled each time
+    pub base_delay: Duration,
+    /// Max backoff
+    pub max_delay: Duration,
+    /// Pick another proxy from the pool between attempts
+    pub switch_proxy: bool,
+}
+
+impl RetryPolicy {
+    /// No retry at all
+    pub const NONE: Self = Self {
+        max_retries: 0,
+        base_delay: Duration::ZERO,
+        max_delay: Duration::ZERO,
+        switch_proxy: false,
+    };
+
+    /// Backoff before the `retry`-th retry, starting from 1.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let ceil = self
+            .base_delay
+            .saturating_mul(1 << retry.saturating_sub(1).min(16))
+            .min(self.max_delay);
+
+        if ceil.is_zero() {
+            return ceil;
+        }
+        rand::thread_rng().gen_range(Duration::ZERO..=ceil)
+    }
+
+    /// Wait before the `retry`-th retry, starting from 1.
+    pub async fn wait(&self, retry: u32) {
+        let backoff = self.backoff(retry);
+        tracing::debug!("Retry [{}/{}] after {:?}", retry, self.max_retries, backoff);
+        tokio::time::sleep(backoff).await;
+    }
+
+    /// Run `attempt` until `retryable` rejects its result or the retry budget
+    /// runs out, waiting between attempts. The last result is returned as is.
+    pub async fn run<T, F, Fut>(
+        &self,
+        mut attempt: F,
+        retryable: impl Fn(&anyhow::Result<T>) -> bool,
+    ) -> anyhow::Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        let mut retried = 0;
+
+        loop {
+            let result = attempt().await;
+            if retried >= self.max_retries || !retryable(&result) {
+                return result;
+            }
+
+            retried += 1;
+            self.wait(retried).await;
+        }
+    }
+}
+
+/// Read-only gRPC methods, which are safe to retry when passed through
+const IDEMPOTENT_GRPC_METHODS: &[&str] = &[
+    "/bilibili.app.dynamic.v2.Dynamic/DynAll",
+    "/bilibili.app.dynamic.v2.Dynamic/DynDetail",
+    "/bilibili.app.dynamic.v2.Dynamic/DynVideo",
+    "/bilibili.app.playerunite.v1.Player/PlayViewUnite",
+    "/bilibili.app.playurl.v1.PlayURL/PlayConf",
+    "/bilibili.app.playurl.v1.PlayURL/PlayView",
+    "/bilibili.app.view.v1.View/View",
+    "/bilibili.app.viewunite.v1.View/View",
+    "/bilibili.community.service.dm.v1.DM/DmSegMobile",
+    "/bilibili.community.service.dm.v1.DM/DmView",
+    "/bilibili.main.community.reply.v1.Reply/DetailList",
+    "/bilibili.main.community.reply.v1.Reply/DialogList",
+    "/bilibili.main.community.reply.v1.Reply/MainList",
+    "/bilibili.polymer.app.search.v1.Search/SearchAll",
+    "/bilibili.polymer.app.search.v1.Search/SearchByType",
+];
+
+/// Whether the gRPC method of given path, like `/package.Service/Method`,
+/// is a read-only one safe to retry.
+///
+/// Unknown methods may have side effects, e.g. sending a comment twice, so
+/// they are never retried.
+pub fn is_idempotent_grpc(path: &str) -> bool {
+    IDEMPOTENT_GRPC_METHODS.contains(&path)
+}
+
+/// Whether the error is a transient one worth retrying: connection errors,
+/// timeouts, 5xx responses and unavailable gRPC services.
+pub fn is_retryable(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            return is_retryable_reqwest_error(e);
+        }
+        if let Some(e) = cause.downcast_ref::<hyper_util::client::legacy::Error>() {
+            return e.is_connect();
+        }
+        if let Some(e) = cause.downcast_ref::<hyper::Error>() {
+            return e.is_timeout();
+        }
+        if let Some(e) = cause.downcast_ref::<tonic::Status>() {
+            return e.code() == tonic::Code::Unavailable;
+        }
+        // Transparent ones, whose inner errors are not in the chain
+        if let Some(e) = cause.downcast_ref::<CrateError>() {
+            return match e {
+                CrateError::HttpStatus(status) => (500..600).contains(status),
+                CrateError::Reqwest(e) => is_retryable_reqwest_error(e),
+                CrateError::HyperClient(e) => e.is_connect(),
+                CrateError::Hyper(e) => e.is_timeout(),
+                CrateError::GrpcStatus(e) => e.code() == tonic::Code::Unavailable,
+                _ => false,
+            };
+        }
+        if let Some(e) = cause.downcast_ref::<ServerError>() {
+            return is_retryable_server_error(e);
+        }
+        if let Some(ServerErrorExt::Server(e) | ServerErrorExt::ServerExt { source: e, .. }) =
+            cause.downcast_ref::<ServerErrorExt>()
+        {
+            return is_retryable_server_error(e);
+        }
+        false
+    })
+}
+
+#[inline]
+fn is_retryable_reqwest_error(e: &reqwest::Error) -> bool {
+    e.is_connect() || e.is_timeout() || e.status().is_some_and(|s| s.is_server_error())
+}
+
+#[inline]
+fn is_retryable_server_error(e: &ServerError) -> bool {
+    matches!(
+        e,
+        ServerError::RpcNetworkFatal
+            | ServerError::RpcReqServerInternal
+            | ServerError::RpcReqBadGateway
+            | ServerError::RpcReqServiceUnavailable
+            | ServerError::RpcGatewayTimeout
+            | ServerError::GrpcReqUnavailable
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::anyhow;
+
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(300),
+            switch_proxy: false,
+        };
+
+        assert!(policy.backoff(1) <= Duration::from_millis(100));
+        assert!(policy.backoff(2) <= Duration::from_millis(200));
+        assert!(policy.backoff(10) <= Duration::from_millis(300));
+        assert_eq!(RetryPolicy::NONE.backoff(1), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_run() {
+        let policy = RetryPolicy {
+            max_retries: 2,
+            ..RetryPolicy::NONE
+        };
+        let run = |results: Vec<Result<u32, u32>>| {
+            let policy = policy.clone();
+            async move {
+                let mut results = results.into_iter();
+                let mut attempts = 0;
+                let result = policy
+                    .run(
+                        || {
+                            attempts += 1;
+                            let result = results.next().unwrap();
+                            async move {
+                                result.map_err(|status| anyhow!(CrateError::HttpStatus(status)))
+                            }
+                        },
+                        |result| result.as_ref().is_err_and(is_retryable),
+                    )
+                    .await;
+                (result.ok(), attempts)
+            }
+        };
+
+        // Succeeded after retries
+        assert_eq!(run(vec![Err(502), Err(503), Ok(1)]).await, (Some(1), 3));
+        // Retry budget runs out
+        assert_eq!(
+            run(vec![Err(502), Err(502), Err(502), Ok(1)]).await,
+            (None, 3)
+        );
+        // Not retryable
+        assert_eq!(run(vec![Err(404), Ok(1)]).await, (None, 1));
+        assert_eq!(run(vec![Ok(1)]).await, (Some(1), 1));
+
+        // Never retried without a budget
+        let mut attempts = 0;
+        let result = RetryPolicy::NONE
+            .run(
+                || {
+                    attempts += 1;
+                    async { Err::<(), _>(anyhow!(CrateError::HttpStatus(502))) }
+                },
+                |_| true,
+            )
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_is_idempotent_grpc() {
+        assert!(is_idempotent_grpc("/bilibili.app.view.v1.View/View"));
+        assert!(is_idempotent_grpc(
+            "/bilibili.app.playerunite.v1.Player/PlayViewUnite"
+        ));
+        assert!(!is_idempotent_grpc(
+            "/bilibili.main.community.reply.v1.Reply/Add"
+        ));
+        assert!(!is_idempotent_grpc("/bilibili.app.view.v1.View/Like"));
+        assert!(!is_idempotent_grpc("bilibili.app.view.v1.View/View"));
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(is_retryable(&anyhow!(ServerError::RpcGatewayTimeout)));
+        assert!(is_retryable(&anyhow!(ServerErrorExt::from(
+            tonic::Status::unavailable("")
+        ))));
+        assert!(is_retryable(&anyhow!(CrateError::HttpStatus(502))));
+        assert!(!is_retryable(&anyhow!(CrateError::HttpStatus(404))));
+        assert!(!is_retryable(&anyhow!(ServerError::RpcReqRiskControl)));
+        assert!(!is_retryable(&anyhow!("unknown")));
+    }
+}
@@ -0,0 +1,140 @@
+use anyhow::Result;
+use tokio::sync::OnceCell;
+
+use std::{
+    collections::HashMap,
+    error::Error as StdError,
+    fmt,
+    future::Future,
+    hash::Hash,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+/// Error of a call shared by all callers coalesced, the causes kept for
+/// mapping error codes.
+#[derive(Debug, Clone)]
+pub struct SharedError(Arc<anyhow::Error>);
+
+impl fmt::Display for SharedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&*self.0, f)
+    }
+}
+
+impl StdError for SharedError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&**self.0)
+    }
+}
+
+type Call<V> = Arc<OnceCell<Result<V, SharedError>>>;
+
+/// Coalesce concurrent calls with the same key into one, all callers waiting
+/// on it and receiving its result.
+///
+/// If the caller running the call is cancelled, another one waiting takes
+/// over. Results are not cached, a call with the key after the last one
+/// completed runs again.
+#[derive(Debug)]
+pub struct Singleflight<K, V> {
+    calls: Mutex<HashMap<K, Call<V>>>,
+}
+
+impl<K, V> Default for Singleflight<K, V> {
+    fn default() -> Self {
+        Self {
+            calls: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Singleflight<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    fn calls(&self) -> MutexGuard<'_, HashMap<K, Call<V>>> {
+        self.calls.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Run `f`, or wait for the one with the same key running.
+    pub async fn run<F, Fut>(&self, key: K, f: F) -> Result<V>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V>>,
+    {
+        let call = self.calls().entry(key.clone()).or_default().clone();
+
+        let result = call
+            .get_or_init(|| async { f().await.map_err(|e| SharedError(Arc::new(e))) })
+            .await
+            .clone();
+
+        let mut calls = self.calls();
+        if calls.get(&key).is_some_and(|c| Arc::ptr_eq(c, &call)) {
+            calls.remove(&key);
+        }
+
+        result.map_err(anyhow::Error::from)
+    }
+
+    /// Count of calls running.
+    pub fn len(&self) -> usize {
+        self.calls().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::anyhow;
+    use lib_utils::error::ServerError;
+
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_singleflight() {
+        let flights = Singleflight::new();
+        let count = &AtomicUsize::new(0);
+
+        let call = move || async move {
+            count.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok(1)
+        };
+        let (a, b, c) = tokio::join!(
+            flights.run("a", call),
+            flights.run("a", call),
+            flights.run("b", call)
+        );
+        assert_eq!((a.unwrap(), b.unwrap(), c.unwrap()), (1, 1, 1));
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+        assert!(flights.is_empty());
+
+        // Runs again after completed
+        flights.run("a", call).await.unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+
+        let (a, b) = tokio::join!(
+            flights.run("e", || async {
+                Err(anyhow!(ServerError::RpcReqRiskControl))
+            }),
+            flights.run("e", || async { Ok(0) })
+        );
+        for e in [a.unwrap_err(), b.unwrap_err()] {
+            assert!(e.chain().any(|e| matches!(
+                e.downcast_ref::<ServerError>(),
+                Some(ServerError::RpcReqRiskControl)
+            )));
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use http::{HeaderMap as HttpHeaderMap, HeaderValue};
+
+use std::time::Duration;
+
+/// Max value of `grpc-timeout`, 8 digits at most
+const GRPC_TIMEOUT_MAX: u64 = 99_999_999;
+
+/// Timeouts of upstream requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Timeouts {
+    /// Timeout of connecting, including handshakes with the proxy
+    pub connect: Duration,
+    /// Timeout of a whole request
+    pub request: Duration,
+    /// Pooled connections idle for this long are closed
+    pub idle: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect: Duration::from_secs(10),
+            request: Duration::from_secs(15),
+            idle: Duration::from_secs(3600),
+        }
+    }
+}
+
+impl Timeouts {
+    /// Deadline of a gRPC request, the smaller one of `request` and the
+    /// `grpc-timeout` given in headers, which is then set to the deadline.
+    pub(crate) fn grpc_deadline(&self, headers: &mut HttpHeaderMap) -> Duration {
+        let deadline = headers
+            .get("grpc-timeout")
+            .and_then(parse_grpc_timeout)
+            .map_or(self.request, |t| t.min(self.request));
+
+        headers.insert("grpc-timeout", encode_grpc_timeout(deadline));
+        deadline
+    }
+}
+
+/// Parse `grpc-timeout` header, like `100m`
+pub(crate) fn parse_grpc_timeout(value: &HeaderValue) -> Option<Duration> {
+    let value = value.to_str().ok()?;
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+
+    let (digits, unit) = value.split_at(value.len() - 1);
+    let n: u64 = digits.parse().ok()?;
+
+    Some(match unit {
+        "H" => Duration::from_secs(n * 3600),
+        "M" => Duration::from_secs(n * 60),
+        "S" => Duration::from_secs(n),
+        "m" => Duration::from_millis(n),
+        "u" => Duration::from_micros(n),
+        "n" => Duration::from_nanos(n),
+        _ => return None,
+    })
+}
+
+/// Encode `grpc-timeout` header, in millis or seconds when too long
+pub(crate) fn encode_grpc_timeout(timeout: Duration) -> HeaderValue {
+    let millis = timeout.as_millis() as u64;
+
+    let value = if millis <= GRPC_TIMEOUT_MAX {
+        format!("{}m", millis)
+    } else {
+        format!("{}S", timeout.as_secs().min(GRPC_TIMEOUT_MAX))
+    };
+
+    // SAFE: digits and unit only
+    HeaderValue::try_from(value).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_grpc_timeout() {
+        let parse = |v: &'static str| parse_grpc_timeout(&HeaderValue::from_static(v));
+
+        assert_eq!(parse("100m"), Some(Duration::from_millis(100)));
+        assert_eq!(parse("2S"), Some(Duration::from_secs(2)));
+        assert_eq!(parse("1H"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse("100"), None);
+        assert_eq!(parse("123456789m"), None);
+
+        assert_eq!(encode_grpc_timeout(Duration::from_secs(15)), "15000m");
+        assert_eq!(
+            encode_grpc_timeout(Duration::from_secs(GRPC_TIMEOUT_MAX)),
+            "99999999S"
+        );
+
+        let timeouts = Timeouts::default();
+        let mut headers = HttpHeaderMap::new();
+        assert_eq!(timeouts.grpc_deadline(&mut headers), timeouts.request);
+        assert_eq!(headers["grpc-timeout"], "15000m");
+
+        headers.insert("grpc-timeout", HeaderValue::from_static("3S"));
+        assert_eq!(timeouts.grpc_deadline(&mut headers), Duration::from_secs(3));
+        assert_eq!(headers["grpc-timeout"], "3000m");
+    }
+}
@@ -1,3 +1,7 @@
+use lib_utils::error::{ServerError, ServerErrorExt};
+
+use std::error::Error as StdError;
+
 #[cfg(feature = "full")]
 pub(crate) type BoxError = Box<dyn std::error::Error + Send + Sync>;
 
@@ -5,7 +9,10 @@ pub(crate) type BoxError = Box<dyn std::error::Error + Send + Sync>;
 /// Unified error type for lib-rpc-client
 pub enum Error {
     #[error(transparent)]
-    Hyper(#[from] hyper_014::Error),
+    Hyper(#[from] hyper::Error),
+
+    #[error(transparent)]
+    HyperClient(#[from] hyper_util::client::legacy::Error),
 
     #[error(transparent)]
     Reqwest(#[from] reqwest::Error),
@@ -55,11 +62,118 @@ pub enum ProxyError {
     #[error(transparent)]
     ResolveSocksIo(#[from] std::io::Error),
     #[error(transparent)]
-    InvalidUri(#[from] http_02::uri::InvalidUri),
+    InvalidUri(#[from] http::uri::InvalidUri),
     #[error(transparent)]
     UrlParse(#[from] url::ParseError),
     #[error("Invalid proxy scheme [{0:?}]")]
     InvalidProxyScheme(Option<String>),
     #[error("Invalid proxy host [{0:?}]")]
     InvalidProxyHost(Option<String>),
+    #[error("No available proxy in pool [{0}]")]
+    NoAvailableProxy(String),
+}
+
+impl From<&Error> for ServerErrorExt {
+    fn from(e: &Error) -> Self {
+        let server_error = match e {
+            Error::Hyper(e) => hyper_server_error(e),
+            Error::HyperClient(_) | Error::ProxyError(_) => ServerError::RpcNetworkFatal,
+            Error::Reqwest(e) => reqwest_server_error(e),
+            Error::GrpcStatus(e) => return Self::from(e.clone()),
+            Error::UrlParse { .. } | Error::Unknown => ServerError::GeneralRpc,
+            Error::HttpStatus(status) => ServerError::from_http_status(*status),
+            Error::NotGrpcResponse | Error::UnknownDataStruct => ServerError::Serialization,
+            Error::BiliError(e) => return Self::from(e.clone()),
+            Error::Any(e) => {
+                return Self::from_anyhow_ref(e).unwrap_or(Self::Server(ServerError::GeneralRpc))
+            }
+        };
+        Self::Server(server_error)
+    }
+}
+
+/// Map errors of this crate, and those of HTTP clients, into [ServerErrorExt].
+///
+/// See [set_error_mapper](lib_utils::error::set_error_mapper).
+pub fn map_error(e: &(dyn StdError + 'static)) -> Option<ServerErrorExt> {
+    if let Some(e) = e.downcast_ref::<Error>() {
+        return Some(ServerErrorExt::from(e));
+    }
+    let server_error = if e.is::<ProxyError>() || e.is::<hyper_util::client::legacy::Error>() {
+        ServerError::RpcNetworkFatal
+    } else if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+        reqwest_server_error(e)
+    } else if let Some(e) = e.downcast_ref::<hyper::Error>() {
+        hyper_server_error(e)
+    } else {
+        return None;
+    };
+    Some(ServerErrorExt::Server(server_error))
+}
+
+#[inline]
+fn reqwest_server_error(e: &reqwest::Error) -> ServerError {
+    if e.is_timeout() {
+        ServerError::RpcGatewayTimeout
+    } else if let Some(status) = e.status() {
+        ServerError::from_http_status(status.as_u16())
+    } else if e.is_decode() {
+        ServerError::Serialization
+    } else if e.is_builder() {
+        ServerError::GeneralRpc
+    } else {
+        ServerError::RpcNetworkFatal
+    }
+}
+
+#[inline]
+fn hyper_server_error(e: &hyper::Error) -> ServerError {
+    if e.is_timeout() {
+        ServerError::RpcGatewayTimeout
+    } else {
+        ServerError::RpcNetworkFatal
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::anyhow;
+    use lib_utils::error::BiliError;
+
+    use super::*;
+
+    #[test]
+    fn test_map_error() {
+        let map = |e: Error| ServerErrorExt::from(&e);
+
+        assert!(matches!(
+            map(Error::HttpStatus(429)),
+            ServerErrorExt::Server(ServerError::RpcReqRateLimit)
+        ));
+        assert!(matches!(
+            map(Error::ProxyError(ProxyError::NoAvailableProxy(
+                "pool".to_owned()
+            ))),
+            ServerErrorExt::Server(ServerError::RpcNetworkFatal)
+        ));
+        assert!(matches!(
+            map(Error::BiliError(BiliError::ReqRiskControl)),
+            ServerErrorExt::Server(ServerError::RpcReqRiskControl)
+        ));
+        assert!(matches!(
+            map(Error::GrpcStatus(tonic::Status::unavailable(""))),
+            ServerErrorExt::Server(ServerError::GrpcReqUnavailable)
+        ));
+        assert!(matches!(
+            map(Error::Any(anyhow!(BiliError::ResVipOnly))),
+            ServerErrorExt::Server(ServerError::VipOnly)
+        ));
+
+        let e = anyhow!(Error::HttpStatus(504));
+        assert!(matches!(
+            e.chain().find_map(map_error),
+            Some(ServerErrorExt::Server(ServerError::RpcGatewayTimeout))
+        ));
+        assert!(map_error(&*anyhow!("unknown")).is_none());
+    }
 }
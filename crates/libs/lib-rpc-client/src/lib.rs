@@ -6,6 +6,12 @@ pub(crate) use lib_utils::error::BiliError;
 
 #[cfg(feature = "full")]
 pub mod client {
+    mod cache;
     pub mod grpc;
+    pub mod limit;
+    pub mod pool;
     pub mod rest;
+    pub mod retry;
+    pub mod singleflight;
+    pub mod timeout;
 }
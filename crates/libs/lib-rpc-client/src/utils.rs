@@ -1,6 +1,6 @@
 use anyhow::{bail, Result};
 use bytes::Bytes;
-use http_02::{HeaderMap as HttpHeaderMap, HeaderValue as HttpHeaderValue};
+use http::{HeaderMap as HttpHeaderMap, HeaderValue as HttpHeaderValue};
 use serde::{Deserialize, Serialize};
 
 use crate::{BiliError, CrateError};
@@ -51,6 +51,14 @@ pub type RawResponseExt = ResponseExt<(), reqwest::Response>;
 /// Consumed response from upstream.
 pub type ConsumedResponseExt<D = serde_json::Value> = ResponseExt<HttpHeaderMap, Option<D>>;
 
+/// Body stream of response from upstream.
+#[cfg(feature = "full")]
+pub type BodyStream =
+    std::pin::Pin<Box<dyn futures_core::Stream<Item = reqwest::Result<Bytes>> + Send>>;
+/// Response from upstream with headers taken, body not consumed but streamed.
+#[cfg(feature = "full")]
+pub type StreamResponseExt = ResponseExt<HttpHeaderMap, BodyStream>;
+
 impl<H, T> ResponseExt<H, T> {
     pub fn new(
         o_req: reqwest::Request,
@@ -95,6 +103,11 @@ impl RawResponseExt {
         Ok(())
     }
 
+    /// Original response
+    pub fn inner(&self) -> &reqwest::Response {
+        &self.resp_data
+    }
+
     /// Into original response
     pub fn into_inner(self) -> reqwest::Response {
         self.resp_data
@@ -132,6 +145,22 @@ impl RawResponseExt {
         })
     }
 
+    /// Consumes reqwest::Response and return `StreamResponseExt` with headers
+    /// and body stream, for piping the body without buffering it.
+    #[cfg(feature = "full")]
+    #[tracing::instrument(level = "debug", name = "ResponseExt.stream", skip_all, err)]
+    pub fn stream(self) -> Result<StreamResponseExt> {
+        self.check_response_status()?;
+        let mut response = self.resp_data;
+        let resp_headers = std::mem::take(response.headers_mut());
+        Ok(StreamResponseExt {
+            o_req: self.o_req,
+            o_proxy: self.o_proxy,
+            resp_headers,
+            resp_data: Box::pin(response.bytes_stream()),
+        })
+    }
+
     /// Consumes reqwest::Response and return `ConsumedResponseExt` with headers
     /// and deserialized JSON data.
     ///
@@ -288,14 +317,17 @@ impl<D> ConsumedResponseExt<D> {
     }
 
     /// Break [`ConsumedResponseExt`] into parts
-    pub fn into_parts(
-        self,
-    ) -> (
-        reqwest::Request,
-        Option<String>,
-        HttpHeaderMap, // compatibility for reqwest with dep:http v0.2
-        Option<D>,
-    ) {
+    pub fn into_parts(self) -> (reqwest::Request, Option<String>, HttpHeaderMap, Option<D>) {
+        (self.o_req, self.o_proxy, self.resp_headers, self.resp_data)
+    }
+}
+
+// ======== impl for StreamResponseExt ========
+
+#[cfg(feature = "full")]
+impl StreamResponseExt {
+    /// Break [`StreamResponseExt`] into parts
+    pub fn into_parts(self) -> (reqwest::Request, Option<String>, HttpHeaderMap, BodyStream) {
         (self.o_req, self.o_proxy, self.resp_headers, self.resp_data)
     }
 }
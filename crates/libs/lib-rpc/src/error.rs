@@ -1,4 +1,7 @@
 use lib_rpc_client::error::Error as ClientError;
+use lib_utils::error::{ServerError, ServerErrorExt};
+
+use std::error::Error as StdError;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Kind {
@@ -9,7 +12,7 @@ pub enum Kind {
     UrlParse(#[from] url::ParseError),
 
     #[error(transparent)]
-    InvalidUri(#[from] http_02::uri::InvalidUri),
+    InvalidUri(#[from] http::uri::InvalidUri),
 
     #[error(transparent)]
     Any(#[from] anyhow::Error),
@@ -27,3 +30,40 @@ pub enum RpcError {
     /// Any other error
     Any(#[from] anyhow::Error),
 }
+
+impl From<&Kind> for ServerErrorExt {
+    fn from(kind: &Kind) -> Self {
+        match kind {
+            Kind::ClientError(e) => Self::from(e),
+            Kind::UrlParse(_) | Kind::InvalidUri(_) => Self::Server(ServerError::GeneralRpc),
+            Kind::Any(e) => {
+                Self::from_anyhow_ref(e).unwrap_or(Self::Server(ServerError::GeneralRpc))
+            }
+        }
+    }
+}
+
+impl From<&RpcError> for ServerErrorExt {
+    fn from(e: &RpcError) -> Self {
+        match e {
+            RpcError::PreRequest(kind) | RpcError::Request(kind) | RpcError::Response(kind) => {
+                Self::from(kind)
+            }
+            RpcError::Any(e) => {
+                Self::from_anyhow_ref(e).unwrap_or(Self::Server(ServerError::GeneralRpc))
+            }
+        }
+    }
+}
+
+/// Map errors of RPCs and their clients into [ServerErrorExt], to be set by
+/// [set_error_mapper](lib_utils::error::set_error_mapper) at startup.
+pub fn map_error(e: &(dyn StdError + 'static)) -> Option<ServerErrorExt> {
+    if let Some(e) = e.downcast_ref::<RpcError>() {
+        return Some(ServerErrorExt::from(e));
+    }
+    if let Some(kind) = e.downcast_ref::<Kind>() {
+        return Some(ServerErrorExt::from(kind));
+    }
+    lib_rpc_client::error::map_error(e)
+}
@@ -75,6 +75,35 @@ impl<'q, 'm: 'q> TryFrom<&'m QueryMap<'m>> for PlayurlReq<'q> {
     }
 }
 
+impl PlayurlReq<'_> {
+    /// Key of the content requested, the same for equivalent requests, e.g.
+    /// ones with `aid` or `bvid` only.
+    pub fn normalized_key(&self) -> String {
+        let vod = &self.vod;
+        let aid = match (vod.aid, &self.vod_ext.bvid) {
+            (0, Some(bvid)) => bv2av!(bvid.as_ref()),
+            (aid, _) => aid as u64,
+        };
+
+        format!(
+            "av{}:{}:{}:{}:{}:{}:{}:{}:{}:{}:ep{}:ss{}:md{}",
+            aid,
+            vod.cid,
+            vod.qn,
+            vod.fnver,
+            vod.fnval,
+            vod.download,
+            vod.force_host,
+            vod.fourk,
+            vod.prefer_codec_type,
+            vod.voice_balance,
+            self.vod_ext.ep_id.as_deref().unwrap_or_default(),
+            self.vod_ext.season_id.as_deref().unwrap_or_default(),
+            self.vod_ext.media_id.as_deref().unwrap_or_default(),
+        )
+    }
+}
+
 impl<'r> TryFrom<PlayViewUniteReq> for PlayurlReq<'r> {
     type Error = anyhow::Error;
 
@@ -1,7 +1,18 @@
-use http_02::HeaderMap as HttpHeaderMap;
+use bytes::Bytes;
+use http::{HeaderMap as HttpHeaderMap, StatusCode};
 
 /// Simple wrapper for grpc response with headers.
+#[derive(Clone)]
 pub struct ResponseWrapper<T> {
     pub inner: T,
     pub headers: HttpHeaderMap,
 }
+
+/// Raw gRPC response with headers and trailers, body not decoded.
+pub struct GrpcRawResponse {
+    /// HTTP status, the gRPC one is `grpc-status` in trailers or headers
+    pub status: StatusCode,
+    pub headers: HttpHeaderMap,
+    pub body: Bytes,
+    pub trailers: Option<HttpHeaderMap>,
+}
@@ -1,9 +1,34 @@
+pub(crate) mod device;
+pub mod grpc;
 pub mod playurl;
 pub(crate) mod client {
     pub use lib_rpc_client::client::grpc;
+    pub use lib_rpc_client::client::limit;
+    pub use lib_rpc_client::client::pool;
     pub use lib_rpc_client::client::rest;
+    pub use lib_rpc_client::client::retry;
+    pub use lib_rpc_client::client::singleflight;
+    pub use lib_rpc_client::client::timeout;
     pub use lib_rpc_client::utils;
 }
 pub mod interface;
+pub mod passthrough;
+pub mod ticket;
+pub mod wbi;
+
+pub use client::{
+    grpc::client::{init_grpc_client, RawBody},
+    limit::{init_global_limit, ConcurrencyLimitOptions},
+    pool::{get_pool, init_proxy_pools, ProxyPool, ProxyPoolOptions},
+    rest::{init_reqwest_clients, ReqBody},
+    retry::RetryPolicy,
+    timeout::Timeouts,
+};
 
 pub(crate) use lib_bilibili::bapis;
+
+/// Count of clients cached for proxies, RESTful and gRPC ones, for metrics.
+#[inline]
+pub fn client_count() -> usize {
+    client::rest::client_count() + client::grpc::client::client_count()
+}
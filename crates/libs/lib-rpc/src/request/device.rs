@@ -0,0 +1,130 @@
+//! Device identity presented to upstream, one for each account, see
+//! [DeviceProfileStore](lib_core::business::fingerprint::profile::DeviceProfileStore).
+
+use lib_core::business::fingerprint::profile::device_profiles;
+use lib_utils::headers::HeaderKey;
+
+use crate::utils::ManagedHeaderMap;
+
+/// Access key of the request, from `access_key` in query or `authorization`
+/// in headers.
+fn access_key_of<'a>(headers: &'a ManagedHeaderMap, query: Option<&'a str>) -> Option<&'a str> {
+    query
+        .and_then(|query| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("access_key="))
+        })
+        .or_else(|| {
+            headers
+                .get(HeaderKey::Authorization)
+                .ok()
+                .and_then(|authorization| authorization.strip_prefix("identify_v1 "))
+        })
+        .filter(|access_key| !access_key.is_empty())
+}
+
+/// Key of the account of the request in
+/// [DeviceProfileStore](lib_core::business::fingerprint::profile::DeviceProfileStore),
+/// by the access key configured, `x-bili-mid` in headers, or the proxy if
+/// neither known.
+pub(crate) fn account_of(
+    headers: &ManagedHeaderMap,
+    query: Option<&str>,
+    proxy: Option<&str>,
+) -> String {
+    let mid = headers
+        .get(HeaderKey::BiliMid)
+        .ok()
+        .and_then(|mid| mid.parse().ok());
+
+    device_profiles().account_key(access_key_of(headers, query), mid, proxy)
+}
+
+/// Set headers of the device of the account for gRPC requests, see
+/// [account_of], unless `x-bili-device-bin` is given.
+pub(crate) async fn inject_device(headers: &mut ManagedHeaderMap, proxy: Option<&str>) {
+    if headers.contains_key(HeaderKey::BiliDeviceBin) {
+        return;
+    }
+
+    let profile = device_profiles()
+        .get_or_create(&account_of(headers, None, proxy))
+        .await;
+    let access_key = access_key_of(headers, None).map(str::to_owned);
+
+    profile.set_grpc_headers(headers, access_key.as_deref());
+}
+
+/// Set headers of the device of the account for REST requests to the app
+/// API, see [account_of], unless `buvid` is given.
+pub(crate) async fn inject_app_device(
+    headers: &mut ManagedHeaderMap,
+    query: Option<&str>,
+    proxy: Option<&str>,
+) {
+    if headers.contains_key(HeaderKey::Buvid) {
+        return;
+    }
+
+    let profile = device_profiles()
+        .get_or_create(&account_of(headers, query, proxy))
+        .await;
+
+    profile.set_headers(headers);
+}
+
+#[cfg(test)]
+mod test {
+    use lib_bilibili::bapis::metadata::device::Device;
+    use lib_utils::headers::BiliHeaderT;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_inject_device() {
+        let mut headers = ManagedHeaderMap::new(true, true);
+        headers.set_access_key("access_key").set_mid(114514);
+        inject_device(&mut headers, None).await;
+        assert!(headers.contains_key(HeaderKey::BiliDeviceBin));
+        assert!(headers.contains_key(HeaderKey::BiliMetadataBin));
+
+        // The same device for the same account, whatever the proxy
+        let mut another = ManagedHeaderMap::new(true, true);
+        another.set_access_key("access_key").set_mid(114514);
+        inject_device(&mut another, Some("http://proxy")).await;
+        assert_eq!(
+            headers.get(HeaderKey::Buvid).unwrap(),
+            another.get(HeaderKey::Buvid).unwrap()
+        );
+
+        // And for REST requests
+        let mut rest = ManagedHeaderMap::new(false, false);
+        rest.set_mid(114514);
+        inject_app_device(&mut rest, Some("access_key=access_key&ts=0"), None).await;
+        assert_eq!(
+            headers.get(HeaderKey::Buvid).unwrap(),
+            rest.get(HeaderKey::Buvid).unwrap()
+        );
+        assert!(!rest.contains_key(HeaderKey::BiliDeviceBin));
+
+        // Kept if given
+        let mut given = ManagedHeaderMap::new(true, true);
+        given.set_device_bin(Device::default());
+        inject_device(&mut given, None).await;
+        assert!(!given.contains_key(HeaderKey::Buvid));
+    }
+
+    #[test]
+    fn test_account_of() {
+        let mut headers = ManagedHeaderMap::new(false, true);
+        let anonymous = account_of(&headers, None, Some("http://proxy"));
+        assert!(anonymous.starts_with("anon:"));
+
+        headers.set_mid(114514);
+        assert_eq!(
+            account_of(&headers, None, Some("http://proxy")),
+            "mid:114514"
+        );
+    }
+}
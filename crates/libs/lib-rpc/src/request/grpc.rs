@@ -0,0 +1,394 @@
+use anyhow::Result;
+use http::{HeaderMap as HttpHeaderMap, Uri};
+use lib_utils::error::ServerErrorExt;
+
+use std::{future::Future, marker::PhantomData, sync::Arc};
+
+use super::{
+    bapis::{
+        app::{
+            playerunite::v1::{player_client::PlayerClient, PlayViewUniteReply, PlayViewUniteReq},
+            view::v1::{view_client::ViewClient, ViewReply, ViewReq},
+        },
+        community::service::dm::v1::{dm_client::DmClient, DmSegMobileReply, DmSegMobileReq},
+        main::community::reply::v1::{reply_client::ReplyClient, MainListReply, MainListReq},
+        polymer::app::search::v1::{
+            search_client::SearchClient, SearchAllRequest, SearchAllResponse,
+        },
+        ticket::v1::{ticket_client::TicketClient, GetTicketRequest, GetTicketResponse},
+    },
+    client::{
+        grpc::{client::GrpcClientExt, CompressionEncoding},
+        pool::ProxyPool,
+        retry::{is_retryable, RetryPolicy},
+        timeout::Timeouts,
+    },
+    device::inject_device,
+    interface::RpcBuilderT,
+    ticket::{inject_ticket, TicketKind},
+};
+use crate::{
+    model::response::ResponseWrapper,
+    utils::{ManagedHeaderMap, Upstream},
+};
+
+/// A method of tonic-generated gRPC clients, implemented by `grpc_rpc!`.
+pub trait GrpcClientT<'c, Req, Resp>: Sized + Send + Clone {
+    /// Create the client with given [`GrpcClientExt`] and origin, accepting
+    /// gzip compressed responses.
+    fn build(
+        inner: GrpcClientExt<'c>,
+        origin: Uri,
+        send_compressed: Option<CompressionEncoding>,
+    ) -> Self;
+
+    /// Call the method
+    fn call(
+        &mut self,
+        request: tonic::Request<Req>,
+    ) -> impl Future<Output = Result<tonic::Response<Resp>, tonic::Status>> + Send;
+}
+
+/// Generic RPC builder for a method of tonic-generated gRPC clients in `bapis`
+#[derive(Debug)]
+pub struct GrpcRpc<'r, C, Req, Resp> {
+    upstream: Upstream<'r>,
+    proxy: Option<&'r str>,
+    proxy_pool: Option<Arc<ProxyPool>>,
+    retry: Option<RetryPolicy>,
+    timeouts: Option<Timeouts>,
+    headers: ManagedHeaderMap,
+    /// Encoding to compress requests with, not compressed if `None`
+    send_compressed: Option<CompressionEncoding>,
+    /// Whether to carry `x-bili-ticket`
+    ticket: bool,
+    request: Req,
+    _client: PhantomData<fn() -> (C, Resp)>,
+}
+
+impl<'r, C, Req, Resp> GrpcRpc<'r, C, Req, Resp> {
+    /// Set the encoding to compress requests with
+    #[inline]
+    pub fn with_send_compressed(mut self, encoding: Option<CompressionEncoding>) -> Self {
+        self.send_compressed = encoding;
+        self
+    }
+}
+
+impl<'r, C, Req, Resp> RpcBuilderT<'r> for GrpcRpc<'r, C, Req, Resp>
+where
+    C: GrpcClientT<'r, Req, Resp>,
+    Req: Clone + Send + Sync,
+    Resp: Send,
+{
+    const DEFAULT_UPSTREAM: Upstream<'r> = Upstream::APP_DEFAULT;
+
+    type Request = Req;
+    type Response = ResponseWrapper<Resp>;
+
+    #[inline]
+    fn new(request: Self::Request, upstream: impl Into<Upstream<'r>>) -> Self {
+        Self {
+            upstream: upstream.into(),
+            proxy: None,
+            proxy_pool: None,
+            retry: None,
+            timeouts: None,
+            headers: ManagedHeaderMap::new(true, true),
+            send_compressed: None,
+            ticket: true,
+            request,
+            _client: PhantomData,
+        }
+    }
+
+    #[inline]
+    fn with_upstream(mut self, upstream: impl Into<Upstream<'r>>) -> Self {
+        self.upstream = upstream.into();
+        self
+    }
+
+    #[inline]
+    fn with_proxy(mut self, proxy: Option<&'r str>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    #[inline]
+    fn with_proxy_pool(mut self, proxy_pool: Option<Arc<ProxyPool>>) -> Self {
+        self.proxy_pool = proxy_pool;
+        self
+    }
+
+    #[inline]
+    fn with_retry(mut self, retry: Option<RetryPolicy>) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    #[inline]
+    fn with_timeouts(mut self, timeouts: Option<Timeouts>) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    #[inline]
+    fn with_headers(mut self, headers: Option<impl Into<HttpHeaderMap>>) -> Self {
+        if let Some(headers) = headers {
+            self.headers = ManagedHeaderMap::new_from_existing(headers.into(), true, true);
+        }
+        self
+    }
+
+    #[inline]
+    fn with_headers_managed(mut self, headers: Option<impl Into<ManagedHeaderMap>>) -> Self {
+        if let Some(headers) = headers {
+            self.headers = headers.into();
+        }
+        self
+    }
+
+    #[inline]
+    fn with_ticket(mut self, ticket: bool) -> Self {
+        self.ticket = ticket;
+        self
+    }
+
+    #[tracing::instrument(
+        level = "debug",
+        name = "GrpcRpc.execute",
+        skip(self),
+        fields(request = std::any::type_name::<Req>()),
+        err
+    )]
+    async fn execute(mut self) -> Result<ResponseWrapper<Resp>> {
+        let uri = self.upstream.uri()?;
+
+        inject_device(&mut self.headers, self.proxy).await;
+        if self.ticket {
+            inject_ticket(
+                &mut self.headers,
+                TicketKind::of(&self.upstream),
+                None,
+                self.proxy,
+            )
+            .await;
+        }
+
+        let policy = self.retry.unwrap_or(RetryPolicy::NONE);
+
+        let grpc_client = match &self.proxy_pool {
+            // Keep the proxy picked when retrying
+            Some(pool) if !policy.switch_proxy => {
+                GrpcClientExt::with_pool_picked(pool.clone(), pool.pick()?)
+            }
+            Some(pool) => GrpcClientExt::with_pool(pool.clone()),
+            None => GrpcClientExt::new(self.proxy),
+        }
+        .with_timeouts(self.timeouts.unwrap_or_default());
+
+        let client = C::build(grpc_client, uri, self.send_compressed);
+
+        policy
+            .run(
+                || {
+                    let mut client = client.clone();
+                    let grpc_request = self.headers.grpc_request(self.request.clone());
+                    async move {
+                        let (headers, inner, _) = client
+                            .call(grpc_request)
+                            .await
+                            .map_err(ServerErrorExt::from)?
+                            .into_parts();
+                        anyhow::Ok(ResponseWrapper {
+                            inner,
+                            headers: headers.into_headers(),
+                        })
+                    }
+                },
+                |result| result.as_ref().is_err_and(is_retryable),
+            )
+            .await
+    }
+}
+
+/// Implement [`GrpcClientT`] for methods of tonic-generated gRPC clients and
+/// define [`GrpcRpc`] builders of them.
+macro_rules! grpc_rpc {
+    ($($(#[$meta:meta])* $name:ident: $client:ident::$method:ident($req:ty) -> $resp:ty;)*) => {
+        $(
+            impl<'c> GrpcClientT<'c, $req, $resp> for $client<GrpcClientExt<'c>> {
+                #[inline]
+                fn build(
+                    inner: GrpcClientExt<'c>,
+                    origin: Uri,
+                    send_compressed: Option<CompressionEncoding>,
+                ) -> Self {
+                    let client = $client::with_origin(inner, origin)
+                        .accept_compressed(CompressionEncoding::Gzip);
+
+                    match send_compressed {
+                        Some(encoding) => client.send_compressed(encoding),
+                        None => client,
+                    }
+                }
+
+                #[inline]
+                fn call(
+                    &mut self,
+                    request: tonic::Request<$req>,
+                ) -> impl Future<Output = Result<tonic::Response<$resp>, tonic::Status>> + Send {
+                    self.$method(request)
+                }
+            }
+
+            $(#[$meta])*
+            pub type $name<'r> = GrpcRpc<'r, $client<GrpcClientExt<'r>>, $req, $resp>;
+        )*
+    };
+}
+
+grpc_rpc! {
+    /// RPC builder for `bilibili.app.playerunite.v1.Player/PlayViewUnite`
+    PlayViewUniteRpc: PlayerClient::play_view_unite(PlayViewUniteReq) -> PlayViewUniteReply;
+    /// RPC builder for `bilibili.app.view.v1.View/View`
+    ViewRpc: ViewClient::view(ViewReq) -> ViewReply;
+    /// RPC builder for `bilibili.community.service.dm.v1.DM/DmSegMobile`
+    DmSegMobileRpc: DmClient::dm_seg_mobile(DmSegMobileReq) -> DmSegMobileReply;
+    /// RPC builder for `bilibili.main.community.reply.v1.Reply/MainList`
+    MainListRpc: ReplyClient::main_list(MainListReq) -> MainListReply;
+    /// RPC builder for `bilibili.polymer.app.search.v1.Search/SearchAll`
+    SearchAllRpc: SearchClient::search_all(SearchAllRequest) -> SearchAllResponse;
+    /// RPC builder for `bilibili.ticket.v1.Ticket/GetTicket`
+    GetTicketRpc: TicketClient::get_ticket(GetTicketRequest) -> GetTicketResponse;
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    /// Attempts of [`FlakyClient`], which is built once per RPC
+    static ATTEMPTS: AtomicU32 = AtomicU32::new(0);
+
+    /// Unavailable for the first 2 attempts, then replies with the request
+    /// plus 1
+    #[derive(Clone)]
+    struct FlakyClient;
+
+    impl<'c> GrpcClientT<'c, u32, u32> for FlakyClient {
+        fn build(
+            _inner: GrpcClientExt<'c>,
+            _origin: Uri,
+            _send_compressed: Option<CompressionEncoding>,
+        ) -> Self {
+            Self
+        }
+
+        fn call(
+            &mut self,
+            request: tonic::Request<u32>,
+        ) -> impl Future<Output = Result<tonic::Response<u32>, tonic::Status>> + Send {
+            let attempt = ATTEMPTS.fetch_add(1, Ordering::SeqCst) + 1;
+            let message = request.into_inner();
+
+            async move {
+                if message == 0 {
+                    return Err(tonic::Status::invalid_argument("zero"));
+                }
+                if attempt < 3 {
+                    return Err(tonic::Status::unavailable("flaky"));
+                }
+
+                let mut response = tonic::Response::new(message + 1);
+                response
+                    .metadata_mut()
+                    .insert("x-attempt", attempt.to_string().parse().unwrap());
+                Ok(response)
+            }
+        }
+    }
+
+    type FlakyRpc<'r> = GrpcRpc<'r, FlakyClient, u32, u32>;
+
+    fn retry(max_retries: u32) -> Option<RetryPolicy> {
+        Some(RetryPolicy {
+            max_retries,
+            ..RetryPolicy::NONE
+        })
+    }
+
+    #[tokio::test]
+    async fn test_grpc_rpc() {
+        ATTEMPTS.store(0, Ordering::SeqCst);
+        let response = FlakyRpc::new(1, FlakyRpc::DEFAULT_UPSTREAM)
+            .with_ticket(false)
+            .with_retry(retry(2))
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(response.inner, 2);
+        assert_eq!(response.headers["x-attempt"], "3");
+
+        // Retry budget runs out
+        ATTEMPTS.store(0, Ordering::SeqCst);
+        let e = FlakyRpc::new(1, FlakyRpc::DEFAULT_UPSTREAM)
+            .with_ticket(false)
+            .with_retry(retry(1))
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(is_retryable(&e));
+        assert_eq!(ATTEMPTS.load(Ordering::SeqCst), 2);
+
+        // Not retryable
+        ATTEMPTS.store(0, Ordering::SeqCst);
+        let e = FlakyRpc::new(0, FlakyRpc::DEFAULT_UPSTREAM)
+            .with_ticket(false)
+            .with_retry(retry(2))
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(!is_retryable(&e));
+        assert_eq!(ATTEMPTS.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_grpc_rpc_macro() {
+        /// Builders defined by `grpc_rpc!` request the app upstream as gRPC,
+        /// carrying tickets by default
+        fn assert_rpc<'c, C: GrpcClientT<'c, Req, Resp>, Req, Resp>(
+            rpc: GrpcRpc<'c, C, Req, Resp>,
+        ) -> GrpcRpc<'c, C, Req, Resp> {
+            assert_eq!(rpc.upstream.str(), "https://app.bilibili.com");
+            assert_eq!(
+                rpc.headers
+                    .get(lib_utils::headers::HeaderKey::Custom("content-type"))
+                    .unwrap(),
+                "application/grpc"
+            );
+            assert_eq!(TicketKind::of(&rpc.upstream), Some(TicketKind::App));
+            assert!(rpc.ticket);
+            rpc
+        }
+
+        assert_rpc(PlayViewUniteRpc::new_default_upstream(Default::default()));
+        assert_rpc(DmSegMobileRpc::new_default_upstream(Default::default()));
+        assert_rpc(MainListRpc::new_default_upstream(Default::default()));
+        assert_rpc(SearchAllRpc::new_default_upstream(Default::default()));
+        assert_rpc(GetTicketRpc::new_default_upstream(Default::default()));
+
+        let rpc = assert_rpc(ViewRpc::new(
+            ViewReq {
+                aid: 170001,
+                ..Default::default()
+            },
+            ViewRpc::DEFAULT_UPSTREAM,
+        ))
+        .with_send_compressed(Some(CompressionEncoding::Gzip));
+        assert_eq!(rpc.request.aid, 170001);
+        assert!(rpc.send_compressed.is_some());
+        assert!(rpc.ticket);
+    }
+}
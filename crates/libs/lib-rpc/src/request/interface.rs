@@ -1,14 +1,21 @@
 use anyhow::Result;
-use http_02::{HeaderMap as HttpHeaderMap, Method as HttpMethod};
+use http::{HeaderMap as HttpHeaderMap, Method as HttpMethod};
 use url::Url;
 
-use std::{borrow::Cow, fmt::Debug as FmtDebug, future::Future};
+use std::{borrow::Cow, fmt::Debug as FmtDebug, future::Future, sync::Arc};
 
-use super::client::{
-    rest::{ReqBody, RestRequest, RestRequestBuilder},
-    utils::RawResponseExt,
+use super::{
+    client::{
+        pool::ProxyPool,
+        rest::{ReqBody, RestRequest, RestRequestBuilder},
+        retry::RetryPolicy,
+        timeout::Timeouts,
+        utils::RawResponseExt,
+    },
+    device::inject_app_device,
+    ticket::{inject_ticket, TicketKind},
 };
-use crate::utils::{ManagedHeaderMap, Upstream};
+use crate::utils::{ManagedHeaderMap, Upstream, UpstreamType};
 
 /// The trait definition for a RESTful RPC request.
 pub trait RpcT<'r> {
@@ -66,6 +73,15 @@ pub trait RpcBuilderT<'r>: Sized {
     /// Set the proxy for the RPC request
     fn with_proxy(self, proxy: Option<&'r str>) -> Self;
 
+    /// Set the proxy pool for the RPC request, overriding the proxy if set
+    fn with_proxy_pool(self, proxy_pool: Option<Arc<ProxyPool>>) -> Self;
+
+    /// Set the retry policy for the RPC request, only idempotent ones are retried
+    fn with_retry(self, retry: Option<RetryPolicy>) -> Self;
+
+    /// Set the timeouts for the RPC request, default ones if `None`
+    fn with_timeouts(self, timeouts: Option<Timeouts>) -> Self;
+
     /// Set the path for the RPC request
     fn with_path(self, _path: &'r str) -> Self {
         self
@@ -87,6 +103,12 @@ pub trait RpcBuilderT<'r>: Sized {
         self
     }
 
+    /// Set whether to carry `x-bili-ticket` if not given in headers, see
+    /// [ticket](super::ticket). Default to `true`.
+    fn with_ticket(self, _ticket: bool) -> Self {
+        self
+    }
+
     /// Execute the RPC request
     fn execute(self) -> impl Future<Output = Result<Self::Response>> + Send;
 }
@@ -100,6 +122,12 @@ pub struct GeneralRpc<'r> {
     path: &'r str,
     /// The query of the request.
     query: Option<Cow<'r, str>>,
+    /// The proxy of the request, also for requesting `x-bili-ticket`.
+    proxy: Option<&'r str>,
+    /// The headers of the request, default ones if `None`.
+    headers: Option<HttpHeaderMap>,
+    /// Whether to carry `x-bili-ticket`. Default to `true`.
+    ticket: bool,
     ///
     inner: RestRequestBuilder<'r>,
 }
@@ -117,6 +145,9 @@ impl<'r> RpcBuilderT<'r> for GeneralRpc<'r> {
             upstream: upstream.into(),
             path: "",
             query: None,
+            proxy: None,
+            headers: None,
+            ticket: true,
             inner: RestRequest::builder(),
         }
     }
@@ -135,10 +166,29 @@ impl<'r> RpcBuilderT<'r> for GeneralRpc<'r> {
 
     #[inline]
     fn with_proxy(mut self, proxy: Option<&'r str>) -> Self {
+        self.proxy = proxy;
         self.inner = self.inner.proxy(proxy);
         self
     }
 
+    #[inline]
+    fn with_proxy_pool(mut self, proxy_pool: Option<Arc<ProxyPool>>) -> Self {
+        self.inner = self.inner.proxy_pool(proxy_pool);
+        self
+    }
+
+    #[inline]
+    fn with_retry(mut self, retry: Option<RetryPolicy>) -> Self {
+        self.inner = self.inner.retry(retry);
+        self
+    }
+
+    #[inline]
+    fn with_timeouts(mut self, timeouts: Option<Timeouts>) -> Self {
+        self.inner = self.inner.timeouts(timeouts);
+        self
+    }
+
     #[inline]
     fn with_path(mut self, path: &'r str) -> Self {
         self.path = path;
@@ -153,14 +203,13 @@ impl<'r> RpcBuilderT<'r> for GeneralRpc<'r> {
 
     #[inline]
     fn with_headers(mut self, headers: Option<impl Into<HttpHeaderMap>>) -> Self {
-        let headers = headers.map(|h| h.into());
-        self.inner = self.inner.headers(headers);
+        self.headers = headers.map(|h| h.into());
         self
     }
 
     #[inline]
     fn with_headers_managed(mut self, headers: Option<impl Into<ManagedHeaderMap>>) -> Self {
-        self.inner = self.inner.headers(headers.map(|h| h.into().take_inner()));
+        self.headers = headers.map(|h| h.into().take_inner());
         self
     }
 
@@ -170,6 +219,12 @@ impl<'r> RpcBuilderT<'r> for GeneralRpc<'r> {
         self
     }
 
+    #[inline]
+    fn with_ticket(mut self, ticket: bool) -> Self {
+        self.ticket = ticket;
+        self
+    }
+
     #[tracing::instrument(level = "debug", name = "GeneralRpc.execute", skip_all, err)]
     async fn execute(self) -> Result<Self::Response> {
         let full_url = self.upstream.url().map(|mut u: Url| {
@@ -178,6 +233,33 @@ impl<'r> RpcBuilderT<'r> for GeneralRpc<'r> {
             u
         })?;
 
-        self.inner.build_with(full_url).execute(self.method).await
+        let app = matches!(self.upstream.u_type, UpstreamType::AppBilibiliCom);
+        let headers = if self.ticket || app {
+            let mut headers = match self.headers {
+                Some(headers) => ManagedHeaderMap::new_from_existing(headers, false, false),
+                None => ManagedHeaderMap::new(false, false),
+            };
+            if app {
+                inject_app_device(&mut headers, self.query.as_deref(), self.proxy).await;
+            }
+            if self.ticket {
+                inject_ticket(
+                    &mut headers,
+                    TicketKind::of(&self.upstream),
+                    self.query.as_deref(),
+                    self.proxy,
+                )
+                .await;
+            }
+            Some(headers.take_inner())
+        } else {
+            self.headers
+        };
+
+        self.inner
+            .headers(headers)
+            .build_with(full_url)
+            .execute(self.method)
+            .await
     }
 }
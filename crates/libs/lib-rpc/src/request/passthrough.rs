@@ -0,0 +1,188 @@
+use anyhow::{anyhow, Result};
+use http::{HeaderMap as HttpHeaderMap, Uri};
+
+use std::sync::Arc;
+
+use super::{
+    client::{
+        grpc::client::{execute_raw, execute_raw_with_pool, RawBody},
+        pool::ProxyPool,
+        retry::{is_idempotent_grpc, is_retryable, RetryPolicy},
+        timeout::Timeouts,
+    },
+    device::inject_device,
+    interface::RpcBuilderT,
+    ticket::{inject_ticket, TicketKind},
+};
+use crate::{
+    error::{Kind, RpcError},
+    model::response::GrpcRawResponse,
+    utils::{ManagedHeaderMap, Upstream},
+};
+use lib_utils::str_concat;
+
+#[derive(Debug)]
+/// RPC builder for passing through raw gRPC requests
+pub struct GrpcPassthroughRpc<'r> {
+    upstream: Upstream<'r>,
+    proxy: Option<&'r str>,
+    proxy_pool: Option<Arc<ProxyPool>>,
+    retry: Option<RetryPolicy>,
+    timeouts: Option<Timeouts>,
+    path: &'r str,
+    headers: HttpHeaderMap,
+    /// Whether to carry `x-bili-ticket`
+    ticket: bool,
+    request: RawBody,
+}
+
+impl<'r> RpcBuilderT<'r> for GrpcPassthroughRpc<'r> {
+    const DEFAULT_UPSTREAM: Upstream<'r> = Upstream::GRPC_DEFAULT;
+
+    type Request = RawBody;
+    type Response = GrpcRawResponse;
+
+    #[inline]
+    fn new(request: Self::Request, upstream: impl Into<Upstream<'r>>) -> Self {
+        Self {
+            upstream: upstream.into(),
+            proxy: None,
+            proxy_pool: None,
+            retry: None,
+            timeouts: None,
+            path: "",
+            headers: HttpHeaderMap::new(),
+            ticket: true,
+            request,
+        }
+    }
+
+    #[inline]
+    fn with_upstream(mut self, upstream: impl Into<Upstream<'r>>) -> Self {
+        self.upstream = upstream.into();
+        self
+    }
+
+    #[inline]
+    fn with_proxy(mut self, proxy: Option<&'r str>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    #[inline]
+    fn with_proxy_pool(mut self, proxy_pool: Option<Arc<ProxyPool>>) -> Self {
+        self.proxy_pool = proxy_pool;
+        self
+    }
+
+    #[inline]
+    fn with_retry(mut self, retry: Option<RetryPolicy>) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    #[inline]
+    fn with_timeouts(mut self, timeouts: Option<Timeouts>) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    #[inline]
+    fn with_path(mut self, path: &'r str) -> Self {
+        self.path = path;
+        self
+    }
+
+    #[inline]
+    fn with_headers(mut self, headers: Option<impl Into<HttpHeaderMap>>) -> Self {
+        if let Some(headers) = headers {
+            self.headers = headers.into();
+        }
+        self
+    }
+
+    #[inline]
+    fn with_headers_managed(mut self, headers: Option<impl Into<ManagedHeaderMap>>) -> Self {
+        if let Some(headers) = headers {
+            self.headers = headers.into().take_inner();
+        }
+        self
+    }
+
+    #[inline]
+    fn with_ticket(mut self, ticket: bool) -> Self {
+        self.ticket = ticket;
+        self
+    }
+
+    #[tracing::instrument(level = "debug", name = "GrpcPassthroughRpc.execute", skip(self), fields(path = self.path), err)]
+    async fn execute(mut self) -> Result<Self::Response> {
+        let uri = Uri::try_from(str_concat!(self.upstream.str(), self.path))
+            .map_err(|e| anyhow!(RpcError::PreRequest(Kind::from(e))))?;
+
+        let mut headers =
+            ManagedHeaderMap::new_from_existing(std::mem::take(&mut self.headers), true, false);
+        inject_device(&mut headers, self.proxy).await;
+        if self.ticket {
+            inject_ticket(
+                &mut headers,
+                TicketKind::of(&self.upstream),
+                None,
+                self.proxy,
+            )
+            .await;
+        }
+        self.headers = headers.take_inner();
+
+        // Unknown methods may not be idempotent, and a streaming body can't be
+        // sent again
+        let policy = self
+            .retry
+            .filter(|_| is_idempotent_grpc(self.path) && self.request.is_replayable())
+            .unwrap_or(RetryPolicy::NONE);
+        let timeouts = self.timeouts.unwrap_or_default();
+        // Keep the proxy picked first if not switching
+        let picked = match &self.proxy_pool {
+            Some(pool) if !policy.switch_proxy => Some(pool.pick()?),
+            _ => None,
+        };
+
+        let (proxy, proxy_pool) = (self.proxy, &self.proxy_pool);
+        // Taken by the first attempt if streaming, the only one then
+        let mut request = Some(self.request);
+        let (parts, body, trailers) = policy
+            .run(
+                || {
+                    let request = request
+                        .as_ref()
+                        .and_then(RawBody::try_clone)
+                        .or_else(|| request.take());
+                    let headers = self.headers.clone();
+                    let (uri, picked, timeouts) = (uri.clone(), picked.clone(), &timeouts);
+                    async move {
+                        let request =
+                            request.ok_or_else(|| anyhow!("Streaming body sent already"))?;
+                        match proxy_pool {
+                            Some(pool) => {
+                                execute_raw_with_pool(pool, picked, timeouts, uri, headers, request)
+                                    .await
+                            }
+                            None => execute_raw(proxy, timeouts, uri, headers, request).await,
+                        }
+                    }
+                },
+                |result| match result {
+                    Ok((parts, _, _)) => parts.status.is_server_error(),
+                    Err(e) => is_retryable(e),
+                },
+            )
+            .await?;
+
+        Ok(GrpcRawResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body,
+            trailers,
+        })
+    }
+}
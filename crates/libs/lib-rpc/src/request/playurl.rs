@@ -1,12 +1,13 @@
 use anyhow::Result;
-use http_02::HeaderMap as HttpHeaderMap;
-use lib_utils::error::ServerErrorExt;
+use http::HeaderMap as HttpHeaderMap;
+use lib_utils::headers::HeaderKey;
+
+use std::sync::{Arc, OnceLock};
 
 use super::{
-    bapis::app::playerunite::v1::{
-        player_client::PlayerClient, PlayViewUniteReply, PlayViewUniteReq,
-    },
-    client::grpc::{client_http02::GrpcClientExt, CompressionEncoding},
+    bapis::app::playerunite::v1::{PlayViewUniteReply, PlayViewUniteReq},
+    client::{pool::ProxyPool, retry::RetryPolicy, singleflight::Singleflight, timeout::Timeouts},
+    grpc::PlayViewUniteRpc,
     interface::RpcBuilderT,
 };
 use crate::{
@@ -14,12 +15,23 @@ use crate::{
     utils::{ManagedHeaderMap, Upstream},
 };
 
+type Flights = Singleflight<String, ResponseWrapper<PlayViewUniteReply>>;
+
+/// Playurl requests in flight, see [PlayurlRpc::execute]
+static FLIGHTS: OnceLock<Flights> = OnceLock::new();
+
+/// RPC builder for Playurl, executed as [PlayViewUniteRpc] with the request
+/// converted.
 #[derive(Debug)]
-/// RPC builder for Playurl
 pub struct PlayurlRpc<'r> {
     upstream: Upstream<'r>,
     proxy: Option<&'r str>,
+    proxy_pool: Option<Arc<ProxyPool>>,
+    retry: Option<RetryPolicy>,
+    timeouts: Option<Timeouts>,
     headers: ManagedHeaderMap,
+    /// Whether to carry `x-bili-ticket`
+    ticket: bool,
     request: PlayurlReq<'r>,
 }
 
@@ -34,7 +46,11 @@ impl<'r> RpcBuilderT<'r> for PlayurlRpc<'r> {
         Self {
             upstream: upstream.into(),
             proxy: None,
+            proxy_pool: None,
+            retry: None,
+            timeouts: None,
             headers: ManagedHeaderMap::new(true, true),
+            ticket: true,
             request,
         }
     }
@@ -51,6 +67,24 @@ impl<'r> RpcBuilderT<'r> for PlayurlRpc<'r> {
         self
     }
 
+    #[inline]
+    fn with_proxy_pool(mut self, proxy_pool: Option<Arc<ProxyPool>>) -> Self {
+        self.proxy_pool = proxy_pool;
+        self
+    }
+
+    #[inline]
+    fn with_retry(mut self, retry: Option<RetryPolicy>) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    #[inline]
+    fn with_timeouts(mut self, timeouts: Option<Timeouts>) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
     #[inline]
     fn with_headers(mut self, headers: Option<impl Into<HttpHeaderMap>>) -> Self {
         if let Some(headers) = headers {
@@ -67,26 +101,123 @@ impl<'r> RpcBuilderT<'r> for PlayurlRpc<'r> {
         self
     }
 
+    #[inline]
+    fn with_ticket(mut self, ticket: bool) -> Self {
+        self.ticket = ticket;
+        self
+    }
+
+    /// Concurrent identical requests, by [PlayurlReq::normalized_key] with the
+    /// same upstream, proxy and user, are coalesced into one upstream call.
     #[tracing::instrument(level = "debug", name = "PlayurlRpc.execute", err)]
     async fn execute(self) -> Result<ResponseWrapper<PlayViewUniteReply>> {
-        let request: PlayViewUniteReq = self.request.try_into()?;
+        let key = self.flight_key();
+        FLIGHTS
+            .get_or_init(Flights::new)
+            .run(key, || self.execute_once())
+            .await
+    }
+}
+
+impl PlayurlRpc<'_> {
+    #[inline]
+    fn flight_key(&self) -> String {
+        let proxy = match &self.proxy_pool {
+            Some(pool) => pool.name(),
+            None => self.proxy.unwrap_or_default(),
+        };
+        let authorization = self
+            .headers
+            .get(HeaderKey::Authorization)
+            .unwrap_or_default();
 
-        let grpc_client = GrpcClientExt::new(self.proxy, self.headers);
+        format!(
+            "{}|{}|{}|{}",
+            self.upstream.str(),
+            proxy,
+            authorization,
+            self.request.normalized_key()
+        )
+    }
 
-        let mut client = PlayerClient::with_origin(grpc_client, self.upstream.uri()?)
-            .accept_compressed(CompressionEncoding::Gzip);
-            // .send_compressed(CompressionEncoding::Gzip);
+    async fn execute_once(self) -> Result<ResponseWrapper<PlayViewUniteReply>> {
+        let request: PlayViewUniteReq = self.request.try_into()?;
 
-        client
-            .play_view_unite(request)
+        PlayViewUniteRpc::new(request, self.upstream)
+            .with_proxy(self.proxy)
+            .with_proxy_pool(self.proxy_pool)
+            .with_retry(self.retry)
+            .with_timeouts(self.timeouts)
+            .with_headers_managed(Some(self.headers))
+            .with_ticket(self.ticket)
+            .execute()
             .await
-            .map(|r| {
-                let (headers, inner, _) = r.into_parts();
-                ResponseWrapper {
-                    inner,
-                    headers: headers.into_headers(),
-                }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        borrow::Cow,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::model::playurl::{VideoVod, VideoVodExt};
+
+    fn request(aid: i32, bvid: Option<&str>) -> PlayurlReq<'_> {
+        PlayurlReq {
+            vod: VideoVod {
+                aid,
+                cid: 114514,
+                qn: 80,
+                ..Default::default()
+            },
+            vod_ext: VideoVodExt {
+                bvid: bvid.map(Cow::Borrowed),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn rpc<'r>(request: PlayurlReq<'r>, authorization: &str) -> PlayurlRpc<'r> {
+        let mut headers = HttpHeaderMap::new();
+        headers.insert("authorization", authorization.parse().unwrap());
+        PlayurlRpc::new(request, PlayurlRpc::DEFAULT_UPSTREAM).with_headers(Some(headers))
+    }
+
+    #[tokio::test]
+    async fn test_flight_key() {
+        let bvid = lib_utils::av2bv!(170001);
+        let a = rpc(request(170001, None), "identify_v1 a");
+        let b = rpc(request(0, Some(&bvid)), "identify_v1 a");
+        assert_eq!(a.flight_key(), b.flight_key());
+        assert_ne!(
+            a.flight_key(),
+            rpc(request(170001, None), "identify_v1 b").flight_key()
+        );
+        assert_ne!(
+            a.flight_key(),
+            rpc(request(170002, None), "identify_v1 a").flight_key()
+        );
+
+        // Identical calls are coalesced by the key
+        let flights = Flights::new();
+        let count = &AtomicUsize::new(0);
+        let call = move || async move {
+            count.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok(ResponseWrapper {
+                inner: PlayViewUniteReply::default(),
+                headers: HttpHeaderMap::new(),
             })
-            .map_err(|e| ServerErrorExt::from(e).into())
+        };
+        let (a, b) = tokio::join!(
+            flights.run(a.flight_key(), call),
+            flights.run(b.flight_key(), call)
+        );
+        assert!(a.is_ok() && b.is_ok());
+        assert_eq!(count.load(Ordering::SeqCst), 1);
     }
 }
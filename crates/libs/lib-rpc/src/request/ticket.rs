@@ -0,0 +1,452 @@
+//! `x-bili-ticket`, requested with signed payload and cached until expired,
+//! one for each account as the device profile, see [account_of].
+//!
+//! Requests without it are more likely to be risk controlled, so it's carried
+//! by outgoing requests automatically, see
+//! [RpcBuilderT::with_ticket](super::interface::RpcBuilderT::with_ticket).
+
+use anyhow::{anyhow, Result};
+use http::Method as HttpMethod;
+use lib_core::business::fingerprint::profile::device_profiles;
+use lib_utils::{
+    headers::{BiliHeaderT, HeaderKey},
+    now,
+    sign::BiliTicket,
+    url::QueryBuilder,
+};
+use prost::Message;
+
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    sync::{Arc, Mutex, OnceLock, PoisonError, RwLock},
+    time::{Duration, Instant},
+};
+
+use super::{
+    bapis::ticket::v1::GetTicketRequest,
+    client::singleflight::Singleflight,
+    device::account_of,
+    grpc::GetTicketRpc,
+    interface::{GeneralRpc, RpcBuilderT},
+};
+use crate::{
+    error::{Kind, RpcError},
+    utils::{ManagedHeaderMap, Upstream, UpstreamType},
+};
+
+/// Tickets are requested again this long before expired, in seconds
+const EXPIRE_MARGIN: u64 = 60;
+
+/// Backoff after the first failure of requesting tickets, doubled for each
+/// one in a row
+const FAILURE_BACKOFF_MIN: Duration = Duration::from_secs(5);
+/// Max backoff after failures of requesting tickets
+const FAILURE_BACKOFF_MAX: Duration = Duration::from_secs(5 * 60);
+
+/// Max count of accounts tickets of each kind are cached for, the oldest
+/// ones are removed first
+const MAX_TICKET_ACCOUNTS: usize = 10000;
+
+/// App tickets cached, see [ticket]
+static APP_TICKETS: OnceLock<TicketCaches> = OnceLock::new();
+/// Web tickets cached, see [ticket]
+static WEB_TICKETS: OnceLock<TicketCaches> = OnceLock::new();
+
+/// Kind of `x-bili-ticket`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TicketKind {
+    /// Requested with `bilibili.ticket.v1.Ticket/GetTicket`
+    App,
+    /// Requested with `/bapis/bilibili.api.ticket.v1.Ticket/GenWebTicket`
+    Web,
+}
+
+impl TicketKind {
+    /// Kind of ticket carried by requests to given upstream, `None` for custom
+    /// ones, which tickets should never be sent to.
+    #[inline]
+    pub fn of(upstream: &Upstream<'_>) -> Option<Self> {
+        match upstream.u_type {
+            UpstreamType::ApiBilibiliCom => Some(Self::Web),
+            UpstreamType::AppBilibiliCom | UpstreamType::GrpcBiliapiNet => Some(Self::App),
+            UpstreamType::Custom => None,
+        }
+    }
+
+    #[inline]
+    fn caches(self) -> &'static TicketCaches {
+        match self {
+            Self::App => APP_TICKETS.get_or_init(TicketCaches::default),
+            Self::Web => WEB_TICKETS.get_or_init(TicketCaches::default),
+        }
+    }
+
+    #[inline]
+    async fn fetch(self, account: &str, proxy: Option<&str>) -> Result<Ticket> {
+        match self {
+            Self::App => fetch_app_ticket(account, proxy).await,
+            Self::Web => fetch_web_ticket(proxy).await,
+        }
+    }
+}
+
+/// `x-bili-ticket` with its expiration
+#[derive(Debug)]
+pub struct Ticket {
+    pub ticket: String,
+    /// Unix timestamp in seconds, `created_at` + `ttl`
+    pub expires_at: u64,
+}
+
+impl Ticket {
+    fn new(ticket: String, created_at: i64, ttl: i64) -> Result<Self> {
+        if ticket.is_empty() {
+            return Err(anyhow!(RpcError::Response(Kind::Any(anyhow!(
+                "Empty ticket in response"
+            )))));
+        }
+
+        Ok(Self {
+            ticket,
+            expires_at: u64::try_from(created_at.saturating_add(ttl)).unwrap_or_default(),
+        })
+    }
+
+    #[inline]
+    fn is_expired(&self) -> bool {
+        now!().as_secs().saturating_add(EXPIRE_MARGIN) >= self.expires_at
+    }
+}
+
+/// Failures of requesting tickets in a row
+#[derive(Debug, Clone, Copy)]
+struct Failure {
+    backoff: Duration,
+    /// Not requested again until then
+    until: Instant,
+}
+
+#[derive(Debug, Default)]
+struct TicketCache {
+    ticket: RwLock<Option<Arc<Ticket>>>,
+    /// Coalesce concurrent requesting
+    fetching: Singleflight<(), Arc<Ticket>>,
+    /// Last failure, so that failing upstream is not requested for every
+    /// outgoing request
+    failure: Mutex<Option<Failure>>,
+}
+
+impl TicketCache {
+    #[inline]
+    fn cached(&self) -> Option<Arc<Ticket>> {
+        self.ticket
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+            .filter(|ticket| !ticket.is_expired())
+    }
+
+    /// The ticket cached, or requested with `fetch` if not backing off from
+    /// failures.
+    async fn get<Fut>(&self, kind: TicketKind, fetch: impl FnOnce() -> Fut) -> Result<Arc<Ticket>>
+    where
+        Fut: Future<Output = Result<Ticket>>,
+    {
+        if let Some(ticket) = self.cached() {
+            return Ok(ticket);
+        }
+
+        let failure = *self.failure.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(failure) = failure.filter(|failure| Instant::now() < failure.until) {
+            return Err(anyhow!(
+                "Backing off from requesting {:?} ticket for {:?}",
+                kind,
+                failure.backoff
+            ));
+        }
+
+        self.fetching
+            .run((), || async {
+                let result = fetch().await;
+
+                let mut failure = self.failure.lock().unwrap_or_else(PoisonError::into_inner);
+                let ticket = match result {
+                    Ok(ticket) => {
+                        *failure = None;
+                        Arc::new(ticket)
+                    }
+                    Err(e) => {
+                        let backoff = failure.map_or(FAILURE_BACKOFF_MIN, |failure| {
+                            (failure.backoff * 2).min(FAILURE_BACKOFF_MAX)
+                        });
+                        *failure = Some(Failure {
+                            backoff,
+                            until: Instant::now() + backoff,
+                        });
+                        return Err(e);
+                    }
+                };
+                drop(failure);
+
+                *self.ticket.write().unwrap_or_else(PoisonError::into_inner) = Some(ticket.clone());
+                tracing::info!(
+                    "{:?} ticket refreshed, expires at {}",
+                    kind,
+                    ticket.expires_at
+                );
+                Ok(ticket)
+            })
+            .await
+    }
+}
+
+/// Ticket caches of one kind, by account
+#[derive(Debug, Default)]
+struct TicketCaches {
+    caches: Mutex<(HashMap<String, Arc<TicketCache>>, VecDeque<String>)>,
+}
+
+impl TicketCaches {
+    /// Cache of the account, the oldest one removed if too many.
+    fn of(&self, account: &str) -> Arc<TicketCache> {
+        let mut guard = self.caches.lock().unwrap_or_else(PoisonError::into_inner);
+        let (caches, order) = &mut *guard;
+        if let Some(cache) = caches.get(account) {
+            return cache.clone();
+        }
+
+        let cache = Arc::new(TicketCache::default());
+        caches.insert(account.to_owned(), cache.clone());
+        order.push_back(account.to_owned());
+        while order.len() > MAX_TICKET_ACCOUNTS {
+            if let Some(oldest) = order.pop_front() {
+                caches.remove(&oldest);
+            }
+        }
+        cache
+    }
+}
+
+/// Get the ticket of the account cached, requested again if missing or
+/// expired, as the device of the account, see [account_of].
+///
+/// Not requested again for a while after failed, with the backoff doubled
+/// for each failure in a row.
+#[tracing::instrument(level = "debug", name = "RpcRequest.ticket", err)]
+pub async fn ticket(kind: TicketKind, account: &str, proxy: Option<&str>) -> Result<Arc<Ticket>> {
+    kind.caches()
+        .of(account)
+        .get(kind, || kind.fetch(account, proxy))
+        .await
+}
+
+/// Set `x-bili-ticket` of given kind for the account of the request if not
+/// given in headers, skipped if no kind of ticket should be carried, see
+/// [TicketKind::of].
+///
+/// Requests go on without it if failed to get one.
+pub(crate) async fn inject_ticket(
+    headers: &mut ManagedHeaderMap,
+    kind: Option<TicketKind>,
+    query: Option<&str>,
+    proxy: Option<&str>,
+) {
+    let Some(kind) = kind else {
+        return;
+    };
+    if headers.contains_key(HeaderKey::BiliTicket) {
+        return;
+    }
+
+    match ticket(kind, &account_of(headers, query, proxy), proxy).await {
+        Ok(ticket) => {
+            headers.set_ticket(Some(&ticket.ticket));
+        }
+        Err(e) => tracing::warn!("Failed to get {:?} ticket, go on without it: {:?}", kind, e),
+    }
+}
+
+#[tracing::instrument(level = "debug", name = "RpcRequest.fetch_app_ticket", err)]
+async fn fetch_app_ticket(account: &str, proxy: Option<&str>) -> Result<Ticket> {
+    // Requested as the device of the account, presented with the ticket later
+    let profile = device_profiles().get_or_create(account).await;
+    let device = profile.device();
+
+    // Fingerprint is optional, while the keys should be signed anyway
+    let (fingerprint, exbadbasket) = (Vec::new(), Vec::new());
+    let payload = BiliTicket::App {
+        device_info: device.encode_to_vec(),
+        fingerprint: fingerprint.clone(),
+        exbadbasket: exbadbasket.clone(),
+    };
+    let request = GetTicketRequest {
+        context: HashMap::from([
+            ("x-exbadbasket".to_owned(), exbadbasket),
+            ("x-fingerprint".to_owned(), fingerprint),
+        ]),
+        key_id: payload.key_id().to_owned(),
+        sign: payload.sign(),
+        token: String::new(),
+    };
+
+    let mut headers = ManagedHeaderMap::new(true, true);
+    profile.set_grpc_headers(&mut headers, None);
+
+    let reply = GetTicketRpc::new_default_upstream(request)
+        .with_proxy(proxy)
+        .with_headers_managed(Some(headers))
+        .with_ticket(false)
+        .execute()
+        .await?
+        .inner;
+
+    Ticket::new(reply.ticket, reply.created_at, reply.ttl)
+}
+
+#[tracing::instrument(level = "debug", name = "RpcRequest.fetch_web_ticket", err)]
+async fn fetch_web_ticket(proxy: Option<&str>) -> Result<Ticket> {
+    let ts = now!().as_secs();
+    let payload = BiliTicket::Web { ts };
+    let key_id = payload.key_id();
+
+    let query = QueryBuilder::default()
+        .add_param("key_id", key_id)
+        .add_param("hexsign", payload.sign_hex())
+        .add_param("context[ts]", ts.to_string())
+        .add_param("csrf", "")
+        .build()?;
+
+    let data = GeneralRpc::new((), Upstream::API_DEFAULT)
+        .with_method(HttpMethod::POST)
+        .with_path("/bapis/bilibili.api.ticket.v1.Ticket/GenWebTicket")
+        .with_query(Some(query.into()))
+        .with_proxy(proxy)
+        .with_ticket(false)
+        .execute()
+        .await?
+        .bili_json()
+        .await?
+        .into_data()
+        .unwrap_or_default();
+
+    Ticket::new(
+        data["ticket"].as_str().unwrap_or_default().to_owned(),
+        data["created_at"].as_i64().unwrap_or_default(),
+        data["ttl"].as_i64().unwrap_or_default(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_ticket() {
+        let now = now!().as_secs() as i64;
+
+        let ticket = Ticket::new("ticket".to_owned(), now, 259200).unwrap();
+        assert_eq!(ticket.expires_at, now as u64 + 259200);
+        assert!(!ticket.is_expired());
+
+        // Expired, or about to be
+        assert!(Ticket::new("ticket".to_owned(), now - 259200, 259200)
+            .unwrap()
+            .is_expired());
+        assert!(Ticket::new("ticket".to_owned(), now, EXPIRE_MARGIN as i64)
+            .unwrap()
+            .is_expired());
+        assert!(Ticket::new("ticket".to_owned(), -1, 0)
+            .unwrap()
+            .is_expired());
+
+        assert!(Ticket::new(String::new(), now, 259200).is_err());
+    }
+
+    #[test]
+    fn test_ticket_kind() {
+        assert_eq!(
+            TicketKind::of(&Upstream::API_DEFAULT),
+            Some(TicketKind::Web)
+        );
+        assert_eq!(
+            TicketKind::of(&Upstream::APP_DEFAULT),
+            Some(TicketKind::App)
+        );
+        assert_eq!(
+            TicketKind::of(&Upstream::new_custom("https://example.com")),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_inject_ticket() {
+        // Never requested, or the one given overwritten
+        let mut headers = ManagedHeaderMap::new(false, false);
+        headers.set_ticket(Some("given"));
+        inject_ticket(&mut headers, Some(TicketKind::App), None, None).await;
+        assert_eq!(headers.get(HeaderKey::BiliTicket).unwrap(), "given");
+
+        let mut headers = ManagedHeaderMap::new(false, false);
+        inject_ticket(&mut headers, None, None, None).await;
+        assert!(!headers.contains_key(HeaderKey::BiliTicket));
+    }
+
+    #[test]
+    fn test_ticket_caches() {
+        let caches = TicketCaches::default();
+        let cache = caches.of("mid:1");
+        assert!(Arc::ptr_eq(&cache, &caches.of("mid:1")));
+        assert!(!Arc::ptr_eq(&cache, &caches.of("mid:2")));
+
+        // The oldest removed if too many
+        for mid in 3..=MAX_TICKET_ACCOUNTS + 1 {
+            caches.of(&format!("mid:{}", mid));
+        }
+        assert!(!Arc::ptr_eq(&cache, &caches.of("mid:1")));
+        assert_eq!(caches.caches.lock().unwrap().0.len(), MAX_TICKET_ACCOUNTS);
+    }
+
+    #[tokio::test]
+    async fn test_ticket_cache_backoff() {
+        let cache = TicketCache::default();
+        let fetched = &AtomicUsize::new(0);
+        let failing = || async move {
+            fetched.fetch_add(1, Ordering::SeqCst);
+            Err(anyhow!("failed"))
+        };
+
+        assert!(cache.get(TicketKind::App, failing).await.is_err());
+        // Backing off, not requested
+        assert!(cache.get(TicketKind::App, failing).await.is_err());
+        assert_eq!(fetched.load(Ordering::SeqCst), 1);
+
+        let backoff =
+            |cache: &TicketCache| cache.failure.lock().unwrap().map(|failure| failure.backoff);
+        assert_eq!(backoff(&cache), Some(FAILURE_BACKOFF_MIN));
+
+        // Doubled once failed again after backoff
+        cache.failure.lock().unwrap().as_mut().unwrap().until = Instant::now();
+        assert!(cache.get(TicketKind::App, failing).await.is_err());
+        assert_eq!(fetched.load(Ordering::SeqCst), 2);
+        assert_eq!(backoff(&cache), Some(FAILURE_BACKOFF_MIN * 2));
+
+        // Cleared once succeeded
+        cache.failure.lock().unwrap().as_mut().unwrap().until = Instant::now();
+        let ticket = cache
+            .get(TicketKind::App, || async {
+                Ticket::new("ticket".to_owned(), now!().as_secs() as i64, 259200)
+            })
+            .await
+            .unwrap();
+        assert_eq!(ticket.ticket, "ticket");
+        assert_eq!(backoff(&cache), None);
+        assert!(Arc::ptr_eq(
+            &ticket,
+            &cache.get(TicketKind::App, failing).await.unwrap()
+        ));
+        assert_eq!(fetched.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,410 @@
+//! WBI keys for signing Web API requests, fetched from `/x/web-interface/nav`
+//! and rotated daily by upstream.
+
+use anyhow::{anyhow, Result};
+use lib_utils::{
+    error::{ServerError, ServerErrorExt},
+    headers::{HeaderKey, ManagedHeaderMap},
+    sign::{Signer, Wbi},
+    url::QueryBuilder,
+};
+
+use std::{
+    borrow::Cow,
+    future::Future,
+    sync::{Arc, OnceLock, PoisonError, RwLock},
+    time::{Duration, Instant},
+};
+
+use super::{
+    client::singleflight::Singleflight,
+    interface::{GeneralRpc, RpcBuilderT},
+};
+use crate::{
+    error::{Kind, RpcError},
+    utils::Upstream,
+};
+
+/// Max age of WBI keys before fetched again
+const WBI_KEYS_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+/// Min age of WBI keys before fetched again on sign errors, so that errors not
+/// caused by stale keys never flood upstream with fetching
+const WBI_KEYS_MIN_AGE: Duration = Duration::from_secs(60);
+
+/// WBI keys cached, see [wbi_keys]
+static WBI_KEYS: OnceLock<WbiKeyStore> = OnceLock::new();
+
+/// WBI keys with the mixin key calculated
+#[derive(Debug)]
+pub struct WbiKeys {
+    pub img_key: String,
+    pub sub_key: String,
+    pub mixin_key: String,
+    fetched_at: Instant,
+}
+
+impl WbiKeys {
+    #[inline]
+    pub fn new(img_key: String, sub_key: String) -> Result<Self> {
+        let mixin_key = Wbi::gen_mixin_key(&img_key, &sub_key)?;
+        Ok(Self {
+            img_key,
+            sub_key,
+            mixin_key,
+            fetched_at: Instant::now(),
+        })
+    }
+
+    /// Signer with the cached mixin key.
+    #[inline]
+    pub fn signer(&self) -> Signer<'_> {
+        Signer::WbiMixinKey {
+            mixin_key: &self.mixin_key,
+        }
+    }
+
+    #[inline]
+    fn is_expired(&self) -> bool {
+        self.fetched_at.elapsed() >= WBI_KEYS_MAX_AGE
+    }
+
+    #[inline]
+    fn is_fresh(&self) -> bool {
+        self.fetched_at.elapsed() < WBI_KEYS_MIN_AGE
+    }
+
+    /// Parse keys from `data` of `/x/web-interface/nav`, which is returned
+    /// even if not logged in.
+    fn from_nav(nav: &serde_json::Value) -> Result<Self> {
+        let wbi_img = &nav["data"]["wbi_img"];
+        let key_of = |field: &str| {
+            wbi_img[field]
+                .as_str()
+                .and_then(key_from_url)
+                .map(str::to_owned)
+                .ok_or_else(|| {
+                    anyhow!(RpcError::Response(Kind::Any(anyhow!(
+                        "Invalid [wbi_img.{}] in nav response",
+                        field
+                    ))))
+                })
+        };
+
+        Self::new(key_of("img_url")?, key_of("sub_url")?)
+    }
+}
+
+/// Key in WBI image url, i.e. the file stem, like
+/// `https://i0.hdslb.com/bfs/wbi/{key}.png`.
+#[inline]
+fn key_from_url(url: &str) -> Option<&str> {
+    url.rsplit('/')
+        .next()
+        .and_then(|name| name.split('.').next())
+        .filter(|key| !key.is_empty())
+}
+
+/// Whether the error is caused by the WBI keys being stale.
+#[inline]
+fn is_sign_error(e: &anyhow::Error) -> bool {
+    matches!(
+        ServerErrorExt::from_anyhow_ref(e),
+        Some(ServerErrorExt::Server(
+            ServerError::RpcReqApiSignInvalid | ServerError::RpcReqAccessDenied
+        ))
+    )
+}
+
+#[derive(Debug, Default)]
+struct WbiKeyStore {
+    keys: RwLock<Option<Arc<WbiKeys>>>,
+    /// Coalesce concurrent fetching
+    fetching: Singleflight<(), Arc<WbiKeys>>,
+}
+
+impl WbiKeyStore {
+    #[inline]
+    fn cached(&self) -> Option<Arc<WbiKeys>> {
+        self.keys
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    async fn refresh<Fut>(&self, fetch: impl FnOnce() -> Fut) -> Result<Arc<WbiKeys>>
+    where
+        Fut: Future<Output = Result<WbiKeys>>,
+    {
+        self.fetching
+            .run((), || async {
+                let keys = Arc::new(fetch().await?);
+                *self.keys.write().unwrap_or_else(PoisonError::into_inner) = Some(keys.clone());
+                tracing::info!("WBI keys refreshed: [{}], [{}]", keys.img_key, keys.sub_key);
+                Ok(keys)
+            })
+            .await
+    }
+
+    /// See [wbi_keys].
+    async fn keys<Fut>(&self, fetch: impl FnOnce() -> Fut) -> Result<Arc<WbiKeys>>
+    where
+        Fut: Future<Output = Result<WbiKeys>>,
+    {
+        match self.cached() {
+            Some(keys) if !keys.is_expired() => Ok(keys),
+            Some(keys) => match self.refresh(fetch).await {
+                Ok(keys) => Ok(keys),
+                Err(e) => {
+                    tracing::warn!("Failed to refresh expired WBI keys: {:?}", e);
+                    Ok(keys)
+                }
+            },
+            None => self.refresh(fetch).await,
+        }
+    }
+
+    /// See [with_wbi_keys].
+    async fn with_keys<T, FetchFut, F, Fut>(&self, fetch: impl Fn() -> FetchFut, f: F) -> Result<T>
+    where
+        FetchFut: Future<Output = Result<WbiKeys>>,
+        F: Fn(Arc<WbiKeys>) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let keys = self.keys(&fetch).await?;
+
+        let e = match f(keys.clone()).await {
+            Ok(result) => return Ok(result),
+            Err(e) if is_sign_error(&e) => e,
+            Err(e) => return Err(e),
+        };
+
+        let keys = match self.cached() {
+            // Refreshed by others
+            Some(cached) if !Arc::ptr_eq(&cached, &keys) => cached,
+            // Fetched just now, not the cause
+            _ if keys.is_fresh() => {
+                tracing::warn!("Sign error with WBI keys fetched just now: {:?}", e);
+                return Err(e);
+            }
+            _ => {
+                tracing::warn!("Sign error with WBI keys, refreshing: {:?}", e);
+                self.refresh(&fetch).await?
+            }
+        };
+
+        f(keys).await
+    }
+}
+
+#[inline]
+fn store() -> &'static WbiKeyStore {
+    WBI_KEYS.get_or_init(WbiKeyStore::default)
+}
+
+/// Fetch WBI keys from upstream.
+#[tracing::instrument(level = "debug", name = "RpcRequest.fetch_wbi_keys", err)]
+async fn fetch_wbi_keys(proxy: Option<&str>) -> Result<WbiKeys> {
+    let mut headers = ManagedHeaderMap::new(false, false);
+    headers.insert_from_static(HeaderKey::Custom("referer"), "https://www.bilibili.com/");
+
+    let nav = GeneralRpc::new((), Upstream::API_DEFAULT)
+        .with_path("/x/web-interface/nav")
+        .with_proxy(proxy)
+        .with_headers_managed(Some(headers))
+        .execute()
+        .await?
+        .json::<serde_json::Value>()
+        .await?
+        .into_data()
+        .unwrap_or_default();
+
+    WbiKeys::from_nav(&nav)
+}
+
+/// Get WBI keys cached, fetched again if missing or expired.
+///
+/// Expired keys are still returned if failed to fetch new ones.
+#[tracing::instrument(level = "debug", name = "RpcRequest.wbi_keys", err)]
+pub async fn wbi_keys(proxy: Option<&str>) -> Result<Arc<WbiKeys>> {
+    store().keys(|| fetch_wbi_keys(proxy)).await
+}
+
+/// Run `f` with WBI keys, run again with keys fetched again once if failed
+/// with sign error, unless the keys were fetched within a minute.
+///
+/// Use [execute_wbi_signed] for requesting Web APIs, unless the request is
+/// built differently.
+pub async fn with_wbi_keys<T, F, Fut>(proxy: Option<&str>, f: F) -> Result<T>
+where
+    F: Fn(Arc<WbiKeys>) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    store().with_keys(|| fetch_wbi_keys(proxy), f).await
+}
+
+/// Request Web API of given path with `params` WBI signed, return `data` of
+/// the response.
+///
+/// Signed again with keys fetched again once if upstream rejects the sign,
+/// see [with_wbi_keys].
+#[tracing::instrument(
+    level = "debug",
+    name = "RpcRequest.execute_wbi_signed",
+    skip(params),
+    err
+)]
+pub async fn execute_wbi_signed(
+    path: &str,
+    params: Vec<(&str, Cow<'_, str>)>,
+    proxy: Option<&str>,
+) -> Result<serde_json::Value> {
+    with_wbi_keys(proxy, |keys| {
+        let params = params.clone();
+        async move {
+            let query = QueryBuilder::new(params)
+                .with_signer(keys.signer())
+                .build()?;
+
+            Ok(GeneralRpc::new((), Upstream::API_DEFAULT)
+                .with_path(path)
+                .with_query(Some(query.into()))
+                .with_proxy(proxy)
+                .execute()
+                .await?
+                .bili_json()
+                .await?
+                .into_data()
+                .unwrap_or_default())
+        }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    const IMG_KEY: &str = "7cd084941338484aae1ad9425b84077c";
+    const SUB_KEY: &str = "4932caff0ff746eab6f01bf08b70ac45";
+
+    #[test]
+    fn test_key_from_url() {
+        assert_eq!(
+            key_from_url("https://i0.hdslb.com/bfs/wbi/7cd084941338484aae1ad9425b84077c.png"),
+            Some(IMG_KEY)
+        );
+        assert_eq!(key_from_url(IMG_KEY), Some(IMG_KEY));
+        assert_eq!(key_from_url("https://i0.hdslb.com/bfs/wbi/"), None);
+        assert_eq!(key_from_url(""), None);
+    }
+
+    #[test]
+    fn test_wbi_keys_from_nav() {
+        // Not logged in
+        let nav = serde_json::json!({
+            "code": -101,
+            "message": "账号未登录",
+            "ttl": 1,
+            "data": {
+                "isLogin": false,
+                "wbi_img": {
+                    "img_url": "https://i0.hdslb.com/bfs/wbi/7cd084941338484aae1ad9425b84077c.png",
+                    "sub_url": "https://i0.hdslb.com/bfs/wbi/4932caff0ff746eab6f01bf08b70ac45.png"
+                }
+            }
+        });
+        let keys = WbiKeys::from_nav(&nav).unwrap();
+        assert_eq!(keys.img_key, IMG_KEY);
+        assert_eq!(keys.sub_key, SUB_KEY);
+        assert_eq!(
+            keys.mixin_key,
+            Wbi::gen_mixin_key(IMG_KEY, SUB_KEY).unwrap()
+        );
+        assert!(!keys.is_expired());
+
+        let nav = serde_json::json!({
+            "code": 0,
+            "message": "0",
+            "ttl": 1,
+            "data": {
+                "isLogin": true,
+                "mid": 114514,
+                "wbi_img": {
+                    "img_url": "https://i0.hdslb.com/bfs/wbi/7cd084941338484aae1ad9425b84077c.png",
+                    "sub_url": ""
+                }
+            }
+        });
+        assert!(WbiKeys::from_nav(&nav).is_err());
+        assert!(WbiKeys::from_nav(&serde_json::Value::Null).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_with_keys() {
+        let store = WbiKeyStore::default();
+        let fetched = &AtomicUsize::new(0);
+        let fetch = || async move {
+            fetched.fetch_add(1, Ordering::SeqCst);
+            let mut keys = WbiKeys::new(IMG_KEY.to_owned(), SUB_KEY.to_owned())?;
+            keys.fetched_at -= WBI_KEYS_MIN_AGE;
+            Ok(keys)
+        };
+
+        // Refreshed and retried once on sign error
+        let called = &AtomicUsize::new(0);
+        let result = store
+            .with_keys(fetch, |_| async move {
+                match called.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(anyhow!(ServerError::RpcReqApiSignInvalid)),
+                    n => Ok(n),
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 1);
+        assert_eq!(fetched.load(Ordering::SeqCst), 2);
+
+        // Never more than once
+        called.store(0, Ordering::SeqCst);
+        let result: Result<()> = store
+            .with_keys(fetch, |_| async move {
+                called.fetch_add(1, Ordering::SeqCst);
+                Err(anyhow!(ServerError::RpcReqAccessDenied))
+            })
+            .await;
+        assert!(is_sign_error(&result.unwrap_err()));
+        assert_eq!(called.load(Ordering::SeqCst), 2);
+        assert_eq!(fetched.load(Ordering::SeqCst), 3);
+
+        // Not retried on other errors, with keys cached
+        called.store(0, Ordering::SeqCst);
+        let result: Result<()> = store
+            .with_keys(fetch, |_| async move {
+                called.fetch_add(1, Ordering::SeqCst);
+                Err(anyhow!(ServerError::RpcReqRiskControl))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(called.load(Ordering::SeqCst), 1);
+        assert_eq!(fetched.load(Ordering::SeqCst), 3);
+
+        // Not refreshed if fetched just now
+        let store = WbiKeyStore::default();
+        let fetched = &AtomicUsize::new(0);
+        let fetch = || async move {
+            fetched.fetch_add(1, Ordering::SeqCst);
+            WbiKeys::new(IMG_KEY.to_owned(), SUB_KEY.to_owned())
+        };
+        called.store(0, Ordering::SeqCst);
+        let result: Result<()> = store
+            .with_keys(fetch, |_| async move {
+                called.fetch_add(1, Ordering::SeqCst);
+                Err(anyhow!(ServerError::RpcReqApiSignInvalid))
+            })
+            .await;
+        assert!(is_sign_error(&result.unwrap_err()));
+        assert_eq!(called.load(Ordering::SeqCst), 1);
+        assert_eq!(fetched.load(Ordering::SeqCst), 1);
+    }
+}
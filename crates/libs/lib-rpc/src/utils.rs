@@ -1,5 +1,5 @@
 use anyhow::{anyhow, Result};
-use http_02::Uri;
+use http::Uri;
 use url::Url;
 
 use std::borrow::Cow;
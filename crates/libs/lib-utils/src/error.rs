@@ -1,9 +1,14 @@
+mod catalog;
+mod i18n;
+
 use axum::response::{IntoResponse, Response as AxumResponse};
 
-use std::{borrow::Cow, error::Error as StdError};
+use std::{borrow::Cow, error::Error as StdError, sync::OnceLock};
 
 use crate::model::response::GeneralResponse;
 
+pub use catalog::{ErrorCategory, ErrorSchema};
+
 pub trait TError<'e>: Sized + StdError {
     fn e_code(&self) -> i64 {
         5_500_000
@@ -26,6 +31,8 @@ pub trait TError<'e>: Sized + StdError {
     serde::Deserialize,
     num_enum::FromPrimitive,
     num_enum::IntoPrimitive,
+    strum::EnumIter,
+    strum::IntoStaticStr,
     thiserror::Error,
 )]
 #[repr(i64)]
@@ -81,6 +88,9 @@ pub enum ServerError {
     /// 非法请求被拦截: 缺少请求参数
     #[error("非法请求被拦截: 请求参数异常")]
     FatalReqParamMissing = 5_412_202,
+    /// 请求或响应内容过大
+    #[error("请求内容过大")]
+    PayloadTooLarge = 4_413_000,
     /// 请求内容被锁定(-423)
     #[error("请求内容被锁定")]
     ReqContentLocked = 5_423_000,
@@ -285,6 +295,28 @@ impl IntoResponse for ServerError {
     }
 }
 
+impl ServerError {
+    /// Map HTTP status of an upstream response into [ServerError].
+    pub fn from_http_status(status: u16) -> Self {
+        match status {
+            400 => Self::RpcReqInvalid,
+            401 => Self::RpcReqUnauthorized,
+            403 => Self::RpcReqAccessDenied,
+            404 => Self::RpcReqNotFound,
+            412 => Self::RpcReqRiskControl,
+            429 => Self::RpcReqRateLimit,
+            500 => Self::RpcReqServerInternal,
+            501 => Self::RpcReqServerNotImplemented,
+            502 => Self::RpcReqBadGateway,
+            503 => Self::RpcReqServiceUnavailable,
+            504 => Self::RpcGatewayTimeout,
+            400..=499 => Self::RpcReqInvalid,
+            500..=599 => Self::RpcReqServerInternal,
+            _ => Self::GeneralRpc,
+        }
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum ServerErrorExt {
     /// Basic server error with default message
@@ -298,26 +330,84 @@ pub enum ServerErrorExt {
     },
     /// Anyhow error unknown
     #[error(transparent)]
-    Any(#[from] anyhow::Error),
+    Any(anyhow::Error),
     /// Custom error
     #[error("{message}")]
     Custom { code: i64, message: String },
 }
 
+/// Maps errors unknown to this crate, e.g. those of RPC clients, into
+/// [ServerErrorExt], see [set_error_mapper].
+pub type ErrorMapper = fn(&(dyn StdError + 'static)) -> Option<ServerErrorExt>;
+
+static ERROR_MAPPER: OnceLock<ErrorMapper> = OnceLock::new();
+
+/// Set the [ErrorMapper] for errors of crates depending on this one, which
+/// are otherwise all [ServerError::General].
+///
+/// Only the first one set is used.
+pub fn set_error_mapper(mapper: ErrorMapper) {
+    if ERROR_MAPPER.set(mapper).is_err() {
+        tracing::warn!("Error mapper already set, ignored");
+    }
+}
+
+impl ServerErrorExt {
+    /// Map a known error into [ServerErrorExt], with the [ErrorMapper] set for
+    /// ones of other crates.
+    ///
+    /// Its causes are not searched, see [ServerErrorExt::from_anyhow_ref].
+    pub fn try_from_error(e: &(dyn StdError + 'static)) -> Option<Self> {
+        if let Some(e) = e.downcast_ref::<Self>() {
+            return match e {
+                Self::Server(e) => Some(Self::Server(*e)),
+                Self::ServerExt { source, message } => Some(Self::ServerExt {
+                    source: *source,
+                    message: message.clone(),
+                }),
+                Self::Any(e) => Self::from_anyhow_ref(e),
+                Self::Custom { code, message } => Some(Self::Custom {
+                    code: *code,
+                    message: message.clone(),
+                }),
+            };
+        }
+        if let Some(e) = e.downcast_ref::<ServerError>() {
+            return Some(Self::Server(*e));
+        }
+        if let Some(e) = e.downcast_ref::<BiliError>() {
+            return Some(Self::from(e.clone()));
+        }
+        if let Some(e) = e.downcast_ref::<tonic::Status>() {
+            return Some(Self::from(e.clone()));
+        }
+        ERROR_MAPPER.get().and_then(|mapper| mapper(e))
+    }
+
+    /// Map the first known one in the chain of causes into [ServerErrorExt].
+    pub fn from_anyhow_ref(e: &anyhow::Error) -> Option<Self> {
+        e.chain().find_map(Self::try_from_error)
+    }
+}
+
+impl From<anyhow::Error> for ServerErrorExt {
+    fn from(e: anyhow::Error) -> Self {
+        let e = match e.downcast::<Self>() {
+            Ok(e) => return e,
+            Err(e) => e,
+        };
+
+        Self::from_anyhow_ref(&e).unwrap_or(Self::Any(e))
+    }
+}
+
 impl<'e> TError<'e> for ServerErrorExt {
     fn e_code(&self) -> i64 {
         match self {
             Self::Server(e) => e.e_code(),
             Self::ServerExt { source, .. } => source.e_code(),
-            Self::Any(e) => {
-                if let Some(e) = e.downcast_ref::<Self>() {
-                    return e.e_code();
-                }
-                if let Some(e) = e.downcast_ref::<ServerError>() {
-                    return e.e_code();
-                }
-                ServerError::General.e_code()
-            }
+            Self::Any(e) => Self::from_anyhow_ref(e)
+                .map_or_else(|| ServerError::General.e_code(), |e| e.e_code()),
             Self::Custom { code, .. } => *code,
         }
     }
@@ -332,11 +422,8 @@ impl<'e> TError<'e> for ServerErrorExt {
                 }
             }
             Self::Any(e) => {
-                if let Some(e) = e.downcast_ref::<Self>() {
-                    return e.e_message();
-                }
-                if let Some(e) = e.downcast_ref::<ServerError>() {
-                    return e.e_message();
+                if let Some(e) = Self::from_anyhow_ref(e) {
+                    return Cow::Owned(e.e_message().into_owned());
                 }
 
                 tracing::error!("Unknown anyhow error: {}", &e);
@@ -589,45 +676,56 @@ impl From<BiliError> for ServerErrorExt {
     }
 }
 
-use crate::{parse_grpc_any, str_concat};
 use lib_bilibili::bapis::rpc::Status as BiliGrpcStatus;
 
+impl BiliError {
+    /// Decode Bilibili's `bilibili.rpc.Status` carried by `grpc-status-details-bin`,
+    /// i.e. the details of [`tonic::Status`].
+    ///
+    /// Return `None` if there's no valid one or its code is `0`.
+    pub fn from_grpc_status_details(details: &[u8]) -> Option<Self> {
+        if details.is_empty() {
+            return None;
+        }
+
+        let status: BiliGrpcStatus = prost::Message::decode(details)
+            .map_err(|e| tracing::warn!("Invalid grpc-status-details-bin: {}", e))
+            .ok()?;
+
+        // The business one is packed in details, or is the status itself
+        let status = status
+            .details
+            .iter()
+            .find(|item| item.type_url.ends_with("bilibili.rpc.Status"))
+            .and_then(|item| prost::Message::decode(item.value.as_slice()).ok())
+            .unwrap_or(status);
+
+        tracing::error!(
+            "gRPC Uptream Error: code={}, message={}, details={:?}",
+            status.code,
+            status.message,
+            status.details
+        );
+
+        Self::try_from((status.code as i64, status.message.as_str())).ok()
+    }
+}
+
 impl From<tonic::Status> for ServerErrorExt {
     #[tracing::instrument(level = "error", name = "ServerErrorExt.from tonic::Status")]
     fn from(e: tonic::Status) -> Self {
+        if let Some(e) = BiliError::from_grpc_status_details(e.details()) {
+            return Self::from(e);
+        }
+
         let grpc_code = e.code();
         match grpc_code {
             tonic::Code::Unknown => {
-                let details = parse_grpc_any!(e.details(), BiliGrpcStatus);
-                for item in details.details.iter() {
-                    if item.type_url.contains("bilibili.rpc.Status") {
-                        let bili_rpc_status =
-                            parse_grpc_any!(item.value.as_slice(), BiliGrpcStatus);
-                        let e_code = bili_rpc_status.code as i64;
-                        let e_message = bili_rpc_status.message;
-                        let e_details = bili_rpc_status.details;
-                        tracing::error!(
-                            "gRPC Uptream Error: code={}, message={}, details={:?}",
-                            e_code,
-                            e_message,
-                            e_details
-                        );
-                        return if let Ok(e) = BiliError::try_from((e_code, e_message.as_str())) {
-                            Self::from(e)
-                        } else {
-                            Self::Custom {
-                                code: e_code,
-                                message: str_concat!(
-                                    "gRPC Uptream Unknown BiliError: ",
-                                    &e_message
-                                ),
-                            }
-                        };
-                    }
-                }
                 tracing::error!("Unknown gRPC Uptream Error: {:?}", e);
                 Self::Server(ServerError::GrpcReqUnknown)
             }
+            // Including the deadline propagated by `grpc-timeout` exceeded
+            tonic::Code::DeadlineExceeded => Self::Server(ServerError::RpcGatewayTimeout),
             _ => Self::Server(ServerError::from(grpc_code as i64 + 5_502_900)),
         }
     }
@@ -652,9 +750,122 @@ pub(crate) enum HeaderError {
     #[error(transparent)]
     ToStrError(#[from] http::header::ToStrError),
     #[error(transparent)]
-    ToStrError02(#[from] http_02::header::ToStrError),
-    #[error(transparent)]
     InvalidHeaderValue(#[from] http::header::InvalidHeaderValue),
-    #[error(transparent)]
-    InvalidHeaderValue02(#[from] http_02::header::InvalidHeaderValue),
+}
+
+#[cfg(test)]
+mod test {
+    use prost::Message;
+
+    use super::*;
+
+    fn bili_status(code: i32, message: &str) -> BiliGrpcStatus {
+        BiliGrpcStatus {
+            code,
+            message: message.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_grpc_status_details() {
+        let from_details = |status: BiliGrpcStatus| {
+            ServerErrorExt::from(tonic::Status::with_details(
+                tonic::Code::Unknown,
+                "",
+                status.encode_to_vec().into(),
+            ))
+        };
+
+        // Packed in details
+        let mut status = bili_status(-10403, "");
+        status.details.push(prost_types::Any {
+            type_url: "type.googleapis.com/bilibili.rpc.Status".to_owned(),
+            value: bili_status(-10403, "抱歉您所在地区不可观看！").encode_to_vec(),
+        });
+        assert!(matches!(
+            from_details(status),
+            ServerErrorExt::Server(ServerError::ServerIPAreaLimit)
+        ));
+
+        // The status itself
+        assert!(matches!(
+            from_details(bili_status(-412, "请求被拦截")),
+            ServerErrorExt::Server(ServerError::RpcReqRiskControl)
+        ));
+        assert!(matches!(
+            from_details(bili_status(-12345, "未知")),
+            ServerErrorExt::Custom { code: -12345, .. }
+        ));
+
+        // No details
+        assert!(matches!(
+            ServerErrorExt::from(tonic::Status::unknown("")),
+            ServerErrorExt::Server(ServerError::GrpcReqUnknown)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_localized_message() {
+        use crate::{locale::Lang, model::response::GeneralResponse};
+
+        assert_eq!(
+            ServerError::VipOnly.message(Lang::ZhHant),
+            "存取權限不足: 大會員專享限制"
+        );
+        assert_eq!(
+            ServerError::VipOnly.message(Lang::ZhHans),
+            ServerError::VipOnly.to_string()
+        );
+
+        let new_error = |code: i64, message: &str| {
+            let message = message.to_owned();
+            Lang::En.scope(async move {
+                let response = GeneralResponse::<()>::new_error(code, message);
+                serde_json::to_value(response).unwrap()["message"].clone()
+            })
+        };
+        let e = ServerError::ReqTooFrequent;
+        assert_eq!(
+            new_error(e.e_code(), &e.e_message()).await,
+            "Too many requests"
+        );
+        // Custom message kept
+        assert_eq!(new_error(e.e_code(), "自定义").await, "自定义");
+    }
+
+    #[test]
+    fn test_from_anyhow() {
+        use anyhow::{anyhow, Context};
+
+        assert!(matches!(
+            ServerErrorExt::from(anyhow!(BiliError::ResVipOnly)),
+            ServerErrorExt::Server(ServerError::VipOnly)
+        ));
+        assert!(matches!(
+            ServerErrorExt::from(
+                Err::<(), _>(tonic::Status::permission_denied(""))
+                    .context("calling upstream")
+                    .unwrap_err()
+            ),
+            ServerErrorExt::Server(ServerError::GrpcReqPermissionDenied)
+        ));
+        assert!(matches!(
+            ServerErrorExt::from(anyhow!(ServerError::RpcReqRiskControl)),
+            ServerErrorExt::Server(ServerError::RpcReqRiskControl)
+        ));
+
+        let unknown = ServerErrorExt::from(anyhow!("unknown"));
+        assert!(matches!(unknown, ServerErrorExt::Any(_)));
+        assert_eq!(unknown.e_code(), ServerError::General.e_code());
+
+        assert!(matches!(
+            ServerError::from_http_status(412),
+            ServerError::RpcReqRiskControl
+        ));
+        assert!(matches!(
+            ServerError::from_http_status(599),
+            ServerError::RpcReqServerInternal
+        ));
+    }
 }
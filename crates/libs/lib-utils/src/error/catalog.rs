@@ -0,0 +1,173 @@
+use std::borrow::Cow;
+
+use http::StatusCode;
+use strum::IntoEnumIterator;
+
+use super::ServerError;
+use crate::locale::Lang;
+
+/// Categories of [ServerError], by the code's `X_YYY_ZZZ` pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    /// `X_4XX_XXX` but account ones: invalid or blocked requests
+    Request,
+    /// `X_401_XXX` and `X_403_XXX`: login, ban and VIP restrictions
+    Account,
+    /// `X_451_XXX`: roaming blacklist, whitelist and content restrictions
+    Roaming,
+    /// `X_502_XXX` but gRPC ones: upstream unavailable
+    Rpc,
+    /// `X_502_9XX`: upstream unavailable, with gRPC status
+    Grpc,
+    /// `X_541_XXX`: resources restricted due to IP of the server
+    ServerIp,
+    /// Others: internal errors of the server
+    Server,
+}
+
+/// Machine-readable description of a [ServerError]
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ErrorSchema {
+    pub code: i64,
+    /// Name of the variant, e.g. `ServerIPAreaLimitHKMOTW`
+    pub name: &'static str,
+    pub category: ErrorCategory,
+    /// HTTP-equivalent status code
+    pub http_status: u16,
+    pub message: Cow<'static, str>,
+}
+
+impl ServerError {
+    /// The `YYY` part of code `X_YYY_ZZZ`
+    #[inline]
+    const fn status_part(self) -> i64 {
+        (self as i64) / 1000 % 1000
+    }
+
+    /// Category of the error, see [ErrorCategory].
+    pub const fn category(self) -> ErrorCategory {
+        match self.status_part() {
+            401 | 403 => ErrorCategory::Account,
+            451 => ErrorCategory::Roaming,
+            502 if (self as i64) % 1000 >= 900 => ErrorCategory::Grpc,
+            502 => ErrorCategory::Rpc,
+            541 => ErrorCategory::ServerIp,
+            400..=499 => ErrorCategory::Request,
+            _ => ErrorCategory::Server,
+        }
+    }
+
+    /// HTTP-equivalent status code, the `YYY` part of code `X_YYY_ZZZ`.
+    ///
+    /// Non-standard ones like `541` are treated as `500`, and
+    /// [ServerError::ServicesUnsupported], i.e. unknown route, as `404`.
+    ///
+    /// Responses are with this status under [StatusPolicy::Http] if the
+    /// error is surfaced there, see [StatusPolicy::status_of].
+    ///
+    /// [StatusPolicy::Http]: crate::model::response::StatusPolicy::Http
+    /// [StatusPolicy::status_of]: crate::model::response::StatusPolicy::status_of
+    pub fn http_status(self) -> StatusCode {
+        match self {
+            Self::Ok => return StatusCode::OK,
+            Self::ServicesUnsupported => return StatusCode::NOT_FOUND,
+            _ => {}
+        }
+
+        u16::try_from(self.status_part())
+            .ok()
+            .and_then(|status| StatusCode::from_u16(status).ok())
+            .filter(|status| status.canonical_reason().is_some())
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    /// Describe the error, with message in given language.
+    pub fn schema(self, lang: Lang) -> ErrorSchema {
+        ErrorSchema {
+            code: self.into(),
+            name: self.into(),
+            category: self.category(),
+            http_status: self.http_status().as_u16(),
+            message: self.message(lang),
+        }
+    }
+
+    /// Describe all errors but [ServerError::Ok], ordered by code.
+    pub fn catalog(lang: Lang) -> Vec<ErrorSchema> {
+        let mut catalog: Vec<_> = Self::iter()
+            .filter(|e| !matches!(e, Self::Ok))
+            .map(|e| e.schema(lang))
+            .collect();
+        catalog.sort_by_key(|schema| schema.code);
+        catalog
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::response::StatusPolicy;
+
+    #[test]
+    fn test_catalog() {
+        let catalog = ServerError::catalog(Lang::En);
+        assert_eq!(catalog.len(), ServerError::iter().count() - 1);
+        assert!(catalog.windows(2).all(|w| w[0].code < w[1].code));
+
+        let schema = catalog.iter().find(|s| s.code == 5_541_112).unwrap();
+        assert_eq!(schema.name, "ServerIPAreaLimitHKMOTW");
+        assert_eq!(schema.category, ErrorCategory::ServerIp);
+        assert_eq!(schema.http_status, 500);
+        assert_eq!(schema.message, "Server network error: area restricted");
+
+        assert_eq!(
+            ServerError::ReqTooFrequent.category(),
+            ErrorCategory::Request
+        );
+        assert_eq!(
+            ServerError::ReqTooFrequent.http_status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(ServerError::VipOnly.category(), ErrorCategory::Account);
+        assert_eq!(ServerError::GrpcReqAborted.category(), ErrorCategory::Grpc);
+        assert_eq!(ServerError::RpcReqNotFound.category(), ErrorCategory::Rpc);
+        assert_eq!(
+            ServerError::RoamingVipOnly.category(),
+            ErrorCategory::Roaming
+        );
+        assert_eq!(ServerError::Database.category(), ErrorCategory::Server);
+    }
+
+    #[test]
+    fn test_http_status_agrees_with_status_policy() {
+        for e in ServerError::iter().filter(|e| !matches!(e, ServerError::Ok)) {
+            let schema = e.schema(Lang::En);
+            let status = StatusPolicy::Http.status_of(schema.code);
+            assert!(
+                status == StatusCode::OK || status.as_u16() == schema.http_status,
+                "{} responded with {} but cataloged as {}",
+                schema.name,
+                status,
+                schema.http_status
+            );
+            assert_eq!(
+                StatusPolicy::Compatible.status_of(schema.code),
+                StatusCode::OK
+            );
+        }
+
+        for (e, status) in [
+            (ServerError::ServicesUnsupported, StatusCode::NOT_FOUND),
+            (
+                ServerError::MethodNotAllowed,
+                StatusCode::METHOD_NOT_ALLOWED,
+            ),
+            (ServerError::ReqTooFrequent, StatusCode::TOO_MANY_REQUESTS),
+            (ServerError::ServerFatal, StatusCode::SERVICE_UNAVAILABLE),
+        ] {
+            assert_eq!(e.http_status(), status);
+            assert_eq!(StatusPolicy::Http.status_of(e.into()), status);
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use std::borrow::Cow;
+
+use super::ServerError;
+use crate::locale::Lang;
+
+impl ServerError {
+    /// Message in given language, the Simplified Chinese one being the
+    /// `#[error]` message.
+    pub fn message(self, lang: Lang) -> Cow<'static, str> {
+        match lang {
+            Lang::ZhHans => Cow::Owned(self.to_string()),
+            Lang::ZhHant => Cow::Borrowed(self.translations().0),
+            Lang::En => Cow::Borrowed(self.translations().1),
+        }
+    }
+
+    /// Messages in Traditional Chinese and English
+    fn translations(self) -> (&'static str, &'static str) {
+        match self {
+            Self::Ok => ("OK", "OK"),
+            Self::AccessKeyInvalid => ("access_key 無效", "Invalid access_key"),
+            Self::UserNotLoggedIn => ("使用者未登入", "Not logged in"),
+            Self::AccountIsBaned => ("帳號被封停", "Account banned"),
+            Self::VipOnly => (
+                "存取權限不足: 大會員專享限制",
+                "Access denied: for VIP only",
+            ),
+            Self::VipOnlySEA => (
+                "存取權限不足: 東南亞 Premium 專享限制",
+                "Access denied: for SEA Premium only",
+            ),
+            Self::PlatformLimitRpcRes => (
+                "抱歉您所使用的平台不可觀看！",
+                "Sorry, not available on your platform",
+            ),
+            Self::MethodNotAllowed => ("請求方法被禁止", "Method not allowed"),
+            Self::RequestInvalidJson => ("請求格式不合法", "Invalid request format"),
+            Self::FatalReqInvalid
+            | Self::FatalIpInvalid
+            | Self::FatalUaInvalid
+            | Self::FatalReqParamInvalid => ("非法請求被攔截", "Invalid request blocked"),
+            Self::FatalUrlSignInvalid => (
+                "非法請求被攔截: 簽名非法",
+                "Invalid request blocked: invalid sign",
+            ),
+            Self::FatalReqParamMissing => (
+                "非法請求被攔截: 請求參數異常",
+                "Invalid request blocked: invalid parameters",
+            ),
+            Self::ReqContentLocked => ("請求內容被鎖定", "Requested content locked"),
+            Self::PayloadTooLarge => ("請求內容過大", "Payload too large"),
+            Self::ReqTooFrequent => ("請求過於頻繁", "Too many requests"),
+            Self::RoamingBlacklisted => ("漫遊黑名單封禁", "Blocked by roaming blacklist"),
+            Self::RoamingWhitelistedOnly => (
+                "漫遊白名單封禁: 伺服器僅供白名單使用者使用",
+                "Blocked: server for whitelisted users only",
+            ),
+            Self::RoamingVipOnly => ("伺服器僅限大會員使用", "Server for VIP only"),
+            Self::RoamingContentLimit => (
+                "漫遊資源封禁: 不支援的資源",
+                "Blocked: unsupported content",
+            ),
+            Self::General | Self::ReqAppkeyNotMatch | Self::ReqAppkeyInvalid => {
+                ("伺服器內部錯誤", "Internal server error")
+            }
+            Self::GeneralRpc => (
+                "伺服器內部錯誤: RPC 錯誤",
+                "Internal server error: RPC error",
+            ),
+            Self::Serialization => (
+                "伺服器內部錯誤: 序列化錯誤",
+                "Internal server error: serialization error",
+            ),
+            Self::Database => (
+                "伺服器內部錯誤: 資料庫錯誤",
+                "Internal server error: database error",
+            ),
+            Self::ServicesDeprecated => (
+                "伺服器內部錯誤: API 已棄用",
+                "Internal server error: API deprecated",
+            ),
+            Self::ServicesUnsupported => (
+                "伺服器內部錯誤: 不支援的服務",
+                "Internal server error: unsupported service",
+            ),
+            Self::ServerInternalNotImpl => (
+                "伺服器內部錯誤: 未實作的服務",
+                "Internal server error: service not implemented",
+            ),
+            Self::RoamingMode => (
+                "伺服器內部錯誤: 嗶哩漫遊模式",
+                "Internal server error: BiliRoaming mode",
+            ),
+            Self::RpcReqApiFatal => (
+                "伺服器內部錯誤: 上游服務不可用(O=-1)",
+                "Internal server error: upstream unavailable (O=-1)",
+            ),
+            Self::RpcReqApiSignInvalid => (
+                "伺服器內部錯誤: 上游服務不可用(O=-3)",
+                "Internal server error: upstream unavailable (O=-3)",
+            ),
+            Self::RpcReqInvalid => (
+                "伺服器內部錯誤: 上游服務不可用(O=-400)",
+                "Internal server error: upstream unavailable (O=-400)",
+            ),
+            Self::RpcReqUnauthorized => (
+                "伺服器內部錯誤: 上游服務不可用(O=-401)",
+                "Internal server error: upstream unavailable (O=-401)",
+            ),
+            Self::RpcReqAccessDenied => (
+                "伺服器內部錯誤: 上游服務不可用(O=-403)",
+                "Internal server error: upstream unavailable (O=-403)",
+            ),
+            Self::RpcReqNotFound => (
+                "伺服器內部錯誤: 上游服務不可用(O=-404)",
+                "Internal server error: upstream unavailable (O=-404)",
+            ),
+            Self::RpcReqRiskControl => (
+                "伺服器內部錯誤: 上游服務不可用(O=-412)",
+                "Internal server error: upstream unavailable (O=-412)",
+            ),
+            Self::RpcReqRateLimit => (
+                "伺服器內部錯誤: 上游服務不可用(E=429)",
+                "Internal server error: upstream unavailable (E=429)",
+            ),
+            Self::RpcNetworkFatal => (
+                "伺服器內部錯誤: 上游服務不可用(網路錯誤)",
+                "Internal server error: upstream unavailable (network error)",
+            ),
+            Self::RpcReqServerInternal => (
+                "伺服器內部錯誤: 上游服務不可用(E=500)",
+                "Internal server error: upstream unavailable (E=500)",
+            ),
+            Self::RpcReqServerNotImplemented => (
+                "伺服器內部錯誤: 上游服務不可用(E=501)",
+                "Internal server error: upstream unavailable (E=501)",
+            ),
+            Self::RpcReqBadGateway => (
+                "伺服器內部錯誤: 上游服務不可用(E=502)",
+                "Internal server error: upstream unavailable (E=502)",
+            ),
+            Self::RpcReqServiceUnavailable => (
+                "伺服器內部錯誤: 上游服務不可用(E=503)",
+                "Internal server error: upstream unavailable (E=503)",
+            ),
+            Self::RpcGatewayTimeout => (
+                "伺服器內部錯誤: 上游服務不可用(E=504)",
+                "Internal server error: upstream unavailable (E=504)",
+            ),
+            Self::GrpcReqCancelled => (
+                "伺服器內部錯誤: 上游服務不可用(gRPC Operation cancelled)",
+                "Internal server error: upstream unavailable (gRPC Operation cancelled)",
+            ),
+            Self::GrpcReqUnknown => (
+                "伺服器內部錯誤: 上游服務不可用(gRPC Unknown error)",
+                "Internal server error: upstream unavailable (gRPC Unknown error)",
+            ),
+            Self::GrpcReqInvalidArgument => (
+                "伺服器內部錯誤: 上游服務不可用(gRPC Client specified an invalid argument)",
+                "Internal server error: upstream unavailable (gRPC Client specified an invalid argument)",
+            ),
+            Self::GrpcReqDeadlineExceeded => (
+                "伺服器內部錯誤: 上游服務不可用(gRPC Operation deadline exceeded)",
+                "Internal server error: upstream unavailable (gRPC Operation deadline exceeded)",
+            ),
+            Self::GrpcReqNotFound => (
+                "伺服器內部錯誤: 上游服務不可用(gRPC Requested entity not found)",
+                "Internal server error: upstream unavailable (gRPC Requested entity not found)",
+            ),
+            Self::GrpcReqAlreadyExists => (
+                "伺服器內部錯誤: 上游服務不可用(gRPC Requested creation already exists)",
+                "Internal server error: upstream unavailable (gRPC Requested creation already exists)",
+            ),
+            Self::GrpcReqPermissionDenied => (
+                "伺服器內部錯誤: 上游服務不可用(gRPC Operation permission denied)",
+                "Internal server error: upstream unavailable (gRPC Operation permission denied)",
+            ),
+            Self::GrpcReqResourceExhausted => (
+                "伺服器內部錯誤: 上游服務不可用(gRPC Resource exhausted)",
+                "Internal server error: upstream unavailable (gRPC Resource exhausted)",
+            ),
+            Self::GrpcReqFailedPrecondition => (
+                "伺服器內部錯誤: 上游服務不可用(gRPC Operation failed precondition)",
+                "Internal server error: upstream unavailable (gRPC Operation failed precondition)",
+            ),
+            Self::GrpcReqAborted => (
+                "伺服器內部錯誤: 上游服務不可用(gRPC Operation aborted)",
+                "Internal server error: upstream unavailable (gRPC Operation aborted)",
+            ),
+            Self::GrpcReqOutOfRange => (
+                "伺服器內部錯誤: 上游服務不可用(gRPC Operation past the valid range)",
+                "Internal server error: upstream unavailable (gRPC Operation past the valid range)",
+            ),
+            Self::GrpcReqUnimplemented => (
+                "伺服器內部錯誤: 上游服務不可用(gRPC Operation not implemented or supported)",
+                "Internal server error: upstream unavailable (gRPC Operation not implemented or supported)",
+            ),
+            Self::GrpcReqInternal => (
+                "伺服器內部錯誤: 上游服務不可用(gRPC Internal error)",
+                "Internal server error: upstream unavailable (gRPC Internal error)",
+            ),
+            Self::GrpcReqUnavailable => (
+                "伺服器內部錯誤: 上游服務不可用(gRPC Service unavailable)",
+                "Internal server error: upstream unavailable (gRPC Service unavailable)",
+            ),
+            Self::GrpcReqDataLoss => (
+                "伺服器內部錯誤: 上游服務不可用(gRPC Unrecoverable data loss or corruption)",
+                "Internal server error: upstream unavailable (gRPC Unrecoverable data loss or corruption)",
+            ),
+            Self::GrpcReqUnauthenticated => (
+                "伺服器內部錯誤: 上游服務不可用(gRPC Request unauthenticated)",
+                "Internal server error: upstream unavailable (gRPC Request unauthenticated)",
+            ),
+            Self::ServerFatal => (
+                "伺服器內部錯誤: 伺服器不可用",
+                "Internal server error: server unavailable",
+            ),
+            Self::ServerIPDrmLimit => ("DRM 限制機房 IP", "DRM restricts datacenter IP"),
+            Self::ServerIPAreaLimit
+            | Self::ServerIPAreaLimitCN
+            | Self::ServerIPAreaLimitHKMOTW
+            | Self::ServerIPAreaLimitHKMO
+            | Self::ServerIPAreaLimitTW
+            | Self::ServerIPAreaLimitSEA => (
+                "伺服器網路錯誤: 受區域限制",
+                "Server network error: area restricted",
+            ),
+        }
+    }
+}
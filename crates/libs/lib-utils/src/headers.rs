@@ -1,5 +1,5 @@
 use anyhow::{anyhow, Result};
-use http_02::{HeaderMap as HttpHeaderMap, HeaderValue as HttpHeaderValue};
+use http::{HeaderMap as HttpHeaderMap, HeaderValue as HttpHeaderValue};
 use tonic::metadata::MetadataMap;
 
 use crate::{
@@ -71,7 +71,7 @@ pub enum HeaderKey {
 #[allow(dead_code)]
 impl HeaderKey {
     #[inline]
-    const fn str(self) -> &'static str {
+    pub(crate) const fn str(self) -> &'static str {
         match self {
             Self::Env => "env",
             Self::AppkeyName => "app-key",
@@ -141,16 +141,11 @@ pub trait BiliHeaderT {
     }
     /// Set `fp_local`, `fp_remote`.
     ///
-    /// TODO: If `android_id` or `drm_id` is given, `fp_local`, `fp_remote` and `buvid` will be generated and set.
-    /// Highly recommend that `drm_id` be given.
-    fn set_fp(&mut self, fp: &str, _android_id: Option<&str>, _drm_id: Option<&str>) -> &mut Self {
-        // let fp_local = str_concat!(fp, ":", drm_id.unwrap_or_default());
-        // let fp_remote = str_concat!(fp, ":", android_id.unwrap_or_default());
-        // let buvid = str_concat!(fp, ":", drm_id.unwrap_or_default(), ":", android_id.unwrap_or_default());
-
-        self.set(HeaderKey::FpLocal, fp)
-            .set(HeaderKey::FpRemote, fp)
-        // .set(HeaderKey::Buvid, &buvid)
+    /// `fp_remote` is the same as `fp_local` before synced with upstream.
+    /// See `lib_core::business::fingerprint` for generating them with `buvid`.
+    fn set_fp(&mut self, fp_local: &str, fp_remote: &str) -> &mut Self {
+        self.set(HeaderKey::FpLocal, fp_local)
+            .set(HeaderKey::FpRemote, fp_remote)
     }
     /// Set `x-bili-mid`, `x-bili-aurora-eid`
     fn set_mid(&mut self, mid: u64) -> &mut Self {
@@ -211,6 +206,17 @@ pub trait BiliHeaderT {
     }
 }
 
+/// Headers reserved by gRPC which tonic sanitizes when turning metadata into
+/// request headers
+const GRPC_RESERVED_HEADERS: [&str; 1] = ["user-agent"];
+
+/// Reserved headers of a gRPC request, like `user-agent`.
+///
+/// As tonic sanitizes them from metadata, they are passed by request
+/// extensions instead and restored by the gRPC client.
+#[derive(Debug, Clone, Default)]
+pub struct GrpcReservedHeaders(pub HttpHeaderMap);
+
 /// United `http::HeaderMap` wrapper for both `reqwest` & `tonic`
 #[derive(Debug, Clone)]
 pub struct ManagedHeaderMap {
@@ -400,6 +406,42 @@ impl ManagedHeaderMap {
         std::mem::take(&mut self.inner)
     }
 
+    /// Set headers to metadata of given gRPC request, overriding existing ones
+    /// except `grpc-*` ones set by tonic, e.g. `grpc-timeout`.
+    ///
+    /// Unlike [`take_inner`](Self::take_inner), this can be called for each
+    /// request, e.g. by the [`tonic::service::Interceptor`].
+    pub fn apply_to_grpc_request<T>(&self, request: &mut tonic::Request<T>) {
+        // Should not panic when release
+        self.verify();
+
+        let mut header_map = std::mem::take(request.metadata_mut()).into_headers();
+        let mut reserved = HttpHeaderMap::new();
+
+        for (key, value) in self.inner.iter() {
+            if GRPC_RESERVED_HEADERS.contains(&key.as_str()) {
+                reserved.append(key.clone(), value.clone());
+            }
+        }
+        header_map.extend(self.inner.clone());
+
+        *request.metadata_mut() = MetadataMap::from_headers(header_map);
+
+        if !reserved.is_empty() {
+            request
+                .extensions_mut()
+                .insert(GrpcReservedHeaders(reserved));
+        }
+    }
+
+    #[inline]
+    /// Create a gRPC request of given message with the headers as metadata
+    pub fn grpc_request<T>(&self, message: T) -> tonic::Request<T> {
+        let mut request = tonic::Request::new(message);
+        self.apply_to_grpc_request(&mut request);
+        request
+    }
+
     /// Verify if all required headers are set.
     ///
     /// Will skip verification if `self.for_bili` is `false`.
@@ -494,23 +536,12 @@ impl Into<HttpHeaderMap> for ManagedHeaderMap {
 }
 
 impl tonic::service::Interceptor for ManagedHeaderMap {
-    fn call(&mut self, request: tonic::Request<()>) -> Result<tonic::Request<()>, tonic::Status> {
-        let (metadata_original, extensions, message) = request.into_parts();
-        let header_map_original = metadata_original.into_headers();
-
-        let mut header_map = self.take_inner();
-        if let Some(encoding) = header_map_original.get("grpc-encoding") {
-            header_map.insert("grpc-encoding", encoding.clone());
-        }
-        if let Some(accept_encoding) = header_map_original.get("grpc-accept-encoding") {
-            header_map.insert("grpc-accept-encoding", accept_encoding.clone());
-        }
-
-        Ok(tonic::Request::from_parts(
-            MetadataMap::from_headers(header_map),
-            extensions,
-            message,
-        ))
+    fn call(
+        &mut self,
+        mut request: tonic::Request<()>,
+    ) -> Result<tonic::Request<()>, tonic::Status> {
+        self.apply_to_grpc_request(&mut request);
+        Ok(request)
     }
 }
 
@@ -543,10 +574,38 @@ mod test {
 
         println!("{:?}", headers.take_inner())
     }
+
+    #[test]
+    fn test_apply_to_grpc_request() {
+        use super::{GrpcReservedHeaders, HeaderKey, ManagedHeaderMap};
+
+        let mut headers = ManagedHeaderMap::new(false, false);
+        headers.insert(HeaderKey::Custom("x-test"), "managed");
+
+        // Reusable for each request
+        for _ in 0..2 {
+            let mut request = tonic::Request::new(());
+            request
+                .metadata_mut()
+                .insert("grpc-timeout", "100m".parse().unwrap());
+            request
+                .metadata_mut()
+                .insert("x-test", "original".parse().unwrap());
+
+            headers.apply_to_grpc_request(&mut request);
+
+            assert_eq!(request.metadata().get("grpc-timeout").unwrap(), "100m");
+            assert_eq!(request.metadata().get("x-test").unwrap(), "managed");
+            assert!(request
+                .extensions()
+                .get::<GrpcReservedHeaders>()
+                .is_some_and(|reserved| reserved.0.contains_key("user-agent")));
+        }
+    }
 }
 
 #[allow(dead_code)]
-pub(crate) mod user_agent {
+pub mod user_agent {
     use crate::str_concat;
     use rand::Rng;
     use std::fmt::Write;
@@ -558,6 +617,7 @@ pub(crate) mod user_agent {
         fn bili_app(&self) -> String;
     }
 
+    #[derive(Debug, Clone)]
     pub struct UniteUA {
         app_build: String,
         app_ver: String,
@@ -765,26 +825,30 @@ pub(crate) mod user_agent {
         pub const UA_DALVIK_DEFAULT: &'static str = "Dalvik/2.1.0 (Linux; U; Android 12; NOH-AN01 Build/HUAWEINOH-AN01) 7.38.0 os/android model/NOH-AN01 mobi_app/android build/7380300 channel/master innerVer/7380300 osVer/12 network/2";
         pub const UA_APP_DEFAULT: &'static str = "Mozilla/5.0 BiliDroid/7.38.0 (bbcallen@gmail.com) os/android model/NOH-AN01 mobi_app/android build/7380300 channel/master innerVer/7380310 osVer/12 network/2";
 
+        /// Phones faked, as (os_ver, brand, device_model, device_build)
+        pub const PHONES: [(&'static str, &'static str, &'static str, &'static str); 15] = [
+            ("13", "Google", "Pixel 6 Pro", "TQ1A.221205.011"),
+            ("13", "samsung", "SM-S9080", "TP1A.220624.014"),
+            ("13", "Xiaomi", "2201122C", "TKQ1.220807.001"),
+            ("12", "HUAWEI", "JEF-AN00", "HUAWEIJEF-AN00"),
+            ("12", "HUAWEI", "VOG-AL10", "HUAWEIVOG-AL10"),
+            ("12", "HUAWEI", "ELS-AN00", "HUAWEIELS-AN00"),
+            ("12", "HUAWEI", "NOH-AN01", "HUAWEINOH-AN01"),
+            ("11", "blackshark", "SKW-A0", "SKYW2203210CN00MR1"),
+            ("11", "Redmi", "21091116AC", "RP1A.200720.011"),
+            ("10", "HUAWEI", "VOG-AL10", "HUAWEIVOG-AL10"),
+            ("10", "HUAWEI", "JEF-AN00", "HUAWEIJEF-AN00"),
+            ("10", "HUAWEI", "VOG-AL10", "HUAWEIVOG-AL10"),
+            ("10", "HUAWEI", "ELS-AN00", "HUAWEIELS-AN00"),
+            ("9", "HONOR", "BND-AL10", "HONORBND-AL10"),
+            ("9", "HUAWEI", "ALP-AL00", "HUAWEIALP-AL00"),
+        ];
+
         #[inline]
         fn gen_random_phone() -> (&'static str, &'static str, &'static str) {
-            let phones = [
-                ("13", "Pixel 6 Pro", "TQ1A.221205.011"),
-                ("13", "SM-S9080", "TP1A.220624.014"),
-                ("13", "2201122C", "TKQ1.220807.001"),
-                ("12", "JEF-AN00", "HUAWEIJEF-AN00"),
-                ("12", "VOG-AL10", "HUAWEIVOG-AL10"),
-                ("12", "ELS-AN00", "HUAWEIELS-AN00"),
-                ("12", "NOH-AN01", "HUAWEINOH-AN01"),
-                ("11", "SKW-A0", "SKYW2203210CN00MR1"),
-                ("11", "21091116AC", "RP1A.200720.011"),
-                ("10", "VOG-AL10", "HUAWEIVOG-AL10"),
-                ("10", "JEF-AN00", "HUAWEIJEF-AN00"),
-                ("10", "VOG-AL10", "HUAWEIVOG-AL10"),
-                ("10", "ELS-AN00", "HUAWEIELS-AN00"),
-                ("9", "BND-AL10", "HONORBND-AL10"),
-                ("9", "ALP-AL00", "HUAWEIALP-AL00"),
-            ];
-            phones[rand::thread_rng().gen_range(0..=14)]
+            let (os_ver, _, device_model, device_build) =
+                Self::PHONES[rand::thread_rng().gen_range(0..Self::PHONES.len())];
+            (os_ver, device_model, device_build)
         }
     }
 }
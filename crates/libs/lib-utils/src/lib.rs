@@ -1,6 +1,7 @@
 pub mod avbvc;
 pub mod error;
 pub mod headers;
+pub mod locale;
 pub mod macros;
 pub mod misc;
 pub mod model;
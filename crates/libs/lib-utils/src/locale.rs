@@ -0,0 +1,183 @@
+//! Locale of the client, for selecting the language of messages returned.
+
+use http::{header::ACCEPT_LANGUAGE, HeaderMap as HttpHeaderMap};
+use lib_bilibili::bapis::metadata::locale::Locale;
+
+use crate::{b64_decode, headers::HeaderKey};
+
+tokio::task_local! {
+    static LANG: Lang;
+}
+
+/// Languages messages are translated into
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Lang {
+    /// Simplified Chinese, the default one
+    #[default]
+    ZhHans,
+    /// Traditional Chinese, for HK / MO / TW users
+    ZhHant,
+    /// English, for all other languages
+    En,
+}
+
+impl Lang {
+    /// Language of the request being handled, see [Lang::scope].
+    ///
+    /// Default to [Lang::ZhHans] if not in any scope.
+    pub fn current() -> Self {
+        LANG.try_with(|lang| *lang).unwrap_or_default()
+    }
+
+    /// Run `f` with given language as [Lang::current].
+    pub async fn scope<F: std::future::Future>(self, f: F) -> F::Output {
+        LANG.scope(self, f).await
+    }
+
+    /// Select the language of a request, from `x-bili-locale-bin` metadata,
+    /// `Accept-Language` header, or `s_locale` query in order.
+    pub fn from_request(headers: &HttpHeaderMap, query: Option<&str>) -> Option<Self> {
+        if let Some(lang) = headers
+            .get(HeaderKey::BiliLocaleBin.str())
+            .and_then(|value| {
+                let value = value.to_str().ok()?.trim_end_matches('=');
+                let value =
+                    b64_decode!(value, base64::engine::general_purpose::STANDARD_NO_PAD).ok()?;
+                prost::Message::decode(value.as_slice()).ok()
+            })
+            .and_then(|locale: Locale| Self::from_locale(&locale))
+        {
+            return Some(lang);
+        }
+
+        if let Some(lang) = headers
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Self::from_accept_language)
+        {
+            return Some(lang);
+        }
+
+        query?
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("s_locale="))
+            .and_then(Self::from_tag)
+    }
+
+    /// From `Locale` of gRPC metadata, the app's locale preferred to the
+    /// system's.
+    pub fn from_locale(locale: &Locale) -> Option<Self> {
+        [&locale.c_locale, &locale.s_locale]
+            .into_iter()
+            .flatten()
+            .find_map(|ids| Self::from_parts(&ids.language, &ids.script, &ids.region))
+    }
+
+    /// From `Accept-Language` header, like `zh-TW,zh;q=0.9,en;q=0.8`, the one
+    /// with highest weight first.
+    pub fn from_accept_language(value: &str) -> Option<Self> {
+        let mut best: Option<(f32, Self)> = None;
+
+        for item in value.split(',') {
+            let mut parts = item.split(';');
+            let Some(lang) = parts.next().and_then(|tag| Self::from_tag(tag.trim())) else {
+                continue;
+            };
+            let weight = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+
+            if best.map_or(true, |(w, _)| weight > w) {
+                best = Some((weight, lang));
+            }
+        }
+
+        best.map(|(_, lang)| lang)
+    }
+
+    /// From a language tag, like `zh-Hant-HK`, `zh_TW` or `en-US`.
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let mut subtags = tag.split(['-', '_']);
+        let language = subtags.next()?;
+
+        let (mut script, mut region) = ("", "");
+        for subtag in subtags {
+            match subtag.len() {
+                4 => script = subtag,
+                2 | 3 => region = subtag,
+                _ => {}
+            }
+        }
+
+        Self::from_parts(language, script, region)
+    }
+
+    fn from_parts(language: &str, script: &str, region: &str) -> Option<Self> {
+        if language.is_empty() || language == "*" {
+            return None;
+        }
+        if !language.eq_ignore_ascii_case("zh") {
+            return Some(Self::En);
+        }
+
+        if script.eq_ignore_ascii_case("Hant") {
+            return Some(Self::ZhHant);
+        }
+        if script.eq_ignore_ascii_case("Hans") {
+            return Some(Self::ZhHans);
+        }
+
+        match region.to_ascii_uppercase().as_str() {
+            "HK" | "MO" | "TW" => Some(Self::ZhHant),
+            _ => Some(Self::ZhHans),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn test_lang() {
+        assert_eq!(Lang::from_tag("zh-Hant-HK"), Some(Lang::ZhHant));
+        assert_eq!(Lang::from_tag("zh_TW"), Some(Lang::ZhHant));
+        assert_eq!(Lang::from_tag("zh-CN"), Some(Lang::ZhHans));
+        assert_eq!(Lang::from_tag("en-US"), Some(Lang::En));
+        assert_eq!(Lang::from_tag(""), None);
+
+        assert_eq!(
+            Lang::from_accept_language("en;q=0.8, zh-TW, zh;q=0.9"),
+            Some(Lang::ZhHant)
+        );
+        assert_eq!(Lang::from_accept_language("*"), None);
+
+        let mut headers = HttpHeaderMap::new();
+        assert_eq!(
+            Lang::from_request(&headers, Some("s_locale=zh_Hant")),
+            Some(Lang::ZhHant)
+        );
+
+        headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_static("en-US"));
+        assert_eq!(
+            Lang::from_request(&headers, Some("s_locale=zh_Hant")),
+            Some(Lang::En)
+        );
+
+        // zh-Hans-CN
+        headers.insert(
+            HeaderKey::BiliLocaleBin.str(),
+            HeaderValue::from_static("Cg4KAnpoEgRIYW5zGgJDThIOCgJ6aBIESGFucxoCQ04"),
+        );
+        assert_eq!(Lang::from_request(&headers, None), Some(Lang::ZhHans));
+    }
+
+    #[tokio::test]
+    async fn test_lang_scope() {
+        assert_eq!(Lang::current(), Lang::ZhHans);
+        assert_eq!(Lang::En.scope(async { Lang::current() }).await, Lang::En);
+    }
+}
@@ -1,12 +1,78 @@
 use anyhow::Result;
 use axum::response::{IntoResponse, Response as AxumResponse};
 use bytes::{BufMut, BytesMut};
-use http::{header, HeaderValue};
+use http::{header, HeaderValue, StatusCode};
 use serde::{Deserialize, Serialize};
 
-use std::{collections::HashMap, fmt::Debug as StdDebug};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    fmt::Debug as StdDebug,
+    pin::Pin,
+    task::{Context, Poll},
+};
 
-use crate::error::{ServerError, ServerErrorExt, TError};
+use crate::{
+    error::{ServerError, ServerErrorExt, TError},
+    locale::Lang,
+};
+
+tokio::task_local! {
+    static STATUS_POLICY: StatusPolicy;
+}
+
+/// Policy of HTTP status of error responses
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusPolicy {
+    /// Always `200 OK` with error in JSON `code`, as official clients expect
+    #[default]
+    Compatible,
+    /// Proper HTTP status for errors generic HTTP clients and load balancers
+    /// should see, see [StatusPolicy::status_of]
+    Http,
+}
+
+impl StatusPolicy {
+    /// Policy of the request being handled, see [StatusPolicy::scope].
+    ///
+    /// Default to [StatusPolicy::Compatible] if not in any scope.
+    pub fn current() -> Self {
+        STATUS_POLICY.try_with(|policy| *policy).unwrap_or_default()
+    }
+
+    /// Run `f` with given policy as [StatusPolicy::current].
+    pub async fn scope<F: std::future::Future>(self, f: F) -> F::Output {
+        STATUS_POLICY.scope(self, f).await
+    }
+
+    /// HTTP status of a response with error `code`.
+    ///
+    /// Under [StatusPolicy::Http], errors below are surfaced with their
+    /// [ServerError::http_status], and others are with `200`:
+    ///
+    /// - [ServerError::ServicesUnsupported], i.e. unknown route: `404`
+    /// - [ServerError::MethodNotAllowed]: `405`
+    /// - [ServerError::ReqTooFrequent]: `429`, with `Retry-After`
+    /// - [ServerError::ServerFatal]: `503`
+    pub fn status_of(self, code: i64) -> StatusCode {
+        if self == Self::Compatible {
+            return StatusCode::OK;
+        }
+
+        match ServerError::from(code) {
+            e if i64::from(e) != code => StatusCode::OK,
+            e @ (ServerError::ServicesUnsupported
+            | ServerError::MethodNotAllowed
+            | ServerError::ReqTooFrequent
+            | ServerError::ServerFatal) => e.http_status(),
+            _ => StatusCode::OK,
+        }
+    }
+}
+
+/// Seconds of `Retry-After` if not given, see [GeneralResponse::with_retry_after]
+const DEFAULT_RETRY_AFTER: u64 = 1;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(default)]
@@ -21,6 +87,10 @@ pub struct GeneralResponse<T: StdDebug + Serialize = serde_json::Value> {
     /// Tracing information
     #[serde(skip_serializing_if = "HashMap::is_empty")]
     logger: HashMap<String, String>,
+
+    /// Seconds of `Retry-After` for `429 Too Many Requests`
+    #[serde(skip)]
+    retry_after: Option<u64>,
 }
 
 impl<T: StdDebug + Serialize> Default for GeneralResponse<T> {
@@ -31,6 +101,7 @@ impl<T: StdDebug + Serialize> Default for GeneralResponse<T> {
             ttl: 1,
             data: None,
             logger: HashMap::with_capacity(4),
+            retry_after: None,
         }
     }
 }
@@ -73,12 +144,25 @@ impl<T: StdDebug + Serialize> GeneralResponse<T> {
     }
 
     /// Create a new [GeneralResponse] with error tracing infos.
+    ///
+    /// Default message of the code is translated into [Lang::current], while
+    /// custom ones are kept as is.
     #[inline]
     #[tracing::instrument(level = "error", name = "GeneralResponse.new_error", skip_all)]
     pub fn new_error(code: i64, message: impl ToString) -> Self {
+        let mut message = message.to_string();
+
+        let lang = Lang::current();
+        if lang != Lang::ZhHans {
+            let e = ServerError::from(code);
+            if i64::from(e) == code && e.to_string() == message {
+                message = e.message(lang).into_owned();
+            }
+        }
+
         let mut response = Self {
             code,
-            message: message.to_string(),
+            message,
             ..Default::default()
         };
 
@@ -96,12 +180,28 @@ impl<T: StdDebug + Serialize> GeneralResponse<T> {
         response
     }
 
+    /// Set seconds of `Retry-After`, for responses being `429 Too Many
+    /// Requests` under [StatusPolicy::Http].
+    #[inline]
+    pub fn with_retry_after(mut self, secs: u64) -> Self {
+        self.retry_after = Some(secs);
+        self
+    }
+
     /// Customly implement [IntoResponse] for [`GeneralResponse`].
     ///
     /// For historical reason, sometimes non standard response with only `data` is required.
+    ///
+    /// HTTP status of error responses follows [StatusPolicy::current].
     #[inline]
     #[tracing::instrument(level = "debug", name = "GeneralResponse.into_response", skip(self))]
     pub fn into_response(self, data_only: bool) -> AxumResponse {
+        let status = match self.code {
+            0 => StatusCode::OK,
+            code => StatusPolicy::current().status_of(code),
+        };
+        let retry_after = self.retry_after.unwrap_or(DEFAULT_RETRY_AFTER);
+
         let mut buf = BytesMut::with_capacity(128).writer();
         if data_only && self.code == 0 {
             serde_json::to_writer(&mut buf, &self.data)
@@ -114,14 +214,21 @@ impl<T: StdDebug + Serialize> GeneralResponse<T> {
                 ServerError::Serialization.into_response()
             },
             |_| {
-                (
+                let mut response = (
+                    status,
                     [(
                         header::CONTENT_TYPE,
                         HeaderValue::from_static("application/json"),
                     )],
                     buf.into_inner().freeze(),
                 )
-                    .into_response()
+                    .into_response();
+                if status == StatusCode::TOO_MANY_REQUESTS {
+                    response
+                        .headers_mut()
+                        .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+                }
+                response
             },
         )
     }
@@ -179,3 +286,88 @@ impl IntoResponse for ResponsePassthrough {
         response
     }
 }
+
+/// A wrapper for passing through gRPC response from upstream, with trailers.
+#[derive(Debug)]
+pub struct GrpcResponsePassthrough {
+    pub headers: http::HeaderMap,
+    pub body: bytes::Bytes,
+    pub trailers: Option<http::HeaderMap>,
+}
+
+impl IntoResponse for GrpcResponsePassthrough {
+    #[tracing::instrument(skip(self))]
+    fn into_response(self) -> AxumResponse {
+        let body = TrailersBody::new(self.body, self.trailers);
+        let mut response = AxumResponse::new(axum::body::Body::new(body));
+        *response.headers_mut() = self.headers;
+        response
+    }
+}
+
+/// A simple [http_body::Body] yielding buffered data and then trailers.
+#[derive(Debug, Default)]
+pub struct TrailersBody {
+    data: Option<bytes::Bytes>,
+    trailers: Option<http::HeaderMap>,
+}
+
+impl TrailersBody {
+    #[inline]
+    pub fn new(data: bytes::Bytes, trailers: Option<http::HeaderMap>) -> Self {
+        Self {
+            data: Some(data).filter(|d| !d.is_empty()),
+            trailers,
+        }
+    }
+}
+
+impl http_body::Body for TrailersBody {
+    type Data = bytes::Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<http_body::Frame<Self::Data>, Self::Error>>> {
+        if let Some(data) = self.data.take() {
+            return Poll::Ready(Some(Ok(http_body::Frame::data(data))));
+        }
+        if let Some(trailers) = self.trailers.take() {
+            return Poll::Ready(Some(Ok(http_body::Frame::trailers(trailers))));
+        }
+        Poll::Ready(None)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.data.is_none() && self.trailers.is_none()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_status_policy() {
+        let response = ServerError::ReqTooFrequent.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = StatusPolicy::Http
+            .scope(async {
+                GeneralResponse::<()>::new_error(ServerError::ReqTooFrequent.into(), "")
+                    .with_retry_after(30)
+                    .into_response(false)
+            })
+            .await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "30");
+
+        let policy = StatusPolicy::Http;
+        assert_eq!(policy.status_of(3_405_000), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(policy.status_of(5_503_000), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(policy.status_of(5_501_201), StatusCode::NOT_FOUND);
+        assert_eq!(policy.status_of(5_500_101), StatusCode::OK);
+        assert_eq!(policy.status_of(-404), StatusCode::OK);
+    }
+}
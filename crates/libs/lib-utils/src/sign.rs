@@ -1,4 +1,4 @@
-use crate::{calc_md5, str_concat};
+use crate::{calc_md5, error::ServerError, str_concat};
 use anyhow::{bail, Result};
 use thiserror::Error;
 
@@ -7,14 +7,180 @@ use thiserror::Error;
 pub enum SignErr {
     #[error("Invalid wbi key, length not 64")]
     InvalidWbiKey,
-    // #[error("Unknown appkey {0}, corresponding appsec not found")]
-    // UnknownAppkey(String),
+    #[error("Unknown appkey {0}, corresponding appsec not found")]
+    UnknownAppkey(String),
 }
 
 #[derive(Debug)]
 pub enum Signer<'s> {
     None,
     Wbi { img_key: &'s str, sub_key: &'s str },
+    WbiMixinKey { mixin_key: &'s str },
+    App { appkey: &'s str },
+}
+
+/// Appkey of a Bilibili client, with corresponding appsec and `mobi_app`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AppkeyInfo {
+    /// Name of the appkey, e.g. `android64`
+    pub name: &'static str,
+    pub appkey: &'static str,
+    pub appsec: &'static str,
+    /// `mobi_app` of the client using the appkey
+    pub mobi_app: &'static str,
+}
+
+impl AppkeyInfo {
+    #[inline]
+    const fn new(
+        name: &'static str,
+        appkey: &'static str,
+        appsec: &'static str,
+        mobi_app: &'static str,
+    ) -> Self {
+        Self {
+            name,
+            appkey,
+            appsec,
+            mobi_app,
+        }
+    }
+
+    /// Find the known appkey.
+    #[inline]
+    pub fn from_appkey(appkey: &str) -> Option<&'static Self> {
+        APPKEYS.iter().find(|info| info.appkey == appkey)
+    }
+
+    /// Find the known appkey by name, e.g. `android64`.
+    #[inline]
+    pub fn from_name(name: &str) -> Option<&'static Self> {
+        APPKEYS.iter().find(|info| info.name == name)
+    }
+}
+
+/// Known appkeys
+pub const APPKEYS: &[AppkeyInfo] = &[
+    AppkeyInfo::new(
+        "android",
+        "1d8b6e7d45233436",
+        "560c52ccd288fed045859ed18bffd973",
+        "android",
+    ),
+    AppkeyInfo::new(
+        "android64",
+        "783bbb7264451d82",
+        "2653583c8873dea268ab9386918b1d65",
+        "android",
+    ),
+    AppkeyInfo::new(
+        "android_b",
+        "07da50c9a0bf829f",
+        "25bdede4e1581c836cab73a48790ca6e",
+        "android_b",
+    ),
+    AppkeyInfo::new(
+        "android_hd",
+        "dfca71928277209b",
+        "b5475a8825547a4fc26c7d518eaaa02e",
+        "android_hd",
+    ),
+    AppkeyInfo::new(
+        "android_i",
+        "ae57252b0c09105d",
+        "c75875c596a69eb55bd119e74b07cfe3",
+        "android_i",
+    ),
+    AppkeyInfo::new(
+        "bstar_a",
+        "7d089525d3611b1c",
+        "acd495b248ec528c2eed1e862d393126",
+        "bstar_a",
+    ),
+    AppkeyInfo::new(
+        "ios",
+        "27eb53fc9058f8c3",
+        "c2ed53a74eeefe3cf99fbd01d8c9c375",
+        "iphone",
+    ),
+    AppkeyInfo::new(
+        "ipad",
+        "cc8617fd6961e070",
+        "3131924b941aac971e45189f265262be",
+        "ipad",
+    ),
+    AppkeyInfo::new(
+        "tv",
+        "4409e2ce8ffd12b8",
+        "59b43e04ad6965f34319062b478f83dd",
+        "android_tv_yst",
+    ),
+];
+
+/// App sign implementation, `sign` = MD5(sorted query + appsec)
+pub struct App;
+
+impl App {
+    /// Calculate `sign` param value.
+    ///
+    /// Pass `sorted_params` with `appkey` and the corresponding `appsec` to this function.
+    #[inline]
+    pub fn gen_sign(sorted_params: &str, appsec: &str) -> String {
+        calc_md5!(str_concat!(sorted_params, appsec))
+    }
+
+    #[tracing::instrument(level = "debug", name = "App.verify", err)]
+    /// Verify sign of the query string of an incoming request, return the
+    /// appkey used.
+    ///
+    /// Attention:
+    /// - FatalUrlSignInvalid if `sign` or `appkey` is missing, or `sign` is bad.
+    /// - ReqAppkeyInvalid if `appkey` is unknown.
+    /// - ReqAppkeyNotMatch if `mobi_app` is not the one of `appkey`.
+    pub fn verify(query: &str) -> Result<&'static AppkeyInfo> {
+        let mut sign = None;
+        let mut appkey = None;
+        let mut mobi_app = None;
+        let mut params = Vec::with_capacity(32);
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+            match k {
+                "sign" => {
+                    sign = Some(v);
+                    continue;
+                }
+                "appkey" => appkey = Some(v),
+                "mobi_app" => mobi_app = Some(v),
+                _ => {}
+            }
+            params.push((k, pair));
+        }
+
+        let (Some(sign), Some(appkey)) = (sign, appkey) else {
+            bail!(ServerError::FatalUrlSignInvalid);
+        };
+        let Some(info) = AppkeyInfo::from_appkey(appkey) else {
+            bail!(ServerError::ReqAppkeyInvalid);
+        };
+
+        // Params are signed as sent, or sorted by key by most clients
+        let is_valid = |params: &[(&str, &str)]| {
+            let unsigned_query = params.iter().map(|(_, pair)| *pair).collect::<Vec<_>>();
+            Self::gen_sign(&unsigned_query.join("&"), info.appsec).eq_ignore_ascii_case(sign)
+        };
+        if !is_valid(&params) {
+            params.sort_by_key(|(k, _)| *k);
+            if !is_valid(&params) {
+                bail!(ServerError::FatalUrlSignInvalid);
+            }
+        }
+
+        if mobi_app.is_some_and(|mobi_app| mobi_app != info.mobi_app) {
+            bail!(ServerError::ReqAppkeyNotMatch);
+        }
+
+        Ok(info)
+    }
 }
 
 /// WBI Sign implementation V1.0.2
@@ -27,7 +193,6 @@ impl Wbi {
     pub fn gen_mixin_key(img_key: &str, sub_key: &str) -> Result<String> {
         let wbi_key = str_concat!(img_key, sub_key);
         if wbi_key.len() != 64 {
-            // TODO Request upstream for new one
             bail!(SignErr::InvalidWbiKey);
         }
 
@@ -80,9 +245,11 @@ impl Wbi {
 
 type HmacSha256 = hmac::Hmac<sha2::Sha256>;
 use hmac::Mac;
+use std::fmt::Write;
 
-#[allow(dead_code)]
-enum BiliTicket {
+/// Payload of requesting `x-bili-ticket`, signed with HMAC-SHA256
+#[derive(Debug, Clone)]
+pub enum BiliTicket {
     App {
         /// context, generated with `com.bapis.bilibili.metadata.device.Device`
         device_info: Vec<u8>,
@@ -96,7 +263,6 @@ enum BiliTicket {
     },
 }
 
-#[allow(dead_code)]
 impl BiliTicket {
     #[inline]
     const fn hmac_key(&self) -> &'static [u8] {
@@ -105,6 +271,16 @@ impl BiliTicket {
             Self::Web { .. } => b"XgwSnGZ1p",
         }
     }
+
+    /// `key_id` of the HMAC key
+    #[inline]
+    pub const fn key_id(&self) -> &'static str {
+        match self {
+            Self::App { .. } => "ec01",
+            Self::Web { .. } => "ec02",
+        }
+    }
+
     pub fn sign(self) -> Vec<u8> {
         let mut mac = HmacSha256::new_from_slice(self.hmac_key()).unwrap();
         match self {
@@ -127,6 +303,16 @@ impl BiliTicket {
         }
         mac.finalize().into_bytes().to_vec()
     }
+
+    /// Sign in lowercase hex, `hexsign` of web ones.
+    pub fn sign_hex(self) -> String {
+        self.sign()
+            .iter()
+            .fold(String::with_capacity(64), |mut hex, b| {
+                let _ = write!(hex, "{:02x}", b);
+                hex
+            })
+    }
 }
 
 #[cfg(test)]
@@ -147,13 +333,51 @@ mod test {
         ];
         assert_eq!(d, EXAMPLE);
     }
-    // Seems without crate hex support... Damn
-    // #[test]
-    // fn test_web() {
-    //     let d = BiliTicket::Web { ts: 1705658461 }.sign();
-    //     assert_eq!(
-    //         hex::encode(d).as_str(),
-    //         "3c22306bd1ec1227b9d07270c6846a58488b9c554eecdf2a40ae518b25f7c59d"
-    //     )
-    // }
+
+    #[test]
+    fn test_app_verify() {
+        let info = App::verify(
+            "id=114514&appkey=1d8b6e7d45233436&mobi_app=android&sign=a21165fe7c05a50c89f5e315b49a5043",
+        )
+        .unwrap();
+        assert_eq!(info.name, "android");
+
+        // Signed with sorted params
+        assert!(App::verify(
+            "mobi_app=android&id=114514&sign=204207a9c8f21dbf1d2733d3111abd4e&appkey=1d8b6e7d45233436"
+        )
+        .is_ok());
+
+        let verify_err = |query: &str| {
+            App::verify(query)
+                .unwrap_err()
+                .downcast::<ServerError>()
+                .unwrap()
+        };
+        assert!(matches!(
+            verify_err("id=114514&appkey=1d8b6e7d45233436&mobi_app=android&sign=00000000000000000000000000000000"),
+            ServerError::FatalUrlSignInvalid
+        ));
+        assert!(matches!(
+            verify_err("id=114514&appkey=1d8b6e7d45233436"),
+            ServerError::FatalUrlSignInvalid
+        ));
+        assert!(matches!(
+            verify_err("id=114514&appkey=0000000000000000&sign=a21165fe7c05a50c89f5e315b49a5043"),
+            ServerError::ReqAppkeyInvalid
+        ));
+        assert!(matches!(
+            verify_err("id=114514&appkey=1d8b6e7d45233436&mobi_app=iphone&sign=3c6f2e5e6b323deecf142c4b3ccf9293"),
+            ServerError::ReqAppkeyNotMatch
+        ));
+    }
+
+    #[test]
+    fn test_web() {
+        let d = BiliTicket::Web { ts: 1705658461 }.sign_hex();
+        assert_eq!(
+            d.as_str(),
+            "3c22306bd1ec1227b9d07270c6846a58488b9c554eecdf2a40ae518b25f7c59d"
+        )
+    }
 }
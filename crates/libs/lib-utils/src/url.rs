@@ -2,7 +2,7 @@ use anyhow::Result;
 use std::borrow::Cow;
 
 use super::error::ServerError;
-use super::sign::{Signer, Wbi};
+use super::sign::{App, AppkeyInfo, SignErr, Signer, Wbi};
 use crate::{now, str_concat};
 
 #[derive(Debug)]
@@ -68,47 +68,61 @@ impl<'q> QueryBuilder<'q> {
             }
             Signer::Wbi { img_key, sub_key } => {
                 let mixin_key = Wbi::gen_mixin_key(img_key, sub_key)?;
+                Ok(self.wbi_sign(&mixin_key))
+            }
+            Signer::WbiMixinKey { mixin_key } => Ok(self.wbi_sign(mixin_key)),
+            Signer::App { appkey } => {
+                let info = AppkeyInfo::from_appkey(appkey)
+                    .ok_or_else(|| SignErr::UnknownAppkey(appkey.to_owned()))?;
 
-                let wts = if cfg!(test) {
-                    "1703513649".to_owned()
-                } else {
-                    now!().as_secs().to_string()
-                };
-
-                let wts_param = str_concat!("wts=", &wts);
-                self.parameters.push(("wts", wts.into()));
+                // The one signed with is the one sent
+                self.parameters.retain(|(k, _)| *k != "appkey");
+                self.parameters.push(("appkey", appkey.into()));
 
                 let unsigned_query = encode_parameters(&mut self.parameters, true);
 
-                let w_rid = Wbi::gen_w_rid(&unsigned_query, &mixin_key);
-
-                // `wts`, `w_rid` should add to the end of unsigned query.
-                // With this we needn't encode parameters twice.
-                let signed_query = {
-                    // `wts_param` will and will only appear once in unsigned_query
-                    let (mut start, part) =
-                        unsigned_query.match_indices(&wts_param).next().unwrap();
-                    let mut part_len = part.len();
-                    // `start` > 0 then not the first, should also remove `&` before `wts`
-                    if start != 0 {
-                        start -= 1;
-                        part_len += 1;
-                    }
-                    // SAFE: Will not out of bound
-                    str_concat!(
-                        unsafe { unsigned_query.get_unchecked(0..start) },
-                        unsafe {
-                            unsigned_query.get_unchecked((start + part_len)..unsigned_query.len())
-                        },
-                        "&w_rid=",
-                        &w_rid,
-                        "&",
-                        &wts_param
-                    )
-                };
-
-                Ok(signed_query)
+                let sign = App::gen_sign(&unsigned_query, info.appsec);
+
+                Ok(str_concat!(&unsigned_query, "&sign=", &sign))
+            }
+        }
+    }
+
+    /// Sign with WBI mixin key, appending `w_rid` and `wts`.
+    fn wbi_sign(mut self, mixin_key: &str) -> String {
+        let wts = if cfg!(test) {
+            "1703513649".to_owned()
+        } else {
+            now!().as_secs().to_string()
+        };
+
+        let wts_param = str_concat!("wts=", &wts);
+        self.parameters.push(("wts", wts.into()));
+
+        let unsigned_query = encode_parameters(&mut self.parameters, true);
+
+        let w_rid = Wbi::gen_w_rid(&unsigned_query, mixin_key);
+
+        // `wts`, `w_rid` should add to the end of unsigned query.
+        // With this we needn't encode parameters twice.
+        {
+            // `wts_param` will and will only appear once in unsigned_query
+            let (mut start, part) = unsigned_query.match_indices(&wts_param).next().unwrap();
+            let mut part_len = part.len();
+            // `start` > 0 then not the first, should also remove `&` before `wts`
+            if start != 0 {
+                start -= 1;
+                part_len += 1;
             }
+            // SAFE: Will not out of bound
+            str_concat!(
+                unsafe { unsigned_query.get_unchecked(0..start) },
+                unsafe { unsigned_query.get_unchecked((start + part_len)..unsigned_query.len()) },
+                "&w_rid=",
+                &w_rid,
+                "&",
+                &wts_param
+            )
         }
     }
 }
@@ -178,6 +192,64 @@ pub mod test {
 
         assert_eq!(signed_url, "mid=11997177&platform=web&token=&web_location=1550101&w_rid=7d4428b3f2f9ee2811e116ec6fd41a4f&wts=1703513649");
     }
+
+    #[test]
+    fn test_wbi_mixin_key_sign() {
+        let mixin_key = Wbi::gen_mixin_key(
+            "7cd084941338484aae1ad9425b84077c",
+            "4932caff0ff746eab6f01bf08b70ac45",
+        )
+        .unwrap();
+
+        let signed_url = QueryBuilder::new(vec![("mid", "11997177".into())])
+            .add_param("token", "")
+            .add_param("platform", "web")
+            .add_param("web_location", "1550101")
+            .with_signer(Signer::WbiMixinKey {
+                mixin_key: &mixin_key,
+            })
+            .build()
+            .unwrap();
+
+        assert_eq!(signed_url, "mid=11997177&platform=web&token=&web_location=1550101&w_rid=7d4428b3f2f9ee2811e116ec6fd41a4f&wts=1703513649");
+    }
+
+    #[test]
+    fn test_app_sign() {
+        let parameters = vec![
+            ("id", "114514".into()),
+            ("str", "1919810".into()),
+            ("test", "いいよ，こいよ".into()),
+        ];
+
+        let signed_url = QueryBuilder::new(parameters)
+            .with_signer(Signer::App {
+                appkey: "1d8b6e7d45233436",
+            })
+            .build()
+            .unwrap();
+
+        assert_eq!(signed_url, "appkey=1d8b6e7d45233436&id=114514&str=1919810&test=%E3%81%84%E3%81%84%E3%82%88%EF%BC%8C%E3%81%93%E3%81%84%E3%82%88&sign=01479cf20504d865519ac50f33ba3a7d");
+        assert!(App::verify(&signed_url).is_ok());
+
+        // Mismatched appkey is overwritten
+        let signed_url = QueryBuilder::new(vec![
+            ("appkey", "27eb53fc9058f8c3".into()),
+            ("id", "114514".into()),
+        ])
+        .with_signer(Signer::App {
+            appkey: "1d8b6e7d45233436",
+        })
+        .build()
+        .unwrap();
+        assert!(!signed_url.contains("27eb53fc9058f8c3"));
+        assert_eq!(App::verify(&signed_url).unwrap().name, "android");
+
+        assert!(QueryBuilder::default()
+            .with_signer(Signer::App { appkey: "unknown" })
+            .build()
+            .is_err());
+    }
 }
 
 /// For faster url query parsing usage onlly
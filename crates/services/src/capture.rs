@@ -0,0 +1,605 @@
+//! Capture of upstream request/response pairs for debugging, see
+//! [CaptureConfig](lib_core::server::config::CaptureConfig).
+//!
+//! Sensitive headers, query values and fields of form or JSON bodies are
+//! redacted, and gRPC messages are decoded into JSON without schema.
+
+pub(crate) mod proto;
+pub(crate) mod store;
+
+use anyhow::Result;
+use axum::{
+    body::Body, extract::Request as AxumRequest, middleware::Next,
+    response::Response as AxumResponse,
+};
+use bytes::{Bytes, BytesMut};
+use http::{header::CONTENT_TYPE, Extensions, HeaderMap};
+use http_body_util::BodyExt;
+use lib_rpc::request::utils::RawResponseExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::collections::{btree_map::Entry, BTreeMap};
+
+use crate::intercept::grpc::GrpcFrames;
+use lib_core::server::config::CONFIG_CAPTURE;
+use lib_utils::{b64_encode, now};
+
+/// Header to trigger capturing a request, or to browse captures, with an
+/// admin token as value.
+pub(crate) const CAPTURE_HEADER: &str = "x-roamingh-capture";
+
+/// Headers always redacted
+const REDACTED_HEADERS: [&str; 5] = [
+    "cookie",
+    "set-cookie",
+    "authorization",
+    "x-bili-metadata-bin",
+    CAPTURE_HEADER,
+];
+
+/// Query keys and fields of form or JSON bodies always redacted
+const REDACTED_KEYS: [&str; 5] = [
+    "access_key",
+    "access_token",
+    "refresh_token",
+    "csrf",
+    "password",
+];
+
+const REDACTED: &str = "<redacted>";
+
+/// Middleware removing [CAPTURE_HEADER] from all requests so that it never
+/// reaches the upstream, marking requests from admins with [Admin] and those
+/// to be captured with [Capture].
+pub async fn capture_middleware(mut req: AxumRequest, next: Next) -> AxumResponse {
+    let by_admin = is_admin(req.headers());
+    req.headers_mut().remove(CAPTURE_HEADER);
+
+    if by_admin {
+        req.extensions_mut().insert(Admin);
+    }
+    if let Some(config) = CONFIG_CAPTURE.get() {
+        if config.enabled || by_admin {
+            let capture = Capture {
+                route: req.uri().path().to_owned(),
+            };
+            req.extensions_mut().insert(capture);
+        }
+    }
+
+    next.run(req).await
+}
+
+/// Whether the request is from an admin, by [CAPTURE_HEADER].
+///
+/// Tokens are compared in constant time, all of them checked.
+fn is_admin(headers: &HeaderMap) -> bool {
+    let Some(config) = CONFIG_CAPTURE.get() else {
+        return false;
+    };
+    let Some(token) = headers.get(CAPTURE_HEADER) else {
+        return false;
+    };
+
+    config
+        .admin_tokens
+        .iter()
+        .filter(|admin| !admin.is_empty())
+        .fold(false, |matched, admin| {
+            matched | constant_time_eq(admin.as_bytes(), token.as_bytes())
+        })
+}
+
+/// Compare without returning early at the first byte differing, so that the
+/// time taken reveals nothing but the length.
+#[inline(never)]
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let diff = a.iter().zip(b).fold(0u8, |diff, (a, b)| diff | (a ^ b));
+    std::hint::black_box(diff) == 0
+}
+
+/// Marker in extensions of a request from an admin, inserted by
+/// [capture_middleware].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Admin;
+
+/// Marker in extensions of a request whose exchange with the upstream is to
+/// be captured, inserted by [capture_middleware] or for a route with
+/// `capture` set.
+#[derive(Debug, Clone)]
+pub(crate) struct Capture {
+    /// Prefix of the route, or path of the request if not routed by prefix
+    route: String,
+}
+
+impl Capture {
+    /// Check whether to capture the request of given route, marked by
+    /// [capture_middleware] or `by_route`, updating the route of the marker.
+    pub(crate) fn check(route: &str, by_route: bool, extensions: &mut Extensions) -> bool {
+        if let Some(capture) = extensions.get_mut::<Self>() {
+            capture.route = route.to_owned();
+            return true;
+        }
+        if !by_route || CONFIG_CAPTURE.get().is_none() {
+            return false;
+        }
+
+        extensions.insert(Self {
+            route: route.to_owned(),
+        });
+        true
+    }
+
+    /// Save the capture in background.
+    pub(crate) fn save(
+        self,
+        request: CapturedRequest,
+        response: Result<CapturedResponse, &anyhow::Error>,
+    ) {
+        match response {
+            Ok(response) => self.save_record(request, Some(response), None),
+            Err(e) => self.save_record(request, None, Some(format!("{:#}", e))),
+        }
+    }
+
+    fn save_record(
+        self,
+        request: CapturedRequest,
+        response: Option<CapturedResponse>,
+        error: Option<String>,
+    ) {
+        let store = store::store();
+        let record = CaptureRecord {
+            id: store.next_id(),
+            timestamp: now!().as_millis() as u64,
+            route: self.route,
+            request,
+            response,
+            error,
+        };
+
+        tracing::debug!("Capture [{}] of [{}]", record.id, record.request.url);
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = store.save(&record) {
+                tracing::error!("Failed to save capture [{}]: {}", record.id, e);
+            }
+        });
+    }
+
+    /// Capture the exchange of a RESTful request, returning the response as is
+    /// and the [CaptureTee] to pipe its body through.
+    pub(crate) fn rest(
+        self,
+        mut request: CapturedRequest,
+        result: Result<RawResponseExt>,
+    ) -> Result<(RawResponseExt, CaptureTee)> {
+        let raw = match result {
+            Ok(raw) => raw,
+            Err(e) => {
+                self.save(request, Err(&e));
+                return Err(e);
+            }
+        };
+
+        // What's actually sent
+        request.url = redact_url(raw.o_req().url().as_str());
+        request.proxy = raw.o_proxy().cloned();
+        request.headers = captured_headers(raw.o_req().headers());
+
+        let tee = CaptureTee {
+            capture: Some((self, request)),
+            status: raw.inner().status().as_u16(),
+            headers: raw.inner().headers().clone(),
+            body: BytesMut::new(),
+            size: 0,
+            max_body_size: CONFIG_CAPTURE.get().map_or(usize::MAX, |c| c.max_body_size),
+        };
+
+        Ok((raw, tee))
+    }
+}
+
+/// Copy of a response body piped through, no more than `max_body_size`, the
+/// capture saved with it once the body is dropped, fully piped or not.
+#[derive(Debug)]
+pub(crate) struct CaptureTee {
+    capture: Option<(Capture, CapturedRequest)>,
+    status: u16,
+    headers: HeaderMap,
+    body: BytesMut,
+    /// Size of the body piped so far
+    size: usize,
+    max_body_size: usize,
+}
+
+impl CaptureTee {
+    /// Pipe given body through, unchanged.
+    pub(crate) fn pipe(mut self, body: Body) -> Body {
+        Body::new(body.map_frame(move |frame| {
+            if let Some(data) = frame.data_ref() {
+                self.push(data);
+            }
+            frame
+        }))
+    }
+
+    fn push(&mut self, data: &Bytes) {
+        self.size += data.len();
+        if self.size <= self.max_body_size {
+            self.body.extend_from_slice(data);
+        } else if !self.body.is_empty() {
+            self.body = BytesMut::new();
+        }
+    }
+
+    fn captured_response(&mut self) -> CapturedResponse {
+        let body = std::mem::take(&mut self.body).freeze();
+        let mut response = CapturedResponse::new(self.status, &self.headers, None, &body);
+        if self.size > self.max_body_size {
+            response.body = CapturedBody::Omitted(self.size);
+        }
+        response
+    }
+}
+
+impl Drop for CaptureTee {
+    fn drop(&mut self) {
+        if let Some((capture, request)) = self.capture.take() {
+            let response = self.captured_response();
+            capture.save_record(request, Some(response), None);
+        }
+    }
+}
+
+/// A captured exchange with the upstream
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CaptureRecord {
+    /// Unique id, sortable by time
+    pub id: String,
+    /// Millis since UNIX epoch
+    pub timestamp: u64,
+    /// Prefix of the route
+    pub route: String,
+    pub request: CapturedRequest,
+    /// `None` if failed, see `error`
+    pub response: Option<CapturedResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CapturedRequest {
+    pub method: String,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    pub headers: BTreeMap<String, String>,
+    pub body: CapturedBody,
+}
+
+impl CapturedRequest {
+    pub(crate) fn new(method: &str, url: &str, headers: &HeaderMap, body: &Bytes) -> Self {
+        Self {
+            method: method.to_owned(),
+            url: redact_url(url),
+            proxy: None,
+            headers: captured_headers(headers),
+            body: CapturedBody::new(headers, body),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CapturedResponse {
+    pub status: u16,
+    /// `grpc-status` in trailers, or in headers of a trailers-only response
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grpc_status: Option<i32>,
+    pub headers: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trailers: Option<BTreeMap<String, String>>,
+    pub body: CapturedBody,
+}
+
+impl CapturedResponse {
+    pub(crate) fn new(
+        status: u16,
+        headers: &HeaderMap,
+        trailers: Option<&HeaderMap>,
+        body: &Bytes,
+    ) -> Self {
+        let grpc_status = trailers
+            .and_then(|trailers| trailers.get("grpc-status"))
+            .or_else(|| headers.get("grpc-status"))
+            .and_then(|status| status.to_str().ok()?.parse().ok());
+
+        Self {
+            status,
+            grpc_status,
+            headers: captured_headers(headers),
+            trailers: trailers.map(captured_headers),
+            body: CapturedBody::new(headers, body),
+        }
+    }
+}
+
+/// Body captured, decoded by content type
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub(crate) enum CapturedBody {
+    Empty,
+    Json(Value),
+    Text(String),
+    /// gRPC messages, decoded without schema, see [proto::decode]
+    Grpc(Vec<Value>),
+    /// Base64 encoded
+    Binary(String),
+    /// Larger than `max_body_size`, with the size in bytes
+    Omitted(usize),
+}
+
+impl CapturedBody {
+    /// Decode the body by `content-type` in given headers.
+    pub(crate) fn new(headers: &HeaderMap, body: &Bytes) -> Self {
+        let max_body_size = CONFIG_CAPTURE.get().map_or(usize::MAX, |c| c.max_body_size);
+
+        if body.is_empty() {
+            return Self::Empty;
+        }
+        if body.len() > max_body_size {
+            return Self::Omitted(body.len());
+        }
+
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|t| t.to_str().ok())
+            .unwrap_or_default();
+
+        if content_type.starts_with("application/grpc") {
+            let gzip = headers.get("grpc-encoding").is_some_and(|e| e == "gzip");
+            if let Ok(frames) = GrpcFrames::decode(body.clone(), gzip) {
+                return Self::Grpc(frames.messages().iter().map(|m| proto::decode(m)).collect());
+            }
+        } else if content_type.contains("json") {
+            if let Ok(mut json) = serde_json::from_slice(body) {
+                redact_json(&mut json);
+                return Self::Json(json);
+            }
+        } else if content_type.starts_with("application/x-www-form-urlencoded") {
+            if let Ok(form) = std::str::from_utf8(body) {
+                return Self::Text(redact_query(form));
+            }
+        }
+
+        match std::str::from_utf8(body) {
+            Ok(text) => Self::Text(text.to_owned()),
+            Err(_) => Self::Binary(b64_encode!(body)),
+        }
+    }
+}
+
+#[inline]
+fn is_redacted(key: &str, built_in: &[&str]) -> bool {
+    built_in.iter().any(|k| key.eq_ignore_ascii_case(k))
+        || CONFIG_CAPTURE
+            .get()
+            .is_some_and(|c| c.redact.iter().any(|k| key.eq_ignore_ascii_case(k)))
+}
+
+/// Headers with sensitive values redacted, values of the same name joined.
+fn captured_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    let mut captured = BTreeMap::new();
+
+    for (name, value) in headers {
+        let value = if is_redacted(name.as_str(), &REDACTED_HEADERS) {
+            REDACTED.to_owned()
+        } else {
+            String::from_utf8_lossy(value.as_bytes()).into_owned()
+        };
+
+        match captured.entry(name.as_str().to_owned()) {
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+            }
+            Entry::Occupied(mut entry) => {
+                let joined = entry.get_mut();
+                joined.push_str(", ");
+                joined.push_str(&value);
+            }
+        }
+    }
+
+    captured
+}
+
+/// URL with sensitive query values redacted.
+fn redact_url(url: &str) -> String {
+    match url.split_once('?') {
+        Some((base, query)) => format!("{}?{}", base, redact_query(query)),
+        None => url.to_owned(),
+    }
+}
+
+/// Query or urlencoded form with sensitive values redacted.
+fn redact_query(query: &str) -> String {
+    query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((key, _)) if is_redacted(key, &REDACTED_KEYS) => format!("{}={}", key, REDACTED),
+            _ => pair.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Redact values of sensitive keys in all objects of the JSON.
+fn redact_json(json: &mut Value) {
+    match json {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if is_redacted(key, &REDACTED_KEYS) {
+                    *value = Value::from(REDACTED);
+                } else {
+                    redact_json(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        assert_eq!(
+            redact_url("https://app.bilibili.com/x/v2/view?access_key=abc&aid=1&csrf=def"),
+            "https://app.bilibili.com/x/v2/view?access_key=<redacted>&aid=1&csrf=<redacted>"
+        );
+        assert_eq!(redact_url("https://a.com/x"), "https://a.com/x");
+
+        let mut headers = HeaderMap::new();
+        headers.insert("cookie", HeaderValue::from_static("SESSDATA=abc"));
+        headers.insert("x-roamingh-capture", HeaderValue::from_static("token"));
+        headers.append("accept", HeaderValue::from_static("text/html"));
+        headers.append("accept", HeaderValue::from_static("application/json"));
+
+        let captured = captured_headers(&headers);
+        assert_eq!(captured["cookie"], REDACTED);
+        assert_eq!(captured["x-roamingh-capture"], REDACTED);
+        assert_eq!(captured["accept"], "text/html, application/json");
+    }
+
+    #[test]
+    fn test_redact_body() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/x-www-form-urlencoded"),
+        );
+        let body = CapturedBody::new(
+            &headers,
+            &Bytes::from_static(b"aid=1&access_key=abc&csrf=def"),
+        );
+        assert!(
+            matches!(body, CapturedBody::Text(text) if text == "aid=1&access_key=<redacted>&csrf=<redacted>")
+        );
+
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let body = CapturedBody::new(
+            &headers,
+            &Bytes::from_static(
+                br#"{"code":0,"data":{"access_token":"abc","list":[{"refresh_token":"def","mid":1}]}}"#,
+            ),
+        );
+        let CapturedBody::Json(json) = body else {
+            panic!("Should be JSON");
+        };
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "code": 0,
+                "data": {
+                    "access_token": REDACTED,
+                    "list": [{ "refresh_token": REDACTED, "mid": 1 }]
+                }
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_capture_middleware() {
+        use tower::ServiceExt;
+
+        let app = axum::Router::new()
+            .route(
+                "/x/v2/view",
+                axum::routing::get(|req: AxumRequest| async move {
+                    format!(
+                        "{},{}",
+                        req.headers().contains_key(CAPTURE_HEADER),
+                        req.extensions().get::<Admin>().is_some()
+                    )
+                }),
+            )
+            .layer(axum::middleware::from_fn(capture_middleware));
+
+        // Never reaches handlers, nor the upstream behind them
+        let request = http::Request::get("/x/v2/view")
+            .header(CAPTURE_HEADER, "token")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "false,false");
+    }
+
+    #[tokio::test]
+    async fn test_capture_tee() {
+        let tee = || CaptureTee {
+            capture: None,
+            status: 200,
+            headers: HeaderMap::new(),
+            body: BytesMut::new(),
+            size: 0,
+            max_body_size: 4,
+        };
+
+        // Piped as is
+        let body = tee().pipe(Body::from("abcdef"));
+        let body = body.collect().await.unwrap().to_bytes();
+        assert_eq!(body, "abcdef");
+
+        let mut tee = tee();
+        tee.push(&Bytes::from_static(b"ab"));
+        tee.push(&Bytes::from_static(b"cd"));
+        assert!(matches!(tee.captured_response().body, CapturedBody::Text(text) if text == "abcd"));
+
+        // Omitted once larger than `max_body_size`, never failed
+        tee.push(&Bytes::from_static(b"e"));
+        assert!(matches!(
+            tee.captured_response().body,
+            CapturedBody::Omitted(5)
+        ));
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"token", b"token"));
+        assert!(!constant_time_eq(b"token", b"tokem"));
+        assert!(!constant_time_eq(b"token", b"token1"));
+        assert!(!constant_time_eq(b"token", b""));
+    }
+
+    #[test]
+    fn test_captured_grpc_status() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", HeaderValue::from_static("7"));
+
+        let response = CapturedResponse::new(200, &headers, Some(&trailers), &Bytes::new());
+        assert_eq!(response.grpc_status, Some(7));
+
+        // Trailers-only
+        headers.insert("grpc-status", HeaderValue::from_static("14"));
+        let response = CapturedResponse::new(200, &headers, None, &Bytes::new());
+        assert_eq!(response.grpc_status, Some(14));
+
+        headers.clear();
+        let response = CapturedResponse::new(200, &headers, None, &Bytes::new());
+        assert_eq!(response.grpc_status, None);
+    }
+}
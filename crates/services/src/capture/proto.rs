@@ -0,0 +1,142 @@
+use lib_utils::b64_encode;
+use serde_json::{map::Entry, Map, Value};
+
+/// Max depth of nested messages to be decoded
+const MAX_DEPTH: usize = 16;
+
+/// Decode a protobuf message without schema into JSON, like `protoc --decode_raw`.
+///
+/// Fields are keyed by field numbers, with values of repeated ones in arrays.
+/// Length-delimited values are decoded as printable strings, nested messages
+/// or base64 encoded bytes in order.
+///
+/// Base64 encoded data is returned if it's not a valid message.
+pub(crate) fn decode(data: &[u8]) -> Value {
+    match decode_message(data, 0) {
+        Some(fields) => Value::Object(fields),
+        None => Value::String(b64_encode!(data)),
+    }
+}
+
+fn decode_message(mut data: &[u8], depth: usize) -> Option<Map<String, Value>> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+
+    let mut fields = Map::new();
+    while !data.is_empty() {
+        let key = read_varint(&mut data)?;
+        let number = key >> 3;
+        if number == 0 {
+            return None;
+        }
+
+        let value = match key & 0b111 {
+            // VARINT
+            0 => Value::from(read_varint(&mut data)?),
+            // I64
+            1 => Value::from(u64::from_le_bytes(
+                read_bytes(&mut data, 8)?.try_into().ok()?,
+            )),
+            // LEN
+            2 => {
+                let len = usize::try_from(read_varint(&mut data)?).ok()?;
+                decode_bytes(read_bytes(&mut data, len)?, depth)
+            }
+            // I32
+            5 => Value::from(u32::from_le_bytes(
+                read_bytes(&mut data, 4)?.try_into().ok()?,
+            )),
+            // Deprecated groups or invalid
+            _ => return None,
+        };
+
+        match fields.entry(number.to_string()) {
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+            }
+            Entry::Occupied(mut entry) => match entry.get_mut() {
+                Value::Array(values) => values.push(value),
+                existing => {
+                    let first = existing.take();
+                    *existing = Value::Array(vec![first, value]);
+                }
+            },
+        }
+    }
+
+    Some(fields)
+}
+
+fn decode_bytes(data: &[u8], depth: usize) -> Value {
+    if let Ok(text) = std::str::from_utf8(data) {
+        if text.chars().all(|c| !c.is_control() || c.is_whitespace()) {
+            return Value::String(text.to_owned());
+        }
+    }
+
+    match decode_message(data, depth + 1) {
+        Some(fields) if !fields.is_empty() => Value::Object(fields),
+        _ => Value::String(b64_encode!(data)),
+    }
+}
+
+#[inline]
+fn read_varint(data: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = data.split_first()?;
+        *data = rest;
+
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+#[inline]
+fn read_bytes<'d>(data: &mut &'d [u8], len: usize) -> Option<&'d [u8]> {
+    if data.len() < len {
+        return None;
+    }
+    let (bytes, rest) = data.split_at(len);
+    *data = rest;
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let data = [
+            // 1: 150
+            0x08, 0x96, 0x01, //
+            // 2: "hi"
+            0x12, 0x02, b'h', b'i', //
+            // 3: { 1: 1 }, repeated
+            0x1a, 0x02, 0x08, 0x01, //
+            0x1a, 0x02, 0x08, 0x02, //
+            // 5: fixed32 1
+            0x2d, 0x01, 0x00, 0x00, 0x00,
+        ];
+
+        assert_eq!(
+            decode(&data),
+            json!({
+                "1": 150,
+                "2": "hi",
+                "3": [{ "1": 1 }, { "1": 2 }],
+                "5": 1
+            })
+        );
+
+        // Truncated
+        assert_eq!(decode(&[0x12, 0x05, b'h']), json!("EgVo"));
+    }
+}
@@ -0,0 +1,179 @@
+use anyhow::Result;
+use lib_utils::now;
+use serde_json::Value;
+
+use std::{
+    io::ErrorKind,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        OnceLock,
+    },
+};
+
+use super::CaptureRecord;
+use lib_core::server::config::CONFIG_CAPTURE;
+
+static STORE: OnceLock<CaptureStore> = OnceLock::new();
+
+/// The store of captures in config.
+pub(crate) fn store() -> &'static CaptureStore {
+    STORE.get_or_init(|| {
+        let config = CONFIG_CAPTURE.get().cloned().unwrap_or_default();
+        CaptureStore::new(config.dir, config.max_files)
+    })
+}
+
+/// Captures saved on disk, one JSON file each named by its id.
+///
+/// Ids are sortable by time, and the oldest captures are removed once more
+/// than `max_files` kept.
+#[derive(Debug)]
+pub(crate) struct CaptureStore {
+    dir: PathBuf,
+    max_files: usize,
+    seq: AtomicU32,
+}
+
+impl CaptureStore {
+    pub(crate) fn new(dir: impl Into<PathBuf>, max_files: usize) -> Self {
+        Self {
+            dir: dir.into(),
+            max_files,
+            seq: AtomicU32::new(0),
+        }
+    }
+
+    /// Generate a new id, millis since UNIX epoch and a sequence number.
+    pub(crate) fn next_id(&self) -> String {
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed) % 1_000_000;
+        format!("{:013}-{:06}", now!().as_millis(), seq)
+    }
+
+    #[inline]
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    /// Save the capture and remove the oldest ones, blocking.
+    pub(crate) fn save(&self, record: &CaptureRecord) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(self.path(&record.id), serde_json::to_vec_pretty(record)?)?;
+
+        let ids = self.ids()?;
+        if ids.len() > self.max_files {
+            for id in &ids[..ids.len() - self.max_files] {
+                if let Err(e) = std::fs::remove_file(self.path(id)) {
+                    tracing::warn!("Failed to remove capture [{}]: {}", id, e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Ids of captures kept, in ascending order.
+    fn ids(&self) -> Result<Vec<String>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut ids: Vec<String> = entries
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name().into_string().ok()?;
+                let id = name.strip_suffix(".json")?;
+                is_valid_id(id).then(|| id.to_owned())
+            })
+            .collect();
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    /// Summaries of the latest `limit` captures, the latest first, blocking.
+    pub(crate) fn list(&self, limit: usize) -> Result<Vec<Value>> {
+        let summaries = self
+            .ids()?
+            .iter()
+            .rev()
+            .take(limit)
+            .filter_map(|id| {
+                let record = self.get(id).ok()??;
+                Some(serde_json::json!({
+                    "id": record["id"],
+                    "timestamp": record["timestamp"],
+                    "route": record["route"],
+                    "method": record["request"]["method"],
+                    "url": record["request"]["url"],
+                    "status": record["response"]["status"],
+                    "error": record["error"],
+                }))
+            })
+            .collect();
+        Ok(summaries)
+    }
+
+    /// Get the capture of given id, `None` if not found, blocking.
+    pub(crate) fn get(&self, id: &str) -> Result<Option<Value>> {
+        if !is_valid_id(id) {
+            return Ok(None);
+        }
+
+        match std::fs::read(self.path(id)) {
+            Ok(content) => Ok(Some(serde_json::from_slice(&content)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Ids are digits and `-` only, never a path.
+#[inline]
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit() || b == b'-')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::capture::{CapturedBody, CapturedRequest};
+
+    #[test]
+    fn test_capture_store() {
+        let dir = std::env::temp_dir().join(format!("captures-{}", std::process::id()));
+        let store = CaptureStore::new(&dir, 2);
+
+        let ids: Vec<String> = (0..3)
+            .map(|_| {
+                let record = CaptureRecord {
+                    id: store.next_id(),
+                    timestamp: 0,
+                    route: "/x/".to_owned(),
+                    request: CapturedRequest {
+                        method: "GET".to_owned(),
+                        url: "https://api.bilibili.com/x/v2/test".to_owned(),
+                        proxy: None,
+                        headers: Default::default(),
+                        body: CapturedBody::Empty,
+                    },
+                    response: None,
+                    error: Some("error".to_owned()),
+                };
+                store.save(&record).unwrap();
+                record.id
+            })
+            .collect();
+
+        // The oldest one removed
+        assert!(store.get(&ids[0]).unwrap().is_none());
+        assert_eq!(store.get(&ids[2]).unwrap().unwrap()["error"], "error");
+        assert!(store.get("../captures").unwrap().is_none());
+
+        let list = store.list(10).unwrap();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0]["id"], ids[2].as_str());
+        assert_eq!(list[0]["url"], "https://api.bilibili.com/x/v2/test");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
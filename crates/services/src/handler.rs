@@ -1,4 +1,10 @@
+pub mod capture;
+pub mod errors;
+pub mod passthrough;
 pub mod playurl;
+pub mod proxy;
+pub mod purify;
+pub mod status;
 pub mod test;
 pub mod test_intercept;
 
@@ -13,7 +19,8 @@ use lib_utils::error::ServerErrorExt;
 use std::{fmt::Debug as FmtDebug, future::Future};
 
 use crate::{
-    intercept::{DefaultInterceptor, InterceptT},
+    intercept::{DefaultInterceptor, InterceptT, InterceptUri},
+    rate_limit::{CacheStatus, RateLimit},
     HandlerFuture,
 };
 use lib_utils::error::ServerError;
@@ -34,11 +41,32 @@ pub trait HandlerT: 'static + Sized + FmtDebug + Clone + Send {
             self.call(req)
                 .await
                 .map(|resp| resp.into_response())
-                .unwrap_or_else(|err| ServerErrorExt::from(err).into_response())
+                .unwrap_or_else(error_response)
         }
     }
 }
 
+/// Marks a response synthesized for an error instead of from upstream, e.g.
+/// one rate limited or failed in interceptors, never cached even if its status
+/// is `200` by the [StatusPolicy](lib_utils::model::response::StatusPolicy).
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ErrorResponse;
+
+/// Response of given error, marked as [ErrorResponse].
+pub(crate) fn error_response(e: anyhow::Error) -> AxumResponse {
+    let mut response = ServerErrorExt::from(e).into_response();
+    response.extensions_mut().insert(ErrorResponse);
+    response
+}
+
+/// Fallback of routes for methods not handled, responding
+/// [ServerError::MethodNotAllowed] instead of the empty `405` of axum, see
+/// [generate_router](crate::generate_router).
+pub async fn method_not_allowed(method: http::Method, uri: http::Uri) -> AxumResponse {
+    tracing::warn!("Method [{}] not allowed for [{}]", method, uri.path());
+    error_response(anyhow::anyhow!(ServerError::MethodNotAllowed))
+}
+
 #[derive(Default, Debug, Clone, Copy)]
 pub struct DefaultHandler;
 
@@ -99,20 +127,33 @@ impl<T, S, R: InterceptT, H: HandlerT> axum::handler::Handler<T, S> for Intercep
     fn call(self, mut req: AxumRequest, _state: S) -> Self::Future {
         Box::pin(async move {
             if let Some(interceptor) = &self.interceptor {
-                if let Err(e) = interceptor.intercept_request(&mut req).await {
-                    return ServerErrorExt::from(e).into_response();
+                match interceptor.intercept_request(&mut req).await {
+                    Ok(Some(response)) => {
+                        tracing::debug!("Request short-circuited by interceptor");
+                        return response;
+                    }
+                    Err(e) => {
+                        return error_response(e);
+                    }
+                    _ => {}
                 }
             }
 
+            if let Err(response) = RateLimit::check(req.extensions(), CacheStatus::Miss) {
+                return response;
+            }
+
+            let uri = req.uri().clone();
             let mut response = self.handler.call_for_response(req).await;
 
             if let Some(interceptor) = &self.interceptor {
+                response.extensions_mut().insert(InterceptUri(uri));
                 match interceptor.intercept_response(&mut response).await {
                     Ok(Some(new_response)) => {
                         response = new_response;
                     }
                     Err(e) => {
-                        return ServerErrorExt::from(e).into_response();
+                        return error_response(e);
                     }
                     _ => {}
                 }
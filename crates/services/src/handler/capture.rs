@@ -0,0 +1,79 @@
+use anyhow::Result;
+use axum::{
+    extract::Request as AxumRequest,
+    http::StatusCode,
+    response::{IntoResponse, Response as AxumResponse},
+};
+use serde_json::Value;
+
+use crate::{
+    axum_response,
+    capture::{store::store, Admin},
+    generate_router, HandlerFuture,
+};
+use lib_utils::error::ServerError;
+
+/// Max count of captures listed by default
+const LIST_LIMIT: usize = 50;
+
+generate_router!(
+    CaptureRouter,
+    ("/admin/captures", GET, CaptureHandler),
+    ("/admin/captures/:id", GET, CaptureHandler)
+);
+
+/// Handler for browsing captures, for admins only, see
+/// [CaptureConfig](lib_core::server::config::CaptureConfig).
+///
+/// - `/admin/captures?limit=50`: summaries of the latest captures
+/// - `/admin/captures/:id`: the capture of given id
+#[derive(Debug, Clone)]
+struct CaptureHandler;
+
+impl<T, S> axum::handler::Handler<T, S> for CaptureHandler {
+    type Future = HandlerFuture;
+
+    #[tracing::instrument(level = "debug", name = "CaptureHandler.call", skip(self, _state))]
+    fn call(self, req: AxumRequest, _state: S) -> Self::Future {
+        Box::pin(async move {
+            if req.extensions().get::<Admin>().is_none() {
+                tracing::warn!("Unauthorized access to captures");
+                return ServerError::FatalReqInvalid.into_response();
+            }
+
+            let id = req
+                .uri()
+                .path()
+                .strip_prefix("/admin/captures/")
+                .map(ToOwned::to_owned);
+
+            match id {
+                Some(id) => match get(id).await {
+                    Ok(None) => StatusCode::NOT_FOUND.into_response(),
+                    result => axum_response!(result.map(Option::unwrap_or_default)),
+                },
+                None => {
+                    let limit = req
+                        .uri()
+                        .query()
+                        .and_then(|query| {
+                            query
+                                .split('&')
+                                .find_map(|pair| pair.strip_prefix("limit="))
+                        })
+                        .and_then(|limit| limit.parse().ok())
+                        .unwrap_or(LIST_LIMIT);
+                    axum_response!(list(limit).await)
+                }
+            }
+        })
+    }
+}
+
+async fn get(id: String) -> Result<Option<Value>> {
+    tokio::task::spawn_blocking(move || store().get(&id)).await?
+}
+
+async fn list(limit: usize) -> Result<Vec<Value>> {
+    tokio::task::spawn_blocking(move || store().list(limit)).await?
+}
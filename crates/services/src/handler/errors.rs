@@ -0,0 +1,24 @@
+use axum::response::IntoResponse;
+
+use crate::{axum_response, generate_router, HandlerFuture};
+use lib_utils::{error::ServerError, locale::Lang};
+
+generate_router!(
+    ErrorCatalogRouter,
+    ("/x/roaming/errors", GET, ErrorCatalogHandler)
+);
+
+/// Handler listing all error codes returned by the server, see
+/// [ErrorSchema](lib_utils::error::ErrorSchema), with messages in the
+/// language of the request.
+#[derive(Debug, Clone)]
+struct ErrorCatalogHandler;
+
+impl<T, S> axum::handler::Handler<T, S> for ErrorCatalogHandler {
+    type Future = HandlerFuture;
+
+    #[tracing::instrument(level = "debug", name = "ErrorCatalogHandler.call", skip(self, _state))]
+    fn call(self, _req: axum::extract::Request, _state: S) -> Self::Future {
+        Box::pin(async move { axum_response!(anyhow::Ok(ServerError::catalog(Lang::current()))) })
+    }
+}
@@ -0,0 +1,238 @@
+use anyhow::Result;
+use axum::{
+    body::{Body, HttpBody},
+    extract::Request as AxumRequest,
+    response::Response as AxumResponse,
+};
+use http_body_util::BodyExt;
+use lib_rpc::{
+    model::response::GrpcRawResponse,
+    request::{
+        interface::{GeneralRpc, RpcBuilderT},
+        passthrough::GrpcPassthroughRpc,
+        ProxyPool, RawBody, ReqBody, Timeouts,
+    },
+    utils::Upstream,
+};
+
+use std::{borrow::Cow, sync::Arc};
+
+use super::HandlerT;
+use crate::{
+    buffer_body,
+    capture::{Capture, CapturedRequest, CapturedResponse},
+    limit_body, retry_policy, upstream_timeouts,
+};
+use lib_utils::model::response::GrpcResponsePassthrough;
+
+/// A handler that passes the original request through to given upstream,
+/// keeping method, path, query, headers and body as is.
+///
+/// Request body is piped to upstream, buffered only when captured, and
+/// response body is piped from upstream without buffering.
+#[derive(Debug, Clone)]
+pub struct PassthroughHandler {
+    upstream: Upstream<'static>,
+    proxy_pool: Option<Arc<ProxyPool>>,
+    /// Timeouts of the route, those of the upstream type in config if not set
+    timeouts: Option<Timeouts>,
+}
+
+impl PassthroughHandler {
+    /// Passthrough to `https://api.bilibili.com`
+    pub const API: Self = Self {
+        upstream: Upstream::API_DEFAULT,
+        proxy_pool: None,
+        timeouts: None,
+    };
+    /// Passthrough to `https://app.bilibili.com`
+    pub const APP: Self = Self {
+        upstream: Upstream::APP_DEFAULT,
+        proxy_pool: None,
+        timeouts: None,
+    };
+
+    #[inline]
+    pub fn new(upstream: impl Into<Upstream<'static>>) -> Self {
+        Self {
+            upstream: upstream.into(),
+            proxy_pool: None,
+            timeouts: None,
+        }
+    }
+
+    /// Request the upstream through proxies picked from given pool
+    #[inline]
+    pub fn with_proxy_pool(mut self, proxy_pool: Option<Arc<ProxyPool>>) -> Self {
+        self.proxy_pool = proxy_pool;
+        self
+    }
+
+    /// Request the upstream with given timeouts
+    #[inline]
+    pub fn with_timeouts(mut self, timeouts: Option<Timeouts>) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+}
+
+impl HandlerT for PassthroughHandler {
+    type Response = AxumResponse;
+
+    #[tracing::instrument(level = "debug", name = "PassthroughHandler.call", skip(self), err)]
+    async fn call(self, req: AxumRequest) -> Result<Self::Response> {
+        let (mut parts, body) = req.into_parts();
+
+        let timeouts = self
+            .timeouts
+            .unwrap_or_else(|| upstream_timeouts(&self.upstream.u_type, Default::default()));
+
+        let (body, capture) = match parts.extensions.remove::<Capture>() {
+            Some(capture) => {
+                let body = buffer_body(body).await?;
+                let url = format!("{}{}", self.upstream.str(), parts.uri);
+                let request =
+                    CapturedRequest::new(parts.method.as_str(), &url, &parts.headers, &body);
+                (Some(ReqBody::from(body)), Some((capture, request)))
+            }
+            // No chunked body for e.g. GET requests
+            None if body.is_end_stream() => (None, None),
+            None => (
+                Some(ReqBody::wrap_stream(limit_body(body)?.into_data_stream())),
+                None,
+            ),
+        };
+
+        let result = GeneralRpc::new((), self.upstream)
+            .with_proxy_pool(self.proxy_pool)
+            .with_retry(retry_policy())
+            .with_timeouts(Some(timeouts))
+            .with_method(parts.method)
+            .with_path(parts.uri.path())
+            .with_query(parts.uri.query().map(Cow::Borrowed))
+            .with_headers(Some(parts.headers))
+            .with_body(body)
+            .execute()
+            .await;
+
+        let (raw, tee) = match capture {
+            Some((capture, request)) => {
+                let (raw, tee) = capture.rest(request, result)?;
+                (raw, Some(tee))
+            }
+            None => (result?, None),
+        };
+
+        let (_, _, resp_headers, stream) = raw.stream()?.into_parts();
+
+        let mut body = Body::from_stream(stream);
+        if let Some(tee) = tee {
+            body = tee.pipe(body);
+        }
+        let mut response = AxumResponse::new(body);
+        *response.headers_mut() = resp_headers;
+        Ok(response)
+    }
+}
+
+/// A handler that passes the original gRPC request through to given upstream,
+/// keeping headers and trailers of the response as is.
+///
+/// Request body is piped to upstream, buffered only when captured.
+#[derive(Debug, Clone)]
+pub struct GrpcPassthroughHandler {
+    upstream: Upstream<'static>,
+    proxy_pool: Option<Arc<ProxyPool>>,
+    /// Timeouts of the route, those of the upstream type in config if not set
+    timeouts: Option<Timeouts>,
+}
+
+impl GrpcPassthroughHandler {
+    /// Passthrough to `https://app.bilibili.com`
+    pub const APP: Self = Self {
+        upstream: Upstream::APP_DEFAULT,
+        proxy_pool: None,
+        timeouts: None,
+    };
+    /// Passthrough to `https://grpc.biliapi.net`
+    pub const GRPC: Self = Self {
+        upstream: Upstream::GRPC_DEFAULT,
+        proxy_pool: None,
+        timeouts: None,
+    };
+
+    #[inline]
+    pub fn new(upstream: impl Into<Upstream<'static>>) -> Self {
+        Self {
+            upstream: upstream.into(),
+            proxy_pool: None,
+            timeouts: None,
+        }
+    }
+
+    /// Request the upstream through proxies picked from given pool
+    #[inline]
+    pub fn with_proxy_pool(mut self, proxy_pool: Option<Arc<ProxyPool>>) -> Self {
+        self.proxy_pool = proxy_pool;
+        self
+    }
+
+    /// Request the upstream with given timeouts
+    #[inline]
+    pub fn with_timeouts(mut self, timeouts: Option<Timeouts>) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+}
+
+impl HandlerT for GrpcPassthroughHandler {
+    type Response = GrpcResponsePassthrough;
+
+    #[tracing::instrument(level = "debug", name = "GrpcPassthroughHandler.call", skip(self), err)]
+    async fn call(self, req: AxumRequest) -> Result<Self::Response> {
+        let (mut parts, body) = req.into_parts();
+
+        let timeouts = self
+            .timeouts
+            .unwrap_or_else(|| upstream_timeouts(&self.upstream.u_type, Default::default()));
+
+        let (body, capture) = match parts.extensions.remove::<Capture>() {
+            Some(capture) => {
+                let body = buffer_body(body).await?;
+                let url = format!("{}{}", self.upstream.str(), parts.uri.path());
+                let request = CapturedRequest::new("POST", &url, &parts.headers, &body);
+                (RawBody::from(body), Some((capture, request)))
+            }
+            None => (RawBody::stream(limit_body(body)?), None),
+        };
+
+        let result = GrpcPassthroughRpc::new(body, self.upstream)
+            .with_proxy_pool(self.proxy_pool)
+            .with_retry(retry_policy())
+            .with_timeouts(Some(timeouts))
+            .with_path(parts.uri.path())
+            .with_headers(Some(parts.headers))
+            .execute()
+            .await;
+
+        if let Some((capture, request)) = capture {
+            let response = result.as_ref().map(|r| {
+                CapturedResponse::new(r.status.as_u16(), &r.headers, r.trailers.as_ref(), &r.body)
+            });
+            capture.save(request, response);
+        }
+
+        let GrpcRawResponse {
+            headers,
+            body,
+            trailers,
+            ..
+        } = result?;
+
+        Ok(GrpcResponsePassthrough {
+            headers,
+            body,
+            trailers,
+        })
+    }
+}
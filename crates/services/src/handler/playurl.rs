@@ -2,7 +2,7 @@ use anyhow::Result;
 use serde_json::json;
 
 use crate::{axum_response, generate_router, HandlerFuture};
-use lib_utils::url::QueryMap;
+use lib_utils::{sign::App, url::QueryMap};
 
 generate_router!(
     PlayurlRouter,
@@ -40,6 +40,10 @@ where
 
 impl PlayurlHandler {
     pub async fn get_playurl(&self, req: axum::extract::Request) -> Result<serde_json::Value> {
+        if let Self::PgcPlayerApi = self {
+            App::verify(req.uri().query().unwrap_or_default())?;
+        }
+
         let query_map = QueryMap::try_from_req(&req)?;
         // TODO implement get playurl
         Ok(json!({
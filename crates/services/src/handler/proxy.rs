@@ -0,0 +1,486 @@
+use anyhow::{bail, Result};
+use axum::{
+    body::Body,
+    extract::Request as AxumRequest,
+    handler::Handler,
+    response::{IntoResponse, Response as AxumResponse},
+};
+use bytes::{Bytes, BytesMut};
+use http::{
+    header::{AUTHORIZATION, CONTENT_TYPE, COOKIE, SET_COOKIE},
+    HeaderMap, HeaderName, Method, StatusCode,
+};
+use lib_rpc::{
+    request::{get_pool, ProxyPool, Timeouts},
+    utils::Upstream,
+};
+
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use super::{
+    passthrough::{GrpcPassthroughHandler, PassthroughHandler},
+    InterceptHandler,
+};
+use crate::{
+    capture::Capture,
+    handler::ErrorResponse,
+    intercept::{
+        chain::InterceptChain, route::RouteInterceptor, sanitize::SanitizeInterceptor,
+        DefaultInterceptor,
+    },
+    max_buffer_size,
+    rate_limit::{CacheStatus, RateLimit},
+    upstream_timeouts, HandlerFuture,
+};
+use lib_core::server::{
+    cache::TtlCache,
+    config::{RouteConfig, UpstreamConfig, CONFIG_ROUTES},
+};
+use lib_utils::{error::ServerErrorExt, model::response::StatusPolicy};
+
+/// Response cached by a route
+#[derive(Debug, Clone)]
+struct CachedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl CachedResponse {
+    /// Response to be shared with others, without cookies set for the
+    /// requesting user.
+    fn new(status: StatusCode, mut headers: HeaderMap, body: Bytes) -> Self {
+        headers.remove(SET_COOKIE);
+        Self {
+            status,
+            headers,
+            body,
+        }
+    }
+}
+
+impl IntoResponse for CachedResponse {
+    fn into_response(self) -> AxumResponse {
+        let mut response = AxumResponse::new(Body::from(self.body));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers;
+        response
+    }
+}
+
+/// Body of a response to be cached
+enum CacheBody {
+    Buffered(Bytes),
+    /// Too large to be cached, piped with the part buffered
+    Streamed(Body),
+}
+
+impl CacheBody {
+    /// Buffer the body, streamed instead once more than `limit` bytes polled.
+    async fn new(mut body: Body, limit: usize) -> Result<Self> {
+        use axum::body::HttpBody;
+        use http_body_util::BodyExt;
+
+        if body.size_hint().lower() > limit as u64 {
+            return Ok(Self::Streamed(body));
+        }
+
+        let mut buffered = BytesMut::new();
+        while let Some(frame) = body.frame().await {
+            if let Some(data) = frame?.data_ref() {
+                buffered.extend_from_slice(data);
+            }
+
+            if buffered.len() > limit {
+                tracing::debug!("Response exceeds buffer size limit [{}], not cached", limit);
+                return Ok(Self::Streamed(Body::new(Prepended {
+                    head: Some(buffered.freeze()),
+                    rest: body,
+                })));
+            }
+        }
+
+        Ok(Self::Buffered(buffered.freeze()))
+    }
+}
+
+/// Body with data polled already put back before the rest
+struct Prepended {
+    head: Option<Bytes>,
+    rest: Body,
+}
+
+impl http_body::Body for Prepended {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<http_body::Frame<Self::Data>, Self::Error>>> {
+        match self.head.take() {
+            Some(head) => Poll::Ready(Some(Ok(http_body::Frame::data(head)))),
+            None => http_body::Body::poll_frame(Pin::new(&mut self.rest), cx),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.head.is_none() && http_body::Body::is_end_stream(&self.rest)
+    }
+}
+
+/// A route of the full server mode, see [RouteConfig].
+#[derive(Debug)]
+struct ProxyRoute {
+    prefix: String,
+    upstream: Upstream<'static>,
+    proxy_pool: Option<Arc<ProxyPool>>,
+    timeouts: Timeouts,
+    /// Interceptors for RESTful requests
+    interceptor: InterceptChain,
+    /// Interceptors for gRPC requests
+    interceptor_grpc: InterceptChain,
+    cache: Option<TtlCache<String, CachedResponse>>,
+    /// Capture requests of the route, see [CaptureConfig](lib_core::server::config::CaptureConfig)
+    capture: bool,
+    /// Overrides the server's one, see [StatusPolicy]
+    status_policy: Option<StatusPolicy>,
+}
+
+impl TryFrom<&RouteConfig> for ProxyRoute {
+    type Error = anyhow::Error;
+
+    fn try_from(config: &RouteConfig) -> Result<Self> {
+        let upstream = match &config.upstream {
+            UpstreamConfig::Api => Upstream::API_DEFAULT,
+            UpstreamConfig::App => Upstream::APP_DEFAULT,
+            UpstreamConfig::Grpc => Upstream::GRPC_DEFAULT,
+            UpstreamConfig::Custom(custom) => {
+                if !(custom.starts_with("https://") || custom.starts_with("http://")) {
+                    bail!("Invalid custom upstream scheme [{}]", custom)
+                }
+                Upstream::from(custom.trim_end_matches('/').to_owned())
+            }
+        };
+
+        let proxy_pool = match &config.proxy_pool {
+            Some(name) => match get_pool(name) {
+                Some(pool) => Some(pool),
+                None => bail!("Proxy pool [{}] not found", name),
+            },
+            None => None,
+        };
+
+        let timeouts = upstream_timeouts(&upstream.u_type, config.timeouts);
+
+        let header_names = |names: &[String]| {
+            names
+                .iter()
+                .map(|name| HeaderName::try_from(name.as_str()))
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        let interceptor_grpc =
+            InterceptChain::new()
+                .with(DefaultInterceptor)
+                .with(SanitizeInterceptor::new(
+                    header_names(&config.sanitize.request)?,
+                    header_names(&config.sanitize.response)?,
+                ));
+        let interceptor = interceptor_grpc
+            .clone()
+            .with(RouteInterceptor::new(config.interceptors.as_slice()));
+
+        Ok(Self {
+            prefix: config.prefix.clone(),
+            upstream,
+            proxy_pool,
+            timeouts,
+            interceptor,
+            interceptor_grpc,
+            cache: config
+                .cache
+                .map(|cache| TtlCache::new(Duration::from_secs(cache.ttl), cache.capacity)),
+            capture: config.capture,
+            status_policy: config.status_policy,
+        })
+    }
+}
+
+impl ProxyRoute {
+    #[tracing::instrument(level = "debug", name = "ProxyRoute.call", fields(route.prefix = %self.prefix), skip_all)]
+    async fn call(&self, mut req: AxumRequest) -> AxumResponse {
+        let capture = Capture::check(&self.prefix, self.capture, req.extensions_mut());
+
+        // Captured ones always reach the upstream, and responses to users
+        // are never shared
+        let cache_key = self
+            .cache
+            .as_ref()
+            .filter(|_| req.method() == Method::GET && !capture && !has_credentials(&req))
+            .map(|_| req.uri().to_string());
+
+        if let Some(cached) = cache_key
+            .as_ref()
+            .and_then(|key| self.cache.as_ref()?.get(key))
+        {
+            tracing::debug!("Response cached");
+            if let Err(response) = RateLimit::check(req.extensions(), CacheStatus::Hit) {
+                return response;
+            }
+            return cached.into_response();
+        }
+
+        // Checked before dispatching, or limited responses would be cached
+        if let Err(response) = RateLimit::check(req.extensions(), CacheStatus::Miss) {
+            return response;
+        }
+        req.extensions_mut().remove::<RateLimit>();
+
+        let is_grpc = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .is_some_and(|t| t.as_bytes().starts_with(b"application/grpc"));
+
+        let response = if is_grpc {
+            let handler = InterceptHandler::new(
+                Some(self.interceptor_grpc.clone()),
+                GrpcPassthroughHandler::new(self.upstream.clone())
+                    .with_proxy_pool(self.proxy_pool.clone())
+                    .with_timeouts(Some(self.timeouts)),
+                "Full server gRPC proxy",
+            );
+            Handler::<(), ()>::call(handler, req, ()).await
+        } else {
+            let handler = InterceptHandler::new(
+                Some(self.interceptor.clone()),
+                PassthroughHandler::new(self.upstream.clone())
+                    .with_proxy_pool(self.proxy_pool.clone())
+                    .with_timeouts(Some(self.timeouts)),
+                "Full server proxy",
+            );
+            Handler::<(), ()>::call(handler, req, ()).await
+        };
+
+        match (&self.cache, cache_key) {
+            (Some(cache), Some(key))
+                if response.status().is_success()
+                    && response.extensions().get::<ErrorResponse>().is_none() =>
+            {
+                let (parts, body) = response.into_parts();
+                let body = match CacheBody::new(body, max_buffer_size()).await {
+                    Ok(CacheBody::Buffered(body)) => body,
+                    Ok(CacheBody::Streamed(body)) => return AxumResponse::from_parts(parts, body),
+                    Err(e) => return ServerErrorExt::from(e).into_response(),
+                };
+
+                cache.insert(
+                    key,
+                    CachedResponse::new(parts.status, parts.headers.clone(), body.clone()),
+                );
+
+                let mut response = AxumResponse::new(Body::from(body));
+                *response.status_mut() = parts.status;
+                *response.headers_mut() = parts.headers;
+                response
+            }
+            _ => response,
+        }
+    }
+}
+
+/// Whether the request carries credentials of a user, i.e. cookies,
+/// `authorization` or `access_key` in query or headers.
+fn has_credentials(req: &AxumRequest) -> bool {
+    let is_access_key = |name: &str| {
+        name.eq_ignore_ascii_case("access_key") || name.eq_ignore_ascii_case("access-key")
+    };
+
+    req.headers().contains_key(COOKIE)
+        || req.headers().contains_key(AUTHORIZATION)
+        || req
+            .headers()
+            .keys()
+            .any(|name| is_access_key(name.as_str()))
+        || req.uri().query().is_some_and(|query| {
+            query
+                .split('&')
+                .any(|pair| is_access_key(pair.split('=').next().unwrap_or_default()))
+        })
+}
+
+/// Handler of the full server mode, proxying requests to the upstream of the
+/// route with the longest prefix matched.
+///
+/// Requests matching no route are handled by [DefaultHandler](super::DefaultHandler).
+#[derive(Debug, Clone)]
+pub struct ProxyHandler {
+    routes: Arc<[ProxyRoute]>,
+}
+
+impl ProxyHandler {
+    /// Create with given routes, invalid ones skipped.
+    pub fn new(routes: &[RouteConfig]) -> Self {
+        let mut routes: Vec<ProxyRoute> = routes
+            .iter()
+            .filter_map(|route| match ProxyRoute::try_from(route) {
+                Ok(route) => Some(route),
+                Err(e) => {
+                    tracing::error!("Invalid route [{}], skipped: {}", route.prefix, e);
+                    None
+                }
+            })
+            .collect();
+        routes.sort_by(|a, b| b.prefix.len().cmp(&a.prefix.len()));
+
+        Self {
+            routes: routes.into(),
+        }
+    }
+
+    /// Create with routes in config.
+    pub fn from_config() -> Self {
+        Self::new(CONFIG_ROUTES.get().map_or(&[][..], Vec::as_slice))
+    }
+
+    #[inline]
+    fn route(&self, path: &str) -> Option<&ProxyRoute> {
+        self.routes
+            .iter()
+            .find(|route| path.starts_with(route.prefix.as_str()))
+    }
+}
+
+impl<T, S> Handler<T, S> for ProxyHandler {
+    type Future = HandlerFuture;
+
+    #[tracing::instrument(level = "debug", name = "ProxyHandler.call", skip(self, _state))]
+    fn call(self, req: AxumRequest, _state: S) -> Self::Future {
+        Box::pin(async move {
+            match self.route(req.uri().path()) {
+                Some(route) => match route.status_policy {
+                    Some(policy) => policy.scope(route.call(req)).await,
+                    None => route.call(req).await,
+                },
+                None => Handler::<(), ()>::call(InterceptHandler::default(), req, ()).await,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lib_core::server::config::RouteInterceptorKind;
+
+    use super::*;
+
+    fn route(prefix: &str, upstream: UpstreamConfig) -> RouteConfig {
+        RouteConfig {
+            prefix: prefix.to_owned(),
+            upstream,
+            interceptors: vec![RouteInterceptorKind::Rewrite],
+            sanitize: Default::default(),
+            cache: None,
+            proxy_pool: None,
+            timeouts: Default::default(),
+            capture: false,
+            status_policy: None,
+        }
+    }
+
+    #[test]
+    fn test_route() {
+        let handler = ProxyHandler::new(&[
+            route("/x/", UpstreamConfig::Api),
+            route("/x/v2/", UpstreamConfig::App),
+            route("/bilibili.", UpstreamConfig::Grpc),
+            route(
+                "/invalid/",
+                UpstreamConfig::Custom("ftp://a.com".to_owned()),
+            ),
+            RouteConfig {
+                proxy_pool: Some("not-found".to_owned()),
+                ..route("/pool/", UpstreamConfig::Api)
+            },
+        ]);
+
+        assert_eq!(handler.routes.len(), 3);
+        assert_eq!(
+            handler.route("/x/v2/feed/index").map(|r| r.upstream.str()),
+            Some("https://app.bilibili.com")
+        );
+        assert_eq!(
+            handler
+                .route("/x/web-interface/nav")
+                .map(|r| r.upstream.str()),
+            Some("https://api.bilibili.com")
+        );
+        assert_eq!(
+            handler
+                .route("/bilibili.app.view.v1.View/View")
+                .map(|r| r.upstream.str()),
+            Some("https://grpc.biliapi.net")
+        );
+        assert!(handler.route("/invalid/path").is_none());
+    }
+
+    #[test]
+    fn test_has_credentials() {
+        let req = |uri: &str, header: Option<(&str, &str)>| {
+            let mut builder = http::Request::get(uri);
+            if let Some((name, value)) = header {
+                builder = builder.header(name, value);
+            }
+            builder.body(Body::empty()).unwrap()
+        };
+
+        assert!(!has_credentials(&req("/x/v2/feed/index?build=1", None)));
+        assert!(has_credentials(&req(
+            "/x/v2/feed/index?a=1&access_key=ak",
+            None
+        )));
+        assert!(has_credentials(&req("/x", Some(("cookie", "SESSDATA=1")))));
+        assert!(has_credentials(&req(
+            "/x",
+            Some(("authorization", "identify_v1 ak"))
+        )));
+        assert!(has_credentials(&req("/x", Some(("access-key", "ak")))));
+    }
+
+    #[tokio::test]
+    async fn test_cache_body() {
+        use http_body_util::BodyExt;
+
+        let body = CacheBody::new(Body::from("small"), 8).await.unwrap();
+        assert!(matches!(body, CacheBody::Buffered(body) if body == "small"));
+
+        // Streamed as is once too large
+        let body = Body::new(Prepended {
+            head: Some(Bytes::from("0123")),
+            rest: Body::new(Prepended {
+                head: Some(Bytes::from("4567")),
+                rest: Body::from("89"),
+            }),
+        });
+        let CacheBody::Streamed(body) = CacheBody::new(body, 6).await.unwrap() else {
+            panic!("should be streamed");
+        };
+        assert_eq!(body.collect().await.unwrap().to_bytes(), "0123456789");
+    }
+
+    #[test]
+    fn test_cached_response_without_cookies() {
+        let mut headers = HeaderMap::new();
+        headers.insert(SET_COOKIE, "SESSDATA=1".parse().unwrap());
+        headers.insert(CONTENT_TYPE, "application/json".parse().unwrap());
+
+        let cached = CachedResponse::new(StatusCode::OK, headers, Bytes::new());
+        assert!(!cached.headers.contains_key(SET_COOKIE));
+        assert!(cached.headers.contains_key(CONTENT_TYPE));
+    }
+}
@@ -0,0 +1,117 @@
+use super::{
+    passthrough::{GrpcPassthroughHandler, PassthroughHandler},
+    InterceptHandler,
+};
+use crate::{
+    generate_router,
+    intercept::{
+        bilibili::{purify_main_list_reply, purify_view_reply, PurifyInterceptor, PurifyTarget},
+        chain::InterceptChain,
+        grpc::GrpcInterceptor,
+        rewrite::RewriteInterceptor,
+        sign::AppSignInterceptor,
+    },
+};
+
+// Response phases of chains run in reverse, purification before rewrite rules
+generate_router!(
+    PurifyRouter,
+    (
+        "/x/v2/feed/index",
+        GET,
+        InterceptHandler::new(
+            Some(
+                InterceptChain::new()
+                    .with(AppSignInterceptor)
+                    .with(RewriteInterceptor)
+                    .with(PurifyInterceptor::new(PurifyTarget::FeedIndex))
+            ),
+            PassthroughHandler::APP,
+            "Purify feed index"
+        )
+    ),
+    (
+        "/x/v2/splash/list",
+        GET,
+        InterceptHandler::new(
+            Some(
+                InterceptChain::new()
+                    .with(AppSignInterceptor)
+                    .with(RewriteInterceptor)
+                    .with(PurifyInterceptor::new(PurifyTarget::SplashList))
+            ),
+            PassthroughHandler::APP,
+            "Purify splash list"
+        )
+    ),
+    (
+        "/x/v2/search/defaultwords",
+        GET,
+        InterceptHandler::new(
+            Some(
+                InterceptChain::new()
+                    .with(AppSignInterceptor)
+                    .with(RewriteInterceptor)
+                    .with(PurifyInterceptor::new(PurifyTarget::SearchDefault))
+            ),
+            PassthroughHandler::APP,
+            "Purify search default words"
+        )
+    ),
+    (
+        "/bilibili.app.view.v1.View/View",
+        POST,
+        InterceptHandler::new(
+            Some(GrpcInterceptor::new(purify_view_reply)),
+            GrpcPassthroughHandler::GRPC,
+            "Purify gRPC view"
+        )
+    ),
+    (
+        "/bilibili.main.community.reply.v1.Reply/MainList",
+        POST,
+        InterceptHandler::new(
+            Some(GrpcInterceptor::new(purify_main_list_reply)),
+            GrpcPassthroughHandler::GRPC,
+            "Purify gRPC reply main list"
+        )
+    )
+);
+
+#[cfg(test)]
+mod test {
+    use lib_utils::error::ServerError;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_app_sign() {
+        let code = |uri: &str| async move {
+            let request = http::Request::get(uri)
+                .body(axum::body::Body::empty())
+                .unwrap();
+            let response = PurifyRouter::new().oneshot(request).await.unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()["code"].as_i64()
+        };
+
+        // Rejected before reaching the upstream
+        assert_eq!(
+            code("/x/v2/feed/index?idx=0").await,
+            Some(ServerError::FatalUrlSignInvalid as i64)
+        );
+        assert_eq!(
+            code("/x/v2/splash/list?appkey=0000000000000000&sign=00000000000000000000000000000000")
+                .await,
+            Some(ServerError::ReqAppkeyInvalid as i64)
+        );
+        assert_eq!(
+            code("/x/v2/search/defaultwords?appkey=1d8b6e7d45233436&mobi_app=iphone&sign=00000000000000000000000000000000")
+                .await,
+            Some(ServerError::FatalUrlSignInvalid as i64)
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use axum::response::IntoResponse;
+use serde::Serialize;
+
+use crate::{axum_response, capture::Admin, generate_router, HandlerFuture};
+use lib_utils::error::ServerError;
+
+generate_router!(StatusRouter, ("/admin/status", GET, StatusHandler));
+
+/// Status of the server, for admins only
+#[derive(Debug, Serialize)]
+struct Status {
+    /// Count of clients cached for proxies, see [client_count](lib_rpc::request::client_count)
+    clients: usize,
+}
+
+/// Handler reporting [Status] of the server, for admins only.
+#[derive(Debug, Clone)]
+struct StatusHandler;
+
+impl<T, S> axum::handler::Handler<T, S> for StatusHandler {
+    type Future = HandlerFuture;
+
+    #[tracing::instrument(level = "debug", name = "StatusHandler.call", skip(self, _state))]
+    fn call(self, req: axum::extract::Request, _state: S) -> Self::Future {
+        Box::pin(async move {
+            if req.extensions().get::<Admin>().is_none() {
+                tracing::warn!("Unauthorized access to status");
+                return ServerError::FatalReqInvalid.into_response();
+            }
+
+            axum_response!(anyhow::Ok(Status {
+                clients: lib_rpc::request::client_count(),
+            }))
+        })
+    }
+}
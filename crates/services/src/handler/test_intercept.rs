@@ -1,9 +1,8 @@
 use anyhow::Result;
-use axum::extract::Request as AxumRequest;
+use axum::{body::Body, extract::Request as AxumRequest, response::Response as AxumResponse};
 
 use super::{DefaultInterceptor, HandlerT, InterceptHandler};
-use crate::{generate_router, http02_compat, impl_rpc_t};
-use lib_utils::model::response::ResponsePassthrough;
+use crate::{buffer_body, generate_router, impl_rpc_t};
 
 generate_router!(
     TestInterceptRouter,
@@ -20,30 +19,22 @@ pub struct TestHandler;
 impl_rpc_t!(TestHandler, Upstream::API_DEFAULT, "/x/web-interface/zone");
 
 impl HandlerT for TestHandler {
-    type Response = ResponsePassthrough;
+    type Response = AxumResponse;
 
     #[tracing::instrument(level = "debug", name = "TestHandler.call", skip(self), err)]
     async fn call(self, req: AxumRequest) -> Result<Self::Response> {
         let (parts, body) = req.into_parts();
         // poll data from request Body
-        let body = axum::body::to_bytes(body, usize::MAX).await?;
+        let body = buffer_body(body).await?;
 
-        let headers = parts.headers;
-
-        http02_compat!(headers_http02, headers, http_02);
-
-        let (_, _, resp_headers_http02, data) =
-            <Self as RpcT>::execute_rpc(None, None, Some(headers_http02), Some(body))
-                .await?
-                .bytes()
+        let (_, _, resp_headers, stream) =
+            <Self as RpcT>::execute_rpc(None, None, Some(parts.headers), Some(body))
                 .await?
+                .stream()?
                 .into_parts();
 
-        http02_compat!(resp_headers, resp_headers_http02, http);
-
-        Ok(ResponsePassthrough {
-            headers: resp_headers,
-            body: data.unwrap_or_default(),
-        })
+        let mut response = AxumResponse::new(Body::from_stream(stream));
+        *response.headers_mut() = resp_headers;
+        Ok(response)
     }
 }
@@ -1,20 +1,126 @@
 pub(crate) mod bilibili;
+pub(crate) mod chain;
+pub(crate) mod grpc;
+pub(crate) mod rewrite;
+pub(crate) mod route;
+pub(crate) mod sanitize;
+pub(crate) mod sign;
 
 use std::future::Future;
 
 use anyhow::Result;
+use axum::body::Body;
 use axum::extract::Request as AxumRequest;
 use axum::response::Response as AxumResponse;
+use bytes::Bytes;
+use serde_json::Value;
+
+/// URI of the original request, available in extensions of the response
+/// when intercepting it.
+#[derive(Debug, Clone)]
+pub struct InterceptUri(pub http::Uri);
+
+/// JSON body of a response buffered, parsed once and rewritten in place by
+/// any number of interceptors, then written back.
+pub(crate) struct JsonBody {
+    bytes: Bytes,
+    /// `None` if the body is not valid JSON
+    json: Option<Value>,
+    modified: usize,
+}
+
+impl JsonBody {
+    /// Buffer and parse the body of given response, which is left empty
+    /// until [write_back](Self::write_back).
+    pub(crate) async fn take(response: &mut AxumResponse) -> Result<Self> {
+        let body = std::mem::take(response.body_mut());
+        let bytes = crate::buffer_body(body).await?;
+
+        let json = match serde_json::from_slice(&bytes) {
+            Ok(json) => Some(json),
+            Err(e) => {
+                tracing::warn!("Response is not valid JSON, skip rewriting: {}", e);
+                None
+            }
+        };
+
+        Ok(Self {
+            bytes,
+            json,
+            modified: 0,
+        })
+    }
+
+    /// Rewrite the JSON with `f`, which returns count of modifications made.
+    pub(crate) fn rewrite(&mut self, f: impl FnOnce(&mut Value) -> usize) -> usize {
+        let modified = self.json.as_mut().map_or(0, f);
+        self.modified += modified;
+        modified
+    }
+
+    /// Write the body back to the response, kept as is when nothing modified,
+    /// return count of modifications made.
+    pub(crate) fn write_back(self, response: &mut AxumResponse) -> Result<usize> {
+        match self.json {
+            Some(json) if self.modified > 0 => {
+                response.headers_mut().remove(http::header::CONTENT_LENGTH);
+                *response.body_mut() = Body::from(serde_json::to_vec(&json)?);
+            }
+            _ => *response.body_mut() = Body::from(self.bytes),
+        }
+
+        Ok(self.modified)
+    }
+}
+
+/// Buffer the JSON body of given response and rewrite it with `f`, which
+/// returns count of modifications made.
+///
+/// Body will be kept as is when it's not valid JSON or nothing modified.
+pub(crate) async fn rewrite_json_response(
+    response: &mut AxumResponse,
+    f: impl FnOnce(&mut Value) -> usize,
+) -> Result<usize> {
+    let mut body = JsonBody::take(response).await?;
+    body.rewrite(f);
+    body.write_back(response)
+}
+
+/// Response phase of an interceptor only rewriting JSON bodies, see
+/// [InterceptT::rewrite_json].
+pub(crate) async fn intercept_json_response(
+    interceptor: &impl InterceptT,
+    response: &mut AxumResponse,
+) -> Result<Option<AxumResponse>> {
+    if !response.status().is_success() {
+        return Ok(None);
+    }
+
+    let Some(InterceptUri(uri)) = response.extensions().get::<InterceptUri>().cloned() else {
+        return Ok(None);
+    };
+    if !interceptor.rewrites_json(uri.path()) {
+        return Ok(None);
+    }
+
+    let modified =
+        rewrite_json_response(response, |json| interceptor.rewrite_json(uri.path(), json)).await?;
+    if modified > 0 {
+        tracing::debug!("Modified {} value(s) of [{}]", modified, uri.path());
+    }
+
+    Ok(None)
+}
 
 pub trait InterceptT: 'static + std::fmt::Debug + Clone + Send {
     #[tracing::instrument(level = "debug", name = "InterceptT.intercept_request", skip(self))]
-    /// Intercept request headers or body, return `Ok(())` to continue
-    /// or error stop the request.
+    /// Intercept request headers or body, return `Ok(None)` to continue,
+    /// synthesized [AxumResponse] to short-circuit or error stop the request.
     fn intercept_request(
         &self,
         request: &mut AxumRequest,
-    ) -> impl Future<Output = Result<()>> + Send {
-        async { Ok(()) }
+    ) -> impl Future<Output = Result<Option<AxumResponse>>> + Send {
+        async { Ok(None) }
     }
 
     /// Intercept response headers or bodys, modify original response,
@@ -26,6 +132,23 @@ pub trait InterceptT: 'static + std::fmt::Debug + Clone + Send {
     ) -> impl Future<Output = Result<Option<AxumResponse>>> + Send {
         async { Ok(None) }
     }
+
+    /// Whether JSON bodies of successful responses to given path are
+    /// rewritten by [rewrite_json](InterceptT::rewrite_json).
+    fn rewrites_json(&self, _path: &str) -> bool {
+        false
+    }
+
+    /// Rewrite the JSON body of a successful response to given path, return
+    /// count of values modified.
+    ///
+    /// [InterceptChain](chain::InterceptChain) parses the body once and
+    /// passes it to its links in turn, instead of calling their
+    /// `intercept_response`, see [intercept_json_response] for the one used
+    /// when not chained.
+    fn rewrite_json(&self, _path: &str, _json: &mut Value) -> usize {
+        0
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -37,12 +160,12 @@ impl InterceptT for DefaultInterceptor {
         skip(self),
         err
     )]
-    async fn intercept_request(&self, request: &mut AxumRequest) -> Result<()> {
+    async fn intercept_request(&self, request: &mut AxumRequest) -> Result<Option<AxumResponse>> {
         let headers = request.headers_mut();
 
         // Remove the host header to avoid the request being rejected by the server
         headers.remove(http::header::HOST);
 
-        Ok(())
+        Ok(None)
     }
 }
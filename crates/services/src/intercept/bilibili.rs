@@ -0,0 +1,447 @@
+use anyhow::Result;
+use axum::{extract::Request as AxumRequest, response::Response as AxumResponse};
+use serde_json::Value;
+
+use super::{intercept_json_response, DefaultInterceptor, InterceptT};
+use lib_bilibili::bapis::{app::view::v1::ViewReply, main::community::reply::v1::MainListReply};
+use lib_core::server::config::{PurifyConfig, CONFIG_INTERCEPT};
+
+#[inline]
+pub(crate) fn purify_config() -> PurifyConfig {
+    CONFIG_INTERCEPT.get().map(|c| c.purify).unwrap_or_default()
+}
+
+/// Category of content to be purified, each can be turned on or off in [PurifyConfig].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PurifyCategory {
+    Ad,
+    Banner,
+    Live,
+    Game,
+}
+
+impl PurifyCategory {
+    #[inline]
+    const fn is_enabled(self, config: &PurifyConfig) -> bool {
+        match self {
+            Self::Ad => config.ad,
+            Self::Banner => config.banner,
+            Self::Live => config.live,
+            Self::Game => config.game,
+        }
+    }
+}
+
+/// How a JSON field of an item is matched
+#[derive(Debug, Clone, Copy)]
+pub enum Matcher {
+    /// Field exists and is not `null`
+    Exists,
+    /// Field is `true`, non-zero number or non-empty string
+    Truthy,
+    /// Field is the given string
+    Eq(&'static str),
+    /// Field is a string starting with given prefix
+    StartsWith(&'static str),
+}
+
+impl Matcher {
+    #[inline]
+    fn is_match(&self, value: &Value) -> bool {
+        match (self, value) {
+            (_, Value::Null) => false,
+            (Self::Exists, _) => true,
+            (Self::Truthy, Value::Bool(b)) => *b,
+            (Self::Truthy, Value::Number(n)) => n.as_f64().is_some_and(|n| n != 0.0),
+            (Self::Truthy, Value::String(s)) => !s.is_empty(),
+            (Self::Eq(expected), Value::String(s)) => s == expected,
+            (Self::StartsWith(prefix), Value::String(s)) => s.starts_with(prefix),
+            _ => false,
+        }
+    }
+}
+
+/// A JSON rule for matching an item(card) to be removed
+#[derive(Debug, Clone, Copy)]
+pub struct PurifyRule {
+    pub category: PurifyCategory,
+    /// Key of the item field to check
+    pub key: &'static str,
+    pub matcher: Matcher,
+}
+
+impl PurifyRule {
+    #[inline]
+    const fn new(category: PurifyCategory, key: &'static str, matcher: Matcher) -> Self {
+        Self {
+            category,
+            key,
+            matcher,
+        }
+    }
+
+    #[inline]
+    fn is_match(&self, item: &Value, config: &PurifyConfig) -> bool {
+        self.category.is_enabled(config)
+            && item
+                .get(self.key)
+                .is_some_and(|value| self.matcher.is_match(value))
+    }
+}
+
+/// Rules for items in `data.items` of `/x/v2/feed/index`
+const FEED_INDEX_RULES: &[PurifyRule] = &[
+    PurifyRule::new(PurifyCategory::Ad, "card_goto", Matcher::StartsWith("ad")),
+    PurifyRule::new(PurifyCategory::Ad, "goto", Matcher::StartsWith("ad")),
+    PurifyRule::new(PurifyCategory::Ad, "ad_info", Matcher::Exists),
+    PurifyRule::new(PurifyCategory::Banner, "card_goto", Matcher::Eq("banner")),
+    PurifyRule::new(
+        PurifyCategory::Banner,
+        "card_type",
+        Matcher::StartsWith("banner"),
+    ),
+    PurifyRule::new(PurifyCategory::Banner, "banner_item", Matcher::Exists),
+    PurifyRule::new(
+        PurifyCategory::Live,
+        "card_goto",
+        Matcher::StartsWith("live"),
+    ),
+    PurifyRule::new(PurifyCategory::Live, "goto", Matcher::Eq("live")),
+    PurifyRule::new(
+        PurifyCategory::Game,
+        "card_goto",
+        Matcher::StartsWith("game"),
+    ),
+    PurifyRule::new(PurifyCategory::Game, "goto", Matcher::StartsWith("game")),
+];
+
+/// Rules for items in `data.list` of `/x/v2/splash/list`
+const SPLASH_LIST_RULES: &[PurifyRule] = &[
+    PurifyRule::new(PurifyCategory::Ad, "is_ad", Matcher::Truthy),
+    PurifyRule::new(PurifyCategory::Ad, "is_ad_loc", Matcher::Truthy),
+    PurifyRule::new(PurifyCategory::Ad, "ad_cb", Matcher::Truthy),
+    PurifyRule::new(
+        PurifyCategory::Live,
+        "uri",
+        Matcher::StartsWith("bilibili://live"),
+    ),
+    PurifyRule::new(
+        PurifyCategory::Game,
+        "uri",
+        Matcher::StartsWith("bilibili://game"),
+    ),
+];
+
+/// Fields in `data` of `/x/v2/search/defaultwords` to be cleared
+const SEARCH_DEFAULT_FIELDS: &[&str] = &["show", "word", "show_front", "goto", "value", "uri"];
+
+/// Target API to be purified
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PurifyTarget {
+    /// Path: /x/v2/feed/index
+    FeedIndex,
+    /// Path: /x/v2/splash/list
+    SplashList,
+    /// Path: /x/v2/search/defaultwords
+    SearchDefault,
+}
+
+impl PurifyTarget {
+    /// Target of given request path, if supported.
+    pub fn from_path(path: &str) -> Option<Self> {
+        match path {
+            "/x/v2/feed/index" => Some(Self::FeedIndex),
+            "/x/v2/splash/list" => Some(Self::SplashList),
+            "/x/v2/search/defaultwords" => Some(Self::SearchDefault),
+            _ => None,
+        }
+    }
+
+    /// Purify given response JSON in place, return count of items removed or cleared.
+    pub(crate) fn purify(self, json: &mut Value, config: &PurifyConfig) -> usize {
+        let Some(data) = json.get_mut("data") else {
+            return 0;
+        };
+
+        match self {
+            Self::FeedIndex => data
+                .get_mut("items")
+                .map_or(0, |items| retain_items(items, FEED_INDEX_RULES, config)),
+            Self::SplashList => {
+                let removed = data
+                    .get_mut("list")
+                    .map_or(0, |list| retain_items(list, SPLASH_LIST_RULES, config));
+
+                if removed > 0 {
+                    // Splash to be shown should be in `list`
+                    let ids: Vec<Value> = data
+                        .get("list")
+                        .and_then(Value::as_array)
+                        .map(|list| list.iter().filter_map(|i| i.get("id").cloned()).collect())
+                        .unwrap_or_default();
+                    if let Some(show) = data.get_mut("show").and_then(Value::as_array_mut) {
+                        show.retain(|s| s.get("id").is_some_and(|id| ids.contains(id)));
+                    }
+                }
+
+                removed
+            }
+            Self::SearchDefault => {
+                if !config.ad {
+                    return 0;
+                }
+                let Some(data) = data.as_object_mut() else {
+                    return 0;
+                };
+                let mut cleared = 0;
+                for key in SEARCH_DEFAULT_FIELDS {
+                    if let Some(value) = data.get_mut(*key) {
+                        if Matcher::Truthy.is_match(value) {
+                            *value = Value::String(String::new());
+                            cleared += 1;
+                        }
+                    }
+                }
+                cleared
+            }
+        }
+    }
+}
+
+/// Remove items matching any of the rules, return count of items removed.
+#[inline]
+fn retain_items(items: &mut Value, rules: &[PurifyRule], config: &PurifyConfig) -> usize {
+    let Some(items) = items.as_array_mut() else {
+        return 0;
+    };
+    let len = items.len();
+    items.retain(|item| !rules.iter().any(|rule| rule.is_match(item, config)));
+    len - items.len()
+}
+
+/// Interceptor removing ad cards, banners, live-room injections and game
+/// promotions from app responses.
+#[derive(Debug, Clone, Copy)]
+pub struct PurifyInterceptor {
+    target: PurifyTarget,
+}
+
+impl PurifyInterceptor {
+    #[inline]
+    pub const fn new(target: PurifyTarget) -> Self {
+        Self { target }
+    }
+}
+
+impl InterceptT for PurifyInterceptor {
+    #[tracing::instrument(
+        level = "debug",
+        name = "PurifyInterceptor.intercept_request",
+        skip(self),
+        err
+    )]
+    async fn intercept_request(&self, request: &mut AxumRequest) -> Result<Option<AxumResponse>> {
+        DefaultInterceptor.intercept_request(request).await
+    }
+
+    #[tracing::instrument(
+        level = "debug",
+        name = "PurifyInterceptor.intercept_response",
+        fields(purify.target = ?self.target),
+        skip_all,
+        err
+    )]
+    async fn intercept_response(
+        &self,
+        response: &mut AxumResponse,
+    ) -> Result<Option<AxumResponse>> {
+        intercept_json_response(self, response).await
+    }
+
+    #[inline]
+    fn rewrites_json(&self, _path: &str) -> bool {
+        purify_config().is_enabled()
+    }
+
+    fn rewrite_json(&self, _path: &str, json: &mut Value) -> usize {
+        let purified = self.target.purify(json, &purify_config());
+        if purified > 0 {
+            tracing::debug!("Purified {} item(s) of {:?}", purified, self.target);
+        }
+        purified
+    }
+}
+
+/// gRPC rule: remove ads from `bilibili.app.view.v1.View/View`
+pub fn purify_view_reply(reply: &mut ViewReply) -> bool {
+    purify_view_reply_with(reply, &purify_config())
+}
+
+#[inline]
+fn purify_view_reply_with(reply: &mut ViewReply, config: &PurifyConfig) -> bool {
+    if !config.ad {
+        return false;
+    }
+
+    let mut purified = !reply.cms.is_empty() || reply.cm_config.is_some();
+    reply.cms.clear();
+    reply.cm_config = None;
+
+    let len = reply.relates.len();
+    reply.relates.retain(|relate| relate.goto != "cm");
+
+    purified || len != reply.relates.len()
+}
+
+/// gRPC rule: remove ads from `bilibili.main.community.reply.v1.Reply/MainList`
+pub fn purify_main_list_reply(reply: &mut MainListReply) -> bool {
+    purify_main_list_reply_with(reply, &purify_config())
+}
+
+#[inline]
+fn purify_main_list_reply_with(reply: &mut MainListReply, config: &PurifyConfig) -> bool {
+    config.ad && reply.cm.take().is_some()
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_purify_feed_index() {
+        let mut json = json!({
+            "code": 0,
+            "data": {
+                "items": [
+                    { "card_goto": "av", "goto": "av", "param": "1" },
+                    { "card_goto": "ad_web_s", "goto": "ad_web", "ad_info": {} },
+                    { "card_goto": "banner", "banner_item": [] },
+                    { "card_goto": "live", "goto": "live" },
+                    { "card_goto": "game", "goto": "game" },
+                ]
+            }
+        });
+
+        let config = PurifyConfig {
+            live: false,
+            ..Default::default()
+        };
+
+        assert_eq!(PurifyTarget::FeedIndex.purify(&mut json, &config), 3);
+        assert_eq!(
+            json["data"]["items"],
+            json!([
+                { "card_goto": "av", "goto": "av", "param": "1" },
+                { "card_goto": "live", "goto": "live" },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_purify_splash_list() {
+        let mut json = json!({
+            "code": 0,
+            "data": {
+                "list": [
+                    { "id": 1, "is_ad": true },
+                    { "id": 2, "is_ad": false },
+                ],
+                "show": [{ "id": 1 }, { "id": 2 }]
+            }
+        });
+
+        assert_eq!(
+            PurifyTarget::SplashList.purify(&mut json, &PurifyConfig::default()),
+            1
+        );
+        assert_eq!(json["data"]["list"], json!([{ "id": 2, "is_ad": false }]));
+        assert_eq!(json["data"]["show"], json!([{ "id": 2 }]));
+    }
+
+    #[test]
+    fn test_purify_view_reply() {
+        use lib_bilibili::bapis::app::view::v1::Relate;
+
+        let relate = |goto: &str| Relate {
+            goto: goto.to_owned(),
+            ..Default::default()
+        };
+        let sample = || ViewReply {
+            cms: vec![Default::default()],
+            cm_config: Some(Default::default()),
+            relates: vec![relate("av"), relate("cm"), relate("bangumi")],
+            ..Default::default()
+        };
+
+        let mut reply = sample();
+        assert!(purify_view_reply_with(&mut reply, &PurifyConfig::default()));
+        assert!(reply.cms.is_empty());
+        assert!(reply.cm_config.is_none());
+        assert_eq!(
+            reply
+                .relates
+                .iter()
+                .map(|r| r.goto.as_str())
+                .collect::<Vec<_>>(),
+            ["av", "bangumi"]
+        );
+
+        // Nothing left to remove
+        assert!(!purify_view_reply_with(
+            &mut reply,
+            &PurifyConfig::default()
+        ));
+
+        let config = PurifyConfig {
+            ad: false,
+            ..Default::default()
+        };
+        let mut reply = sample();
+        assert!(!purify_view_reply_with(&mut reply, &config));
+        assert_eq!(reply.relates.len(), 3);
+    }
+
+    #[test]
+    fn test_purify_main_list_reply() {
+        let sample = || MainListReply {
+            cm: Some(Default::default()),
+            ..Default::default()
+        };
+
+        let mut reply = sample();
+        assert!(purify_main_list_reply_with(
+            &mut reply,
+            &PurifyConfig::default()
+        ));
+        assert!(reply.cm.is_none());
+        assert!(!purify_main_list_reply_with(
+            &mut reply,
+            &PurifyConfig::default()
+        ));
+
+        let config = PurifyConfig {
+            ad: false,
+            ..Default::default()
+        };
+        let mut reply = sample();
+        assert!(!purify_main_list_reply_with(&mut reply, &config));
+        assert!(reply.cm.is_some());
+    }
+
+    #[test]
+    fn test_purify_search_default() {
+        let mut json = json!({
+            "code": 0,
+            "data": { "trackid": "1", "show": "promoted", "word": "promoted", "goto": "" }
+        });
+
+        assert_eq!(
+            PurifyTarget::SearchDefault.purify(&mut json, &PurifyConfig::default()),
+            2
+        );
+        assert_eq!(json["data"]["show"], "");
+        assert_eq!(json["data"]["trackid"], "1");
+    }
+}
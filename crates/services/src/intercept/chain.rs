@@ -0,0 +1,382 @@
+use anyhow::Result;
+use axum::{extract::Request as AxumRequest, response::Response as AxumResponse};
+use serde_json::Value;
+use tracing::Instrument;
+
+use std::{fmt::Debug as FmtDebug, future::Future, pin::Pin, sync::Arc};
+
+use super::{InterceptT, InterceptUri, JsonBody};
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Object safe version of [InterceptT], implemented for all [Sync] interceptors
+/// so that they can be boxed as links of [InterceptChain].
+pub trait DynInterceptT: 'static + FmtDebug + Send + Sync {
+    /// Name of the interceptor, for tracing
+    fn name(&self) -> &'static str;
+
+    fn intercept_request_boxed<'a>(
+        &'a self,
+        request: &'a mut AxumRequest,
+    ) -> BoxFuture<'a, Result<Option<AxumResponse>>>;
+
+    fn intercept_response_boxed<'a>(
+        &'a self,
+        response: &'a mut AxumResponse,
+    ) -> BoxFuture<'a, Result<Option<AxumResponse>>>;
+
+    fn rewrites_json(&self, path: &str) -> bool;
+
+    fn rewrite_json(&self, path: &str, json: &mut Value) -> usize;
+}
+
+impl<I: InterceptT + Sync> DynInterceptT for I {
+    #[inline]
+    fn name(&self) -> &'static str {
+        std::any::type_name::<I>()
+    }
+
+    #[inline]
+    fn intercept_request_boxed<'a>(
+        &'a self,
+        request: &'a mut AxumRequest,
+    ) -> BoxFuture<'a, Result<Option<AxumResponse>>> {
+        Box::pin(self.intercept_request(request))
+    }
+
+    #[inline]
+    fn intercept_response_boxed<'a>(
+        &'a self,
+        response: &'a mut AxumResponse,
+    ) -> BoxFuture<'a, Result<Option<AxumResponse>>> {
+        Box::pin(self.intercept_response(response))
+    }
+
+    #[inline]
+    fn rewrites_json(&self, path: &str) -> bool {
+        InterceptT::rewrites_json(self, path)
+    }
+
+    #[inline]
+    fn rewrite_json(&self, path: &str, json: &mut Value) -> usize {
+        InterceptT::rewrite_json(self, path, json)
+    }
+}
+
+/// An ordered chain of interceptors, itself an interceptor.
+///
+/// Request phases run in order and response phases in reverse. A link may
+/// short-circuit with a synthesized response in its request phase, then only
+/// links before it see that response, in reverse as well.
+///
+/// Links rewriting JSON bodies, see [InterceptT::rewrite_json], share one
+/// parsed body, which is written back before a link seeing the whole response
+/// and at the end.
+#[derive(Debug, Clone)]
+pub struct InterceptChain {
+    links: Arc<[Arc<dyn DynInterceptT>]>,
+}
+
+impl Default for InterceptChain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InterceptChain {
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            links: Arc::from(Vec::new()),
+        }
+    }
+
+    /// Append an interceptor to the end of the chain.
+    pub fn with(self, link: impl InterceptT + Sync) -> Self {
+        let mut links = self.links.to_vec();
+        links.push(Arc::new(link));
+        Self {
+            links: links.into(),
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.links.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+
+    /// Run response phases of given links in reverse.
+    async fn intercept_response_links(
+        links: &[Arc<dyn DynInterceptT>],
+        response: &mut AxumResponse,
+    ) -> Result<()> {
+        let path = response
+            .extensions()
+            .get::<InterceptUri>()
+            .filter(|_| response.status().is_success())
+            .map(|InterceptUri(uri)| uri.path().to_owned());
+        let mut json: Option<JsonBody> = None;
+
+        for (index, link) in links.iter().enumerate().rev() {
+            let span = tracing::debug_span!(
+                "InterceptChain.link",
+                link.index = index,
+                link.name = link.name(),
+                link.phase = "response"
+            );
+
+            if let Some(path) = path.as_deref().filter(|path| link.rewrites_json(path)) {
+                let json = match &mut json {
+                    Some(json) => json,
+                    None => json.insert(JsonBody::take(response).await?),
+                };
+                let modified = span.in_scope(|| json.rewrite(|json| link.rewrite_json(path, json)));
+                if modified > 0 {
+                    tracing::debug!("Modified {} value(s) by [{}]", modified, link.name());
+                }
+                continue;
+            }
+
+            if let Some(json) = json.take() {
+                json.write_back(response)?;
+            }
+
+            if let Some(new_response) = link
+                .intercept_response_boxed(response)
+                .instrument(span)
+                .await?
+            {
+                // Keep the original URI for links left
+                let uri = response.extensions_mut().remove::<InterceptUri>();
+                *response = new_response;
+                if let Some(uri) = uri {
+                    response.extensions_mut().insert(uri);
+                }
+            }
+        }
+
+        if let Some(json) = json {
+            json.write_back(response)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl InterceptT for InterceptChain {
+    #[tracing::instrument(
+        level = "debug",
+        name = "InterceptChain.intercept_request",
+        fields(chain.len = self.links.len()),
+        skip_all,
+        err
+    )]
+    async fn intercept_request(&self, request: &mut AxumRequest) -> Result<Option<AxumResponse>> {
+        for (index, link) in self.links.iter().enumerate() {
+            let span = tracing::debug_span!(
+                "InterceptChain.link",
+                link.index = index,
+                link.name = link.name(),
+                link.phase = "request"
+            );
+
+            let Some(mut response) = link
+                .intercept_request_boxed(request)
+                .instrument(span)
+                .await?
+            else {
+                continue;
+            };
+
+            tracing::debug!("Short-circuited by [{}]", link.name());
+
+            response
+                .extensions_mut()
+                .insert(InterceptUri(request.uri().clone()));
+            Self::intercept_response_links(&self.links[..index], &mut response).await?;
+
+            return Ok(Some(response));
+        }
+
+        Ok(None)
+    }
+
+    #[tracing::instrument(
+        level = "debug",
+        name = "InterceptChain.intercept_response",
+        fields(chain.len = self.links.len()),
+        skip_all,
+        err
+    )]
+    async fn intercept_response(
+        &self,
+        response: &mut AxumResponse,
+    ) -> Result<Option<AxumResponse>> {
+        Self::intercept_response_links(&self.links, response).await?;
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::body::Body;
+    use http::{HeaderValue, StatusCode};
+
+    use super::*;
+
+    /// Append its name to header `x-trace` of requests and responses, or to
+    /// `trace` of JSON bodies if `json`
+    #[derive(Debug, Clone)]
+    struct Trace {
+        name: &'static str,
+        short_circuit: bool,
+        json: bool,
+    }
+
+    fn append(headers: &mut http::HeaderMap, name: &str) {
+        let trace = headers
+            .get("x-trace")
+            .and_then(|t| t.to_str().ok())
+            .map_or_else(|| name.to_owned(), |t| format!("{},{}", t, name));
+        headers.insert("x-trace", HeaderValue::try_from(trace).unwrap());
+    }
+
+    impl InterceptT for Trace {
+        async fn intercept_request(
+            &self,
+            request: &mut AxumRequest,
+        ) -> Result<Option<AxumResponse>> {
+            append(request.headers_mut(), self.name);
+
+            if self.short_circuit {
+                let mut response = AxumResponse::new(Body::empty());
+                *response.status_mut() = StatusCode::FORBIDDEN;
+                return Ok(Some(response));
+            }
+            Ok(None)
+        }
+
+        async fn intercept_response(
+            &self,
+            response: &mut AxumResponse,
+        ) -> Result<Option<AxumResponse>> {
+            append(response.headers_mut(), self.name);
+            Ok(None)
+        }
+
+        fn rewrites_json(&self, _path: &str) -> bool {
+            self.json
+        }
+
+        fn rewrite_json(&self, _path: &str, json: &mut Value) -> usize {
+            match json["trace"].as_array_mut() {
+                Some(trace) => trace.push(self.name.into()),
+                None => json["trace"] = serde_json::json!([self.name]),
+            }
+            1
+        }
+    }
+
+    fn chain(short_circuit_at: Option<&'static str>) -> InterceptChain {
+        ["a", "b", "c"]
+            .into_iter()
+            .fold(InterceptChain::new(), |chain, name| {
+                chain.with(Trace {
+                    name,
+                    short_circuit: short_circuit_at == Some(name),
+                    json: false,
+                })
+            })
+    }
+
+    #[tokio::test]
+    async fn test_chain_order() {
+        let chain = chain(None);
+
+        let mut request = AxumRequest::new(Body::empty());
+        assert!(chain
+            .intercept_request(&mut request)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(request.headers()["x-trace"], "a,b,c");
+
+        let mut response = AxumResponse::new(Body::empty());
+        assert!(chain
+            .intercept_response(&mut response)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(response.headers()["x-trace"], "c,b,a");
+    }
+
+    #[tokio::test]
+    async fn test_chain_json_order() {
+        let trace = |name, json| Trace {
+            name,
+            short_circuit: false,
+            json,
+        };
+        // Like purification followed by rewrite rules on the response, with
+        // one seeing the whole response between them
+        let chain = InterceptChain::new()
+            .with(trace("rules", true))
+            .with(trace("rewrite", true))
+            .with(trace("headers", false))
+            .with(trace("purify", true));
+
+        let mut response = AxumResponse::new(Body::from(r#"{"code":0}"#));
+        response
+            .extensions_mut()
+            .insert(InterceptUri("/x/v2/feed/index".parse().unwrap()));
+        assert!(chain
+            .intercept_response(&mut response)
+            .await
+            .unwrap()
+            .is_none());
+
+        assert_eq!(response.headers()["x-trace"], "headers");
+        let body = axum::body::to_bytes(std::mem::take(response.body_mut()), usize::MAX)
+            .await
+            .unwrap();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            json["trace"],
+            serde_json::json!(["purify", "rewrite", "rules"])
+        );
+
+        // Not rewritten if not successful
+        let mut response = AxumResponse::new(Body::from(r#"{"code":0}"#));
+        *response.status_mut() = StatusCode::BAD_GATEWAY;
+        response
+            .extensions_mut()
+            .insert(InterceptUri("/x/v2/feed/index".parse().unwrap()));
+        chain.intercept_response(&mut response).await.unwrap();
+        let body = axum::body::to_bytes(std::mem::take(response.body_mut()), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, r#"{"code":0}"#);
+    }
+
+    #[tokio::test]
+    async fn test_chain_short_circuit() {
+        let chain = chain(Some("c"));
+
+        let mut request = AxumRequest::new(Body::empty());
+        let response = chain
+            .intercept_request(&mut request)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(request.headers()["x-trace"], "a,b,c");
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(response.headers()["x-trace"], "b,a");
+    }
+}
@@ -0,0 +1,278 @@
+use anyhow::{bail, Result};
+use axum::{body::Body, extract::Request as AxumRequest, response::Response as AxumResponse};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use http_body_util::{BodyExt, Limited};
+
+use std::{
+    fmt::Debug as FmtDebug,
+    io::{Read, Write},
+    marker::PhantomData,
+};
+
+use super::{DefaultInterceptor, InterceptT};
+use lib_utils::{error::ServerError, model::response::TrailersBody};
+
+/// Length of gRPC message prefix: 1 byte compressed flag + 4 bytes length
+const GRPC_HEADER_LEN: usize = 5;
+
+/// Length-prefixed gRPC messages, decompressed.
+#[derive(Debug, Default)]
+pub struct GrpcFrames {
+    /// Whether each message is gzip compressed originally
+    compressed: Vec<bool>,
+    messages: Vec<Bytes>,
+}
+
+impl GrpcFrames {
+    /// Decode length-prefixed gRPC messages from given body.
+    ///
+    /// `gzip` should be set when `grpc-encoding` of the response is `gzip`.
+    #[tracing::instrument(level = "debug", name = "GrpcFrames.decode", skip(data), err)]
+    pub fn decode(mut data: Bytes, gzip: bool) -> Result<Self> {
+        let mut messages = Vec::with_capacity(1);
+        let mut compressed = Vec::with_capacity(1);
+
+        while data.has_remaining() {
+            if data.remaining() < GRPC_HEADER_LEN {
+                tracing::error!("Incomplete gRPC message prefix");
+                bail!(ServerError::Serialization)
+            }
+
+            let flag = data.get_u8();
+            let len = data.get_u32() as usize;
+            if data.remaining() < len {
+                tracing::error!(
+                    "Incomplete gRPC message, expected {} bytes, actually {}",
+                    len,
+                    data.remaining()
+                );
+                bail!(ServerError::Serialization)
+            }
+
+            let message = data.split_to(len);
+            let message = match flag {
+                0 => message,
+                1 if gzip => {
+                    let mut buf = Vec::with_capacity(len * 4);
+                    GzDecoder::new(&message[..]).read_to_end(&mut buf)?;
+                    buf.into()
+                }
+                _ => {
+                    tracing::error!("Unsupported gRPC compressed flag [{}]", flag);
+                    bail!(ServerError::Serialization)
+                }
+            };
+            compressed.push(flag == 1);
+            messages.push(message);
+        }
+
+        Ok(Self {
+            compressed,
+            messages,
+        })
+    }
+
+    /// Encode messages with length-prefix, each compressed as the original one.
+    #[tracing::instrument(level = "debug", name = "GrpcFrames.encode", skip(self), err)]
+    pub fn encode(self) -> Result<Bytes> {
+        let mut buf = BytesMut::with_capacity(
+            self.messages
+                .iter()
+                .map(|m| m.len() + GRPC_HEADER_LEN)
+                .sum(),
+        );
+
+        for (message, compressed) in self.messages.into_iter().zip(self.compressed) {
+            let message = if compressed {
+                let mut encoder =
+                    GzEncoder::new(Vec::with_capacity(message.len()), Compression::default());
+                encoder.write_all(&message)?;
+                Bytes::from(encoder.finish()?)
+            } else {
+                message
+            };
+
+            buf.put_u8(compressed as u8);
+            buf.put_u32(message.len() as u32);
+            buf.put(message);
+        }
+
+        Ok(buf.freeze())
+    }
+
+    #[inline]
+    pub fn messages(&self) -> &[Bytes] {
+        &self.messages
+    }
+
+    /// Messages to be mutated in place, the count of them is kept so that
+    /// each one is encoded with its own compressed flag.
+    #[inline]
+    pub fn messages_mut(&mut self) -> &mut [Bytes] {
+        &mut self.messages
+    }
+}
+
+/// Rule to mutate a decoded gRPC message, return `true` if modified.
+pub type GrpcRewriteRule<M> = fn(&mut M) -> bool;
+
+/// Interceptor decoding gRPC responses into typed messages, which will
+/// then be mutated with given rule and re-encoded.
+pub struct GrpcInterceptor<M> {
+    rule: GrpcRewriteRule<M>,
+    _message: PhantomData<fn() -> M>,
+}
+
+impl<M> GrpcInterceptor<M> {
+    #[inline]
+    pub const fn new(rule: GrpcRewriteRule<M>) -> Self {
+        Self {
+            rule,
+            _message: PhantomData,
+        }
+    }
+}
+
+impl<M> Clone for GrpcInterceptor<M> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<M> Copy for GrpcInterceptor<M> {}
+
+impl<M> FmtDebug for GrpcInterceptor<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GrpcInterceptor")
+            .field("message", &std::any::type_name::<M>())
+            .finish()
+    }
+}
+
+impl<M: prost::Message + Default + 'static> InterceptT for GrpcInterceptor<M> {
+    #[tracing::instrument(
+        level = "debug",
+        name = "GrpcInterceptor.intercept_request",
+        skip(self),
+        err
+    )]
+    async fn intercept_request(&self, request: &mut AxumRequest) -> Result<Option<AxumResponse>> {
+        DefaultInterceptor.intercept_request(request).await
+    }
+
+    #[tracing::instrument(
+        level = "debug",
+        name = "GrpcInterceptor.intercept_response",
+        fields(grpc.message = std::any::type_name::<M>()),
+        skip_all,
+        err
+    )]
+    async fn intercept_response(
+        &self,
+        response: &mut AxumResponse,
+    ) -> Result<Option<AxumResponse>> {
+        if !response.status().is_success() {
+            return Ok(None);
+        }
+
+        let gzip = response
+            .headers()
+            .get("grpc-encoding")
+            .is_some_and(|e| e.as_bytes() == b"gzip");
+
+        let body = Limited::new(
+            std::mem::take(response.body_mut()),
+            crate::max_buffer_size(),
+        )
+        .collect()
+        .await
+        .map_err(crate::buffer_error)?;
+        let trailers = body.trailers().cloned();
+        let data = body.to_bytes();
+
+        // Trailers-only response or empty one
+        if data.is_empty() {
+            *response.body_mut() = Body::new(TrailersBody::new(data, trailers));
+            return Ok(None);
+        }
+
+        let mut frames = match GrpcFrames::decode(data.clone(), gzip) {
+            Ok(frames) => frames,
+            Err(e) => {
+                tracing::warn!("Invalid gRPC response, skip rewriting: {}", e);
+                *response.body_mut() = Body::new(TrailersBody::new(data, trailers));
+                return Ok(None);
+            }
+        };
+
+        let mut modified = false;
+        for message in frames.messages_mut().iter_mut() {
+            let mut decoded = match M::decode(message.clone()) {
+                Ok(decoded) => decoded,
+                Err(e) => {
+                    tracing::warn!("Decode gRPC message error, skip rewriting: {}", e);
+                    continue;
+                }
+            };
+            if (self.rule)(&mut decoded) {
+                *message = decoded.encode_to_vec().into();
+                modified = true;
+            }
+        }
+
+        let data = if modified {
+            tracing::debug!("gRPC response rewritten");
+            response.headers_mut().remove(http::header::CONTENT_LENGTH);
+            frames.encode()?
+        } else {
+            data
+        };
+
+        *response.body_mut() = Body::new(TrailersBody::new(data, trailers));
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_grpc_frames() {
+        let messages = vec![Bytes::from_static(b"hello"), Bytes::from_static(b"world")];
+
+        for compressed in [vec![false, false], vec![true, true], vec![true, false]] {
+            let encoded = GrpcFrames {
+                compressed: compressed.clone(),
+                messages: messages.clone(),
+            }
+            .encode()
+            .unwrap();
+
+            let decoded = GrpcFrames::decode(encoded, true).unwrap();
+            assert_eq!(decoded.compressed, compressed);
+            assert_eq!(decoded.messages(), &messages[..]);
+
+            // Each frame keeps its own flag when encoded again
+            assert_eq!(decoded.encode().unwrap()[0], compressed[0] as u8);
+        }
+
+        let mixed = GrpcFrames {
+            compressed: vec![true, false],
+            messages: messages.clone(),
+        }
+        .encode()
+        .unwrap();
+        let plain_frame_at = mixed.len() - GRPC_HEADER_LEN - messages[1].len();
+        assert_eq!(mixed[plain_frame_at], 0);
+        assert_eq!(&mixed[plain_frame_at + GRPC_HEADER_LEN..], b"world");
+    }
+
+    #[test]
+    fn test_grpc_frames_incomplete() {
+        let data = Bytes::from_static(&[0, 0, 0, 0, 5, b'h', b'i']);
+        assert!(GrpcFrames::decode(data, false).is_err());
+    }
+}
@@ -0,0 +1,460 @@
+use anyhow::{bail, Result};
+use axum::{extract::Request as AxumRequest, response::Response as AxumResponse};
+use serde_json::Value;
+
+use std::{str::FromStr, sync::OnceLock};
+
+use super::{intercept_json_response, DefaultInterceptor, InterceptT};
+use lib_core::server::config::{
+    PredicateOp, RewriteAction, RewritePredicate, RewriteRuleConfig, CONFIG_INTERCEPT,
+};
+
+static REWRITE_RULES: OnceLock<Vec<RewriteRule>> = OnceLock::new();
+
+/// Rewrite rules declared in config, invalid ones skipped.
+pub fn rewrite_rules() -> &'static [RewriteRule] {
+    REWRITE_RULES.get_or_init(|| {
+        CONFIG_INTERCEPT
+            .get()
+            .map(|config| {
+                config
+                    .rules
+                    .iter()
+                    .filter_map(|rule| match RewriteRule::try_from(rule) {
+                        Ok(rule) => Some(rule),
+                        Err(e) => {
+                            tracing::error!(
+                                "Invalid rewrite rule [{}] [{}], skipped: {}",
+                                rule.path,
+                                rule.selector,
+                                e
+                            );
+                            None
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    })
+}
+
+/// Apply rules matching given path to the JSON, return count of values modified.
+pub fn apply_rules(rules: &[RewriteRule], path: &str, json: &mut Value) -> usize {
+    rules
+        .iter()
+        .filter(|rule| rule.is_match(path))
+        .map(|rule| rule.apply(json))
+        .sum()
+}
+
+/// A segment of [Selector]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    /// `.key` or `['key']`
+    Key(String),
+    /// `[0]`
+    Index(usize),
+    /// `.*` or `[*]`, all fields of an object or items of an array
+    Wildcard,
+}
+
+/// A JSONPath-like selector, like `$.data.items[*].ad_info`.
+///
+/// Only child segments are supported, without recursive descent, slices or filters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selector {
+    segments: Vec<Segment>,
+}
+
+impl FromStr for Selector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let Some(mut rest) = s.strip_prefix('$') else {
+            bail!("Selector should start with `$`");
+        };
+
+        let mut segments = Vec::new();
+        while !rest.is_empty() {
+            if let Some(r) = rest.strip_prefix('.') {
+                let end = r.find(['.', '[']).unwrap_or(r.len());
+                let segment = match &r[..end] {
+                    "" => bail!("Empty key in selector"),
+                    "*" => Segment::Wildcard,
+                    key => Segment::Key(key.to_owned()),
+                };
+                segments.push(segment);
+                rest = &r[end..];
+            } else if let Some(r) = rest.strip_prefix('[') {
+                let Some(end) = r.find(']') else {
+                    bail!("Unclosed `[` in selector");
+                };
+                let inner = r[..end].trim();
+                let segment = if inner == "*" {
+                    Segment::Wildcard
+                } else if let Some(key) = inner
+                    .strip_prefix('\'')
+                    .and_then(|i| i.strip_suffix('\''))
+                    .or_else(|| inner.strip_prefix('"').and_then(|i| i.strip_suffix('"')))
+                {
+                    Segment::Key(key.to_owned())
+                } else if let Ok(index) = inner.parse() {
+                    Segment::Index(index)
+                } else {
+                    bail!("Invalid index [{}] in selector", inner);
+                };
+                segments.push(segment);
+                rest = &r[end + 1..];
+            } else {
+                bail!("Unexpected [{}] in selector", rest);
+            }
+        }
+
+        Ok(Self { segments })
+    }
+}
+
+impl Selector {
+    /// Apply the action to values selected, return count of values modified.
+    pub fn apply(&self, json: &mut Value, action: &RewriteAction) -> usize {
+        let Some((last, parents)) = self.segments.split_last() else {
+            return apply_root(json, action);
+        };
+
+        let mut modified = 0;
+        visit(json, parents, &mut |parent| {
+            modified += apply_child(parent, last, action)
+        });
+        modified
+    }
+}
+
+fn visit(value: &mut Value, segments: &[Segment], f: &mut impl FnMut(&mut Value)) {
+    match segments.split_first() {
+        Some((first, rest)) => {
+            for child in children_mut(value, first) {
+                visit(child, rest, f)
+            }
+        }
+        None => f(value),
+    }
+}
+
+fn children_mut<'v>(value: &'v mut Value, segment: &Segment) -> Vec<&'v mut Value> {
+    match (segment, value) {
+        (Segment::Key(key), Value::Object(object)) => object.get_mut(key).into_iter().collect(),
+        (Segment::Index(index), Value::Array(array)) => array.get_mut(*index).into_iter().collect(),
+        (Segment::Wildcard, Value::Object(object)) => object.values_mut().collect(),
+        (Segment::Wildcard, Value::Array(array)) => array.iter_mut().collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn apply_root(json: &mut Value, action: &RewriteAction) -> usize {
+    match action {
+        RewriteAction::Remove => {
+            *json = Value::Null;
+            1
+        }
+        RewriteAction::Replace { value } | RewriteAction::Set { value } => {
+            *json = value.clone();
+            1
+        }
+        RewriteAction::Filter { predicate } => filter_items(json, predicate),
+    }
+}
+
+fn apply_child(parent: &mut Value, last: &Segment, action: &RewriteAction) -> usize {
+    match action {
+        RewriteAction::Remove => match (last, parent) {
+            (Segment::Key(key), Value::Object(object)) => object.remove(key).is_some() as usize,
+            (Segment::Index(index), Value::Array(array)) if *index < array.len() => {
+                array.remove(*index);
+                1
+            }
+            (Segment::Wildcard, Value::Object(object)) => {
+                let len = object.len();
+                object.clear();
+                len
+            }
+            (Segment::Wildcard, Value::Array(array)) => {
+                let len = array.len();
+                array.clear();
+                len
+            }
+            _ => 0,
+        },
+        RewriteAction::Set { value } => match (last, parent) {
+            (Segment::Key(key), Value::Object(object)) => {
+                object.insert(key.clone(), value.clone());
+                1
+            }
+            (last, parent) => replace_children(parent, last, value),
+        },
+        RewriteAction::Replace { value } => replace_children(parent, last, value),
+        RewriteAction::Filter { predicate } => children_mut(parent, last)
+            .into_iter()
+            .map(|child| filter_items(child, predicate))
+            .sum(),
+    }
+}
+
+#[inline]
+fn replace_children(parent: &mut Value, last: &Segment, value: &Value) -> usize {
+    let children = children_mut(parent, last);
+    let len = children.len();
+    for child in children {
+        *child = value.clone();
+    }
+    len
+}
+
+/// Remove items matching the predicate, return count of items removed.
+fn filter_items(items: &mut Value, predicate: &RewritePredicate) -> usize {
+    let Some(items) = items.as_array_mut() else {
+        return 0;
+    };
+    let len = items.len();
+    items.retain(|item| !is_match(item, predicate));
+    len - items.len()
+}
+
+fn is_match(item: &Value, predicate: &RewritePredicate) -> bool {
+    let field = predicate
+        .key
+        .split('.')
+        .try_fold(item, |value, key| value.get(key))
+        .filter(|value| !value.is_null());
+
+    match (predicate.op, field) {
+        (PredicateOp::Exists, field) => field.is_some(),
+        (PredicateOp::Truthy, Some(Value::Bool(b))) => *b,
+        (PredicateOp::Truthy, Some(Value::Number(n))) => n.as_f64().is_some_and(|n| n != 0.0),
+        (PredicateOp::Truthy, Some(Value::String(s))) => !s.is_empty(),
+        (PredicateOp::Eq, Some(field)) => *field == predicate.value,
+        (PredicateOp::Ne, Some(field)) => *field != predicate.value,
+        (PredicateOp::StartsWith, Some(Value::String(s))) => predicate
+            .value
+            .as_str()
+            .is_some_and(|prefix| s.starts_with(prefix)),
+        _ => false,
+    }
+}
+
+/// Request path a rule applies to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathPattern {
+    Exact(String),
+    /// Path declared ending with `*`
+    Prefix(String),
+}
+
+impl PathPattern {
+    #[inline]
+    fn is_match(&self, path: &str) -> bool {
+        match self {
+            Self::Exact(exact) => path == exact,
+            Self::Prefix(prefix) => path.starts_with(prefix.as_str()),
+        }
+    }
+}
+
+impl From<&str> for PathPattern {
+    fn from(path: &str) -> Self {
+        match path.strip_suffix('*') {
+            Some(prefix) => Self::Prefix(prefix.to_owned()),
+            None => Self::Exact(path.to_owned()),
+        }
+    }
+}
+
+/// Compiled [RewriteRuleConfig]
+#[derive(Debug, Clone)]
+pub struct RewriteRule {
+    path: PathPattern,
+    selector: Selector,
+    action: RewriteAction,
+}
+
+impl TryFrom<&RewriteRuleConfig> for RewriteRule {
+    type Error = anyhow::Error;
+
+    fn try_from(config: &RewriteRuleConfig) -> Result<Self> {
+        Ok(Self {
+            path: config.path.as_str().into(),
+            selector: config.selector.parse()?,
+            action: config.action.clone(),
+        })
+    }
+}
+
+impl RewriteRule {
+    #[inline]
+    pub fn is_match(&self, path: &str) -> bool {
+        self.path.is_match(path)
+    }
+
+    /// Apply the rule to the JSON, return count of values modified.
+    #[inline]
+    pub fn apply(&self, json: &mut Value) -> usize {
+        self.selector.apply(json, &self.action)
+    }
+}
+
+/// Interceptor applying rewrite rules declared in config to JSON responses.
+#[derive(Debug, Clone, Copy)]
+pub struct RewriteInterceptor;
+
+impl InterceptT for RewriteInterceptor {
+    #[tracing::instrument(
+        level = "debug",
+        name = "RewriteInterceptor.intercept_request",
+        skip(self),
+        err
+    )]
+    async fn intercept_request(&self, request: &mut AxumRequest) -> Result<Option<AxumResponse>> {
+        DefaultInterceptor.intercept_request(request).await
+    }
+
+    #[tracing::instrument(
+        level = "debug",
+        name = "RewriteInterceptor.intercept_response",
+        skip_all,
+        err
+    )]
+    async fn intercept_response(
+        &self,
+        response: &mut AxumResponse,
+    ) -> Result<Option<AxumResponse>> {
+        intercept_json_response(self, response).await
+    }
+
+    #[inline]
+    fn rewrites_json(&self, path: &str) -> bool {
+        rewrite_rules().iter().any(|rule| rule.is_match(path))
+    }
+
+    #[inline]
+    fn rewrite_json(&self, path: &str, json: &mut Value) -> usize {
+        apply_rules(rewrite_rules(), path, json)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn rule(path: &str, selector: &str, action: RewriteAction) -> RewriteRule {
+        RewriteRule::try_from(&RewriteRuleConfig {
+            path: path.to_owned(),
+            selector: selector.to_owned(),
+            action,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_selector_parse() {
+        assert_eq!(
+            "$.data.items[*]['ad_info'][0].*"
+                .parse::<Selector>()
+                .unwrap(),
+            Selector {
+                segments: vec![
+                    Segment::Key("data".to_owned()),
+                    Segment::Key("items".to_owned()),
+                    Segment::Wildcard,
+                    Segment::Key("ad_info".to_owned()),
+                    Segment::Index(0),
+                    Segment::Wildcard,
+                ]
+            }
+        );
+        assert!("$".parse::<Selector>().unwrap().segments.is_empty());
+
+        for invalid in ["data", "$..data", "$.data[", "$.data[x]", "$data"] {
+            assert!(invalid.parse::<Selector>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_rewrite_rules() {
+        let rules = [
+            rule(
+                "/x/v2/feed/*",
+                "$.data.items[*].ad_info",
+                RewriteAction::Remove,
+            ),
+            rule(
+                "/x/v2/feed/index",
+                "$.data.items",
+                RewriteAction::Filter {
+                    predicate: RewritePredicate {
+                        key: "args.up_id".to_owned(),
+                        op: PredicateOp::Eq,
+                        value: json!(1),
+                    },
+                },
+            ),
+            rule(
+                "/x/v2/feed/index",
+                "$.data.config.toast",
+                RewriteAction::Set { value: json!(null) },
+            ),
+            rule(
+                "/x/v2/feed/index",
+                "$.data.items[*].title",
+                RewriteAction::Replace {
+                    value: json!("title"),
+                },
+            ),
+            rule("/x/v2/splash/list", "$.data", RewriteAction::Remove),
+        ];
+
+        let mut json = json!({
+            "code": 0,
+            "data": {
+                "items": [
+                    { "title": "1", "args": { "up_id": 1 } },
+                    { "title": "2", "args": { "up_id": 2 }, "ad_info": {} },
+                    { "args": { "up_id": 3 } },
+                ],
+                "config": {}
+            }
+        });
+
+        assert_eq!(apply_rules(&rules, "/x/v2/feed/index", &mut json), 4);
+        assert_eq!(
+            json,
+            json!({
+                "code": 0,
+                "data": {
+                    "items": [
+                        { "title": "title", "args": { "up_id": 2 } },
+                        { "args": { "up_id": 3 } },
+                    ],
+                    "config": { "toast": null }
+                }
+            })
+        );
+
+        assert_eq!(apply_rules(&rules, "/x/v2/search", &mut json), 0);
+    }
+
+    #[test]
+    fn test_predicate_ne() {
+        let predicate = RewritePredicate {
+            key: "code".to_owned(),
+            op: PredicateOp::Ne,
+            value: json!(0),
+        };
+
+        assert!(is_match(&json!({ "code": -404 }), &predicate));
+        assert!(!is_match(&json!({ "code": 0 }), &predicate));
+        // Missing or `null` fields never match
+        assert!(!is_match(&json!({ "message": "" }), &predicate));
+        assert!(!is_match(&json!({ "code": null }), &predicate));
+    }
+}
@@ -0,0 +1,80 @@
+use anyhow::Result;
+use axum::{extract::Request as AxumRequest, response::Response as AxumResponse};
+use serde_json::Value;
+
+use std::sync::Arc;
+
+use super::{
+    bilibili::{purify_config, PurifyTarget},
+    intercept_json_response,
+    rewrite::{apply_rules, rewrite_rules},
+    DefaultInterceptor, InterceptT,
+};
+use lib_core::server::config::RouteInterceptorKind;
+
+/// Interceptor of a full server route, applying purification and rewrite rules
+/// to JSON responses in the order declared.
+#[derive(Debug, Clone)]
+pub struct RouteInterceptor {
+    kinds: Arc<[RouteInterceptorKind]>,
+}
+
+impl RouteInterceptor {
+    #[inline]
+    pub fn new(kinds: impl Into<Arc<[RouteInterceptorKind]>>) -> Self {
+        Self {
+            kinds: kinds.into(),
+        }
+    }
+}
+
+impl InterceptT for RouteInterceptor {
+    #[tracing::instrument(
+        level = "debug",
+        name = "RouteInterceptor.intercept_request",
+        skip(self),
+        err
+    )]
+    async fn intercept_request(&self, request: &mut AxumRequest) -> Result<Option<AxumResponse>> {
+        DefaultInterceptor.intercept_request(request).await
+    }
+
+    #[tracing::instrument(
+        level = "debug",
+        name = "RouteInterceptor.intercept_response",
+        fields(route.interceptors = ?self.kinds),
+        skip_all,
+        err
+    )]
+    async fn intercept_response(
+        &self,
+        response: &mut AxumResponse,
+    ) -> Result<Option<AxumResponse>> {
+        intercept_json_response(self, response).await
+    }
+
+    fn rewrites_json(&self, path: &str) -> bool {
+        self.kinds.iter().any(|kind| match kind {
+            RouteInterceptorKind::Purify => {
+                purify_config().is_enabled() && PurifyTarget::from_path(path).is_some()
+            }
+            RouteInterceptorKind::Rewrite => rewrite_rules().iter().any(|rule| rule.is_match(path)),
+        })
+    }
+
+    /// Apply purification and rewrite rules in the order declared.
+    fn rewrite_json(&self, path: &str, json: &mut Value) -> usize {
+        let config = purify_config();
+        let target = PurifyTarget::from_path(path).filter(|_| config.is_enabled());
+
+        self.kinds
+            .iter()
+            .map(|kind| match kind {
+                RouteInterceptorKind::Purify => {
+                    target.map_or(0, |target| target.purify(json, &config))
+                }
+                RouteInterceptorKind::Rewrite => apply_rules(rewrite_rules(), path, json),
+            })
+            .sum()
+    }
+}
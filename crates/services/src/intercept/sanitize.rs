@@ -0,0 +1,62 @@
+use anyhow::Result;
+use axum::{extract::Request as AxumRequest, response::Response as AxumResponse};
+use http::HeaderName;
+
+use std::sync::Arc;
+
+use super::InterceptT;
+
+/// Interceptor removing given headers from requests and responses.
+#[derive(Debug, Clone)]
+pub struct SanitizeInterceptor {
+    request: Arc<[HeaderName]>,
+    response: Arc<[HeaderName]>,
+}
+
+impl SanitizeInterceptor {
+    #[inline]
+    pub fn new(
+        request: impl Into<Arc<[HeaderName]>>,
+        response: impl Into<Arc<[HeaderName]>>,
+    ) -> Self {
+        Self {
+            request: request.into(),
+            response: response.into(),
+        }
+    }
+}
+
+impl InterceptT for SanitizeInterceptor {
+    #[tracing::instrument(
+        level = "debug",
+        name = "SanitizeInterceptor.intercept_request",
+        skip(self),
+        err
+    )]
+    async fn intercept_request(&self, request: &mut AxumRequest) -> Result<Option<AxumResponse>> {
+        let headers = request.headers_mut();
+        for name in self.request.iter() {
+            headers.remove(name);
+        }
+
+        Ok(None)
+    }
+
+    #[tracing::instrument(
+        level = "debug",
+        name = "SanitizeInterceptor.intercept_response",
+        skip_all,
+        err
+    )]
+    async fn intercept_response(
+        &self,
+        response: &mut AxumResponse,
+    ) -> Result<Option<AxumResponse>> {
+        let headers = response.headers_mut();
+        for name in self.response.iter() {
+            headers.remove(name);
+        }
+
+        Ok(None)
+    }
+}
@@ -0,0 +1,27 @@
+use anyhow::Result;
+use axum::{extract::Request as AxumRequest, response::Response as AxumResponse};
+use lib_utils::sign::{App, AppkeyInfo};
+
+use super::InterceptT;
+
+/// Interceptor rejecting requests of APP routes not signed with a known
+/// appkey, see [App::verify].
+///
+/// The [AppkeyInfo] of the appkey is inserted into extensions of the request.
+#[derive(Debug, Clone, Copy)]
+pub struct AppSignInterceptor;
+
+impl InterceptT for AppSignInterceptor {
+    #[tracing::instrument(
+        level = "debug",
+        name = "AppSignInterceptor.intercept_request",
+        skip_all,
+        err
+    )]
+    async fn intercept_request(&self, request: &mut AxumRequest) -> Result<Option<AxumResponse>> {
+        let info: &'static AppkeyInfo = App::verify(request.uri().query().unwrap_or_default())?;
+        request.extensions_mut().insert(info);
+
+        Ok(None)
+    }
+}
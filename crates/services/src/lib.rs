@@ -1,10 +1,261 @@
+mod capture;
 pub mod handler;
 pub mod intercept;
 mod model;
+mod rate_limit;
+
+pub use capture::capture_middleware;
+pub use rate_limit::rate_limit_middleware;
 
 pub type HandlerFuture =
     std::pin::Pin<Box<dyn std::future::Future<Output = axum::response::Response> + Send>>;
 
+/// Max size of a body to be buffered, see `max_buffer_size` in config.
+#[inline]
+pub(crate) fn max_buffer_size() -> usize {
+    lib_core::server::config::CONFIG_SERVER
+        .get()
+        .map_or(usize::MAX, |c| c.max_buffer_size)
+}
+
+/// Buffer the whole body, no more than [max_buffer_size].
+///
+/// Only use it when the body must be seen as a whole, or pipe it instead.
+pub(crate) async fn buffer_body(body: axum::body::Body) -> anyhow::Result<bytes::Bytes> {
+    buffer_body_limited(body, max_buffer_size()).await
+}
+
+/// Buffer the whole body, failed once more than `limit` bytes are polled,
+/// or at once if the declared length exceeds it.
+async fn buffer_body_limited(body: axum::body::Body, limit: usize) -> anyhow::Result<bytes::Bytes> {
+    use http_body_util::BodyExt;
+
+    Ok(limit_body_with(body, limit)?
+        .collect()
+        .await
+        .map_err(buffer_error)?
+        .to_bytes())
+}
+
+/// Limit the body to be piped to no more than [max_buffer_size], polling it
+/// fails once exceeded.
+pub(crate) fn limit_body(
+    body: axum::body::Body,
+) -> anyhow::Result<http_body_util::Limited<axum::body::Body>> {
+    limit_body_with(body, max_buffer_size())
+}
+
+/// Limit the body to no more than `limit`, failed at once if the declared
+/// length exceeds it.
+fn limit_body_with(
+    body: axum::body::Body,
+    limit: usize,
+) -> anyhow::Result<http_body_util::Limited<axum::body::Body>> {
+    use axum::body::HttpBody;
+
+    if body.size_hint().lower() > limit as u64 {
+        tracing::error!(
+            "Body of declared length [{}] exceeds buffer size limit [{}]",
+            body.size_hint().lower(),
+            limit
+        );
+        anyhow::bail!(lib_utils::error::ServerError::PayloadTooLarge)
+    }
+
+    Ok(http_body_util::Limited::new(body, limit))
+}
+
+/// Map error when buffering body with limit, [LengthLimitError](http_body_util::LengthLimitError)
+/// into [ServerError::PayloadTooLarge](lib_utils::error::ServerError::PayloadTooLarge).
+pub(crate) fn buffer_error(e: axum::BoxError) -> anyhow::Error {
+    if e.is::<http_body_util::LengthLimitError>() {
+        tracing::error!("Body exceeds buffer size limit [{}]", max_buffer_size());
+        anyhow::anyhow!(lib_utils::error::ServerError::PayloadTooLarge)
+    } else {
+        anyhow::anyhow!(e)
+    }
+}
+
+/// Retry policy of upstream requests, see `retry` in config.
+pub(crate) fn retry_policy() -> Option<lib_rpc::request::RetryPolicy> {
+    let config = lib_core::server::config::CONFIG_RETRY.get()?;
+
+    (config.max_retries > 0).then(|| lib_rpc::request::RetryPolicy {
+        max_retries: config.max_retries,
+        base_delay: std::time::Duration::from_millis(config.base_delay_ms),
+        max_delay: std::time::Duration::from_millis(config.max_delay_ms),
+        switch_proxy: config.switch_proxy,
+    })
+}
+
+/// Timeouts of requests to given upstream type, see `timeouts` in config.
+///
+/// Those of the route, the upstream type and `default` are used in order, and
+/// built-in ones for the rest.
+pub(crate) fn upstream_timeouts(
+    upstream: &lib_rpc::utils::UpstreamType,
+    route: lib_core::server::config::TimeoutConfig,
+) -> lib_rpc::request::Timeouts {
+    use lib_rpc::utils::UpstreamType;
+    use std::time::Duration;
+
+    let mut config = route;
+    if let Some(upstreams) = lib_core::server::config::CONFIG_TIMEOUTS.get() {
+        let by_type = match upstream {
+            UpstreamType::ApiBilibiliCom => upstreams.api,
+            UpstreamType::AppBilibiliCom => upstreams.app,
+            UpstreamType::GrpcBiliapiNet => upstreams.grpc,
+            UpstreamType::Custom => upstreams.custom,
+        };
+        config = config.or(by_type).or(upstreams.default);
+    }
+
+    let default = lib_rpc::request::Timeouts::default();
+    let millis = |ms: Option<u64>, default| ms.map_or(default, Duration::from_millis);
+    lib_rpc::request::Timeouts {
+        connect: millis(config.connect_ms, default.connect),
+        request: millis(config.request_ms, default.request),
+        idle: millis(config.idle_ms, default.idle),
+    }
+}
+
+/// Middleware selecting the language of error messages by the request, see
+/// [Lang::from_request](lib_utils::locale::Lang::from_request).
+pub async fn locale_middleware(
+    req: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let lang = lib_utils::locale::Lang::from_request(req.headers(), req.uri().query());
+    lang.unwrap_or_default().scope(next.run(req)).await
+}
+
+/// Middleware applying the server's [StatusPolicy](lib_utils::model::response::StatusPolicy)
+/// to error responses, see [ServerConfigServer](lib_core::server::config::ServerConfigServer).
+pub async fn status_policy_middleware(
+    req: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let policy = lib_core::server::config::CONFIG_SERVER
+        .get()
+        .map(|config| config.status_policy);
+    policy.unwrap_or_default().scope(next.run(req)).await
+}
+
+/// Map errors of RPCs and their clients into precise error codes, instead of
+/// the general one.
+pub fn init_error_mapper() {
+    lib_utils::error::set_error_mapper(lib_rpc::error::map_error);
+}
+
+/// Options of a concurrency limit in config, `None` for unlimited.
+fn concurrency_limit(
+    config: &lib_core::server::config::ConcurrencyConfig,
+) -> Option<lib_rpc::request::ConcurrencyLimitOptions> {
+    (config.max_concurrent > 0).then(|| lib_rpc::request::ConcurrencyLimitOptions {
+        max_concurrent: config.max_concurrent,
+        max_queued: config.max_queued,
+        queue_timeout: std::time::Duration::from_millis(config.queue_timeout_ms),
+    })
+}
+
+/// Init the limit of concurrent upstream requests in config, if any.
+///
+/// Should be called after config initialized.
+pub fn init_concurrency_limit() {
+    let Some(options) = lib_core::server::config::CONFIG_CONCURRENCY
+        .get()
+        .and_then(concurrency_limit)
+    else {
+        return;
+    };
+
+    if let Err(e) = lib_rpc::request::init_global_limit(options) {
+        tracing::error!("Failed to init concurrency limit: {}", e);
+    }
+}
+
+/// Init device profiles presented to upstream, loaded from the file in config
+/// if any.
+///
+/// Should be called after config initialized.
+pub fn init_device_profiles() {
+    let config = lib_core::server::config::CONFIG_DEVICE
+        .get()
+        .cloned()
+        .unwrap_or_default();
+
+    if let Err(e) = lib_core::business::fingerprint::profile::init_device_profiles(&config) {
+        tracing::error!("Failed to init device profiles: {:?}", e);
+    }
+}
+
+/// Init proxy pools in config and spawn their health checks, invalid ones skipped.
+///
+/// Clients for proxies in pools are created and kept, never evicted.
+///
+/// Should be called within the runtime, after config initialized.
+pub fn init_proxy_pools() {
+    use lib_rpc::request::{
+        get_pool, init_grpc_client, init_reqwest_clients, ProxyPool, ProxyPoolOptions,
+    };
+    use std::time::Duration;
+
+    let configs = lib_core::server::config::CONFIG_PROXY_POOLS
+        .get()
+        .map_or(&[][..], Vec::as_slice);
+
+    let pools = configs
+        .iter()
+        .filter_map(|config| {
+            let mut options = ProxyPoolOptions {
+                max_failures: config.max_failures.max(1),
+                eject_duration: Duration::from_secs(config.eject_secs),
+                health_check_interval: (config.health_check_interval > 0)
+                    .then(|| Duration::from_secs(config.health_check_interval)),
+                ..Default::default()
+            };
+            if let Some(url) = &config.health_check_url {
+                options.health_check_url = url.clone();
+            }
+            options.concurrency = config.concurrency.as_ref().and_then(concurrency_limit);
+
+            let proxies = config.proxies.iter().map(|p| (p.url.as_str(), p.weight));
+            match ProxyPool::new(&config.name, proxies, options) {
+                Ok(pool) => Some(pool),
+                Err(e) => {
+                    tracing::error!("Invalid proxy pool [{}], skipped: {}", config.name, e);
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if let Err(e) = lib_rpc::request::init_proxy_pools(pools) {
+        tracing::error!("Failed to init proxy pools: {}", e);
+        return;
+    }
+
+    let configs: Vec<_> = configs
+        .iter()
+        .filter_map(|config| Some((config, get_pool(&config.name)?)))
+        .collect();
+
+    let proxies: Vec<&str> = configs
+        .iter()
+        .flat_map(|(config, _)| config.proxies.iter().map(|p| p.url.as_str()))
+        .collect();
+    if let Err(e) = init_reqwest_clients(proxies.clone()) {
+        tracing::error!("Failed to init RESTful clients: {}", e);
+    }
+    if let Err(e) = init_grpc_client(proxies) {
+        tracing::error!("Failed to init gRPC clients: {}", e);
+    }
+
+    for (_, pool) in configs {
+        pool.spawn_health_check();
+    }
+}
+
 #[macro_export]
 macro_rules! axum_response {
     ($result:expr) => {
@@ -53,7 +304,11 @@ macro_rules! generate_router {
             pub fn new() -> axum::Router {
                 axum::Router::new()
                     $(
-                        .route($route, crate::axum_route!($method => $handler),)
+                        .route(
+                            $route,
+                            crate::axum_route!($method => $handler)
+                                .fallback(crate::handler::method_not_allowed),
+                        )
                     )*
             }
         }
@@ -66,7 +321,11 @@ macro_rules! generate_router {
             pub fn new() -> axum::Router {
                 axum::Router::new()
                     $(
-                        .route($route, crate::axum_route!($method => $handler),)
+                        .route(
+                            $route,
+                            crate::axum_route!($method => $handler)
+                                .fallback(crate::handler::method_not_allowed),
+                        )
                     )*
                     .fallback::<_, ()>($fallback_handler)
             }
@@ -74,28 +333,6 @@ macro_rules! generate_router {
     };
 }
 
-/// Convert headers between from http 0.2 and http 1.0
-#[macro_export]
-macro_rules! http02_compat {
-    ($name:ident, $original_map:expr, $compat:ident) => {
-        let mut $name = $compat::HeaderMap::with_capacity($original_map.len());
-        {
-            let mut key = None;
-            $original_map.into_iter().for_each(|(k, v)| {
-                if let Some(k) = k {
-                    key = Some(k)
-                }
-
-                let key = key.as_ref().unwrap().as_str().as_bytes();
-                let k = $compat::HeaderName::from_bytes(key).unwrap();
-                let v = $compat::HeaderValue::from_bytes(v.as_bytes()).unwrap();
-
-                $name.append(k, v);
-            })
-        }
-    };
-}
-
 #[macro_export]
 macro_rules! impl_rpc_t {
     ($($name:ident, $upstream:expr, $path:expr),*) => {
@@ -108,3 +345,64 @@ macro_rules! impl_rpc_t {
         )*
     };
 }
+
+#[cfg(test)]
+mod test {
+    use http_body_util::BodyExt;
+    use lib_utils::error::ServerError;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    generate_router!(TestRouter, ("/test", GET, || async { "ok" }));
+
+    #[tokio::test]
+    async fn test_method_not_allowed() {
+        let request = |method: http::Method| {
+            http::Request::builder()
+                .method(method)
+                .uri("/test")
+                .body(axum::body::Body::empty())
+                .unwrap()
+        };
+
+        let response = TestRouter::new()
+            .oneshot(request(http::Method::GET))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+
+        let response = TestRouter::new()
+            .oneshot(request(http::Method::POST))
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()["code"].as_i64(),
+            Some(ServerError::MethodNotAllowed as i64)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_buffer_body_limited() {
+        let body = || axum::body::Body::from(vec![0u8; 16]);
+
+        assert_eq!(buffer_body_limited(body(), 16).await.unwrap().len(), 16);
+
+        let e = buffer_body_limited(body(), 15).await.unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<lib_utils::error::ServerError>(),
+            Some(lib_utils::error::ServerError::PayloadTooLarge)
+        ));
+
+        // Length unknown until polled
+        let unsized_body = axum::body::Body::new(
+            http_body_util::Full::new(bytes::Bytes::from(vec![0u8; 16])).map_frame(|frame| frame),
+        );
+        let e = buffer_body_limited(unsized_body, 15).await.unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<lib_utils::error::ServerError>(),
+            Some(lib_utils::error::ServerError::PayloadTooLarge)
+        ));
+    }
+}
@@ -0,0 +1,399 @@
+//! Rate limiting by route groups, see
+//! [RateLimitConfig](lib_core::server::config::RateLimitConfig).
+//!
+//! [rate_limit_middleware] resolves the group and key of a request, while
+//! tokens are taken where it's known whether the response is from cache.
+
+use axum::response::Response as AxumResponse;
+use http::{header::AUTHORIZATION, Extensions, HeaderMap};
+
+use std::{
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    sync::{Arc, OnceLock},
+};
+
+use crate::handler::ErrorResponse;
+use lib_core::server::{
+    config::{BucketConfig, RateLimitGroupConfig, RateLimitKey, CONFIG_RATE_LIMIT},
+    rate_limit::RateLimiter,
+};
+use lib_utils::{error::ServerError, model::response::GeneralResponse};
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+const X_BILI_MID: &str = "x-bili-mid";
+
+/// Whether the response is from cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CacheStatus {
+    Hit,
+    Miss,
+}
+
+#[derive(Debug)]
+struct LimitGroup {
+    prefix: String,
+    key: RateLimitKey,
+    hit: Option<RateLimiter<String>>,
+    miss: Option<RateLimiter<String>>,
+    /// Limits of client IP along with the user, see [RateLimitKey::AccessKey]
+    ip_hit: Option<RateLimiter<String>>,
+    ip_miss: Option<RateLimiter<String>>,
+}
+
+impl From<&RateLimitGroupConfig> for LimitGroup {
+    fn from(config: &RateLimitGroupConfig) -> Self {
+        let limiter =
+            |bucket: Option<_>| bucket.map(|b: BucketConfig| RateLimiter::new(b.rate, b.burst));
+
+        Self {
+            prefix: config.prefix.clone(),
+            key: config.key,
+            hit: limiter(config.hit),
+            miss: limiter(config.miss),
+            ip_hit: limiter(config.ip_hit.or(config.hit)),
+            ip_miss: limiter(config.ip_miss.or(config.miss)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Limiters {
+    trusted_proxies: Vec<IpAddr>,
+    /// Sorted by prefix length, the longest first
+    groups: Vec<Arc<LimitGroup>>,
+}
+
+fn limiters() -> &'static Limiters {
+    static LIMITERS: OnceLock<Limiters> = OnceLock::new();
+
+    LIMITERS.get_or_init(|| {
+        let Some(config) = CONFIG_RATE_LIMIT.get() else {
+            return Limiters::default();
+        };
+
+        let mut groups: Vec<_> = config
+            .groups
+            .iter()
+            .map(|group| Arc::new(LimitGroup::from(group)))
+            .collect();
+        groups.sort_by(|a, b| b.prefix.len().cmp(&a.prefix.len()));
+
+        Limiters {
+            trusted_proxies: config.trusted_proxies.clone(),
+            groups,
+        }
+    })
+}
+
+/// Middleware resolving the rate limit of a request, inserted into its
+/// extensions, see [RateLimit::check].
+pub async fn rate_limit_middleware(
+    mut req: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> AxumResponse {
+    let limiters = limiters();
+
+    if let Some(group) = limiters
+        .groups
+        .iter()
+        .find(|group| req.uri().path().starts_with(group.prefix.as_str()))
+    {
+        let peer = req
+            .extensions()
+            .get::<axum::extract::ConnectInfo<SocketAddr>>()
+            .map(|info| info.0.ip());
+        let client_ip = client_ip(peer, req.headers(), &limiters.trusted_proxies);
+
+        // The user is not verified here, so that one can't escape the limit
+        // of the IP with made-up ones
+        let (key, ip) = match group.key {
+            RateLimitKey::AccessKey => match user_key(req.headers(), req.uri().query()) {
+                Some(user) => (user, Some(ip_key(client_ip))),
+                None => (ip_key(client_ip), None),
+            },
+            RateLimitKey::Ip => (ip_key(client_ip), None),
+            RateLimitKey::Global => (String::new(), None),
+        };
+
+        req.extensions_mut().insert(RateLimit {
+            group: group.clone(),
+            key,
+            ip,
+        });
+    }
+
+    next.run(req).await
+}
+
+/// Rate limit of a request, in its extensions if any group matched.
+#[derive(Debug, Clone)]
+pub(crate) struct RateLimit {
+    group: Arc<LimitGroup>,
+    /// Key of the bucket by the group
+    key: String,
+    /// Key of the client IP along with the user, see [RateLimitKey::AccessKey]
+    ip: Option<String>,
+}
+
+impl RateLimit {
+    /// Take a token of each bucket for the request, returning the `429`
+    /// response if limited by any, marked as [ErrorResponse].
+    ///
+    /// All buckets are checked before any token taken, so that a request
+    /// limited by one never costs others.
+    pub(crate) fn check(extensions: &Extensions, status: CacheStatus) -> Result<(), AxumResponse> {
+        let Some(limit) = extensions.get::<Self>() else {
+            return Ok(());
+        };
+
+        let (limiter, ip_limiter) = match status {
+            CacheStatus::Hit => (&limit.group.hit, &limit.group.ip_hit),
+            CacheStatus::Miss => (&limit.group.miss, &limit.group.ip_miss),
+        };
+        let buckets = [
+            limiter.as_ref().map(|limiter| (limiter, &limit.key)),
+            ip_limiter.as_ref().zip(limit.ip.as_ref()),
+        ];
+        let buckets = buckets.iter().flatten();
+
+        let limited = |key: &str, wait: std::time::Duration| {
+            tracing::warn!(
+                "Rate limited [{}] of group [{}], cache {:?}",
+                key,
+                limit.group.prefix,
+                status
+            );
+            let retry_after = wait.as_secs().saturating_add(1);
+            let mut response = GeneralResponse::<()>::new_error(
+                ServerError::ReqTooFrequent.into(),
+                ServerError::ReqTooFrequent,
+            )
+            .with_retry_after(retry_after)
+            .into_response(false);
+            response.extensions_mut().insert(ErrorResponse);
+            response
+        };
+
+        buckets.clone().try_for_each(|(limiter, key)| {
+            limiter
+                .check(key)
+                .map_err(|wait| limited(key.as_str(), wait))
+        })?;
+        buckets.for_each(|(limiter, key)| {
+            // Taken by concurrent requests since checked, let it go
+            let _ = limiter.acquire((*key).clone());
+        });
+        Ok(())
+    }
+}
+
+/// Key of the client IP, IPv6 ones by /64, which is usually assigned to a
+/// single client.
+#[inline]
+fn ip_key(ip: Option<IpAddr>) -> String {
+    match ip.map(|ip| ip.to_canonical()) {
+        Some(IpAddr::V6(ip)) => {
+            let prefix = Ipv6Addr::from(u128::from(ip) & (u128::MAX << 64));
+            format!("ip:{}/64", prefix)
+        }
+        Some(ip) => format!("ip:{}", ip),
+        None => "ip:unknown".to_owned(),
+    }
+}
+
+/// Key of the user, by mid if given, or `access_key`, see [RateLimitKey::AccessKey].
+fn user_key(headers: &HeaderMap, query: Option<&str>) -> Option<String> {
+    let mid = headers
+        .get(X_BILI_MID)
+        .and_then(|mid| mid.to_str().ok())
+        .or_else(|| query?.split('&').find_map(|pair| pair.strip_prefix("mid=")))
+        .and_then(|mid| mid.parse::<u64>().ok())
+        .filter(|mid| *mid > 0);
+
+    match mid {
+        Some(mid) => Some(format!("mid:{}", mid)),
+        None => access_key(headers, query).map(|access_key| format!("ak:{}", access_key)),
+    }
+}
+
+/// IP of the client, from `X-Forwarded-For` if the peer is a trusted proxy.
+///
+/// Hops are checked from the nearest one, the first untrusted one is used.
+fn client_ip(peer: Option<IpAddr>, headers: &HeaderMap, trusted: &[IpAddr]) -> Option<IpAddr> {
+    let mut ip = peer?;
+
+    let hops = headers
+        .get_all(X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect::<Vec<_>>();
+
+    for hop in hops.into_iter().rev() {
+        if !trusted.contains(&ip) {
+            break;
+        }
+        match hop.trim().parse() {
+            Ok(hop) => ip = hop,
+            Err(_) => break,
+        }
+    }
+
+    Some(ip)
+}
+
+/// `access_key` of the user, from query or gRPC `authorization` metadata.
+fn access_key<'a>(headers: &'a HeaderMap, query: Option<&'a str>) -> Option<&'a str> {
+    query
+        .and_then(|query| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("access_key="))
+        })
+        .or_else(|| {
+            headers
+                .get(AUTHORIZATION)?
+                .to_str()
+                .ok()?
+                .strip_prefix("identify_v1 ")
+        })
+        .filter(|access_key| !access_key.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn test_client_ip() {
+        let trusted: Vec<IpAddr> = vec![[127, 0, 0, 1].into(), [10, 0, 0, 1].into()];
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            X_FORWARDED_FOR,
+            HeaderValue::from_static("1.1.1.1, 2.2.2.2, 10.0.0.1"),
+        );
+
+        // Untrusted peer
+        let peer = Some([3, 3, 3, 3].into());
+        assert_eq!(client_ip(peer, &headers, &trusted), peer);
+
+        let peer = Some([127, 0, 0, 1].into());
+        assert_eq!(
+            client_ip(peer, &headers, &trusted),
+            Some([2, 2, 2, 2].into())
+        );
+
+        headers.insert(X_FORWARDED_FOR, HeaderValue::from_static("invalid"));
+        assert_eq!(client_ip(peer, &headers, &trusted), peer);
+
+        assert_eq!(client_ip(None, &headers, &trusted), None);
+    }
+
+    #[test]
+    fn test_access_key() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            access_key(&headers, Some("a=1&access_key=abc")),
+            Some("abc")
+        );
+        assert_eq!(access_key(&headers, Some("access_key=")), None);
+
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("identify_v1 def"));
+        assert_eq!(access_key(&headers, None), Some("def"));
+    }
+
+    #[test]
+    fn test_ip_key() {
+        assert_eq!(ip_key(Some([1, 1, 1, 1].into())), "ip:1.1.1.1");
+        assert_eq!(ip_key(None), "ip:unknown");
+
+        // By /64
+        let ip: IpAddr = "2001:db8:1:2:3:4:5:6".parse().unwrap();
+        assert_eq!(ip_key(Some(ip)), "ip:2001:db8:1:2::/64");
+        let another: IpAddr = "2001:db8:1:2:ffff::1".parse().unwrap();
+        assert_eq!(ip_key(Some(ip)), ip_key(Some(another)));
+
+        let mapped: IpAddr = "::ffff:1.1.1.1".parse().unwrap();
+        assert_eq!(ip_key(Some(mapped)), "ip:1.1.1.1");
+    }
+
+    #[test]
+    fn test_user_key() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(user_key(&headers, None), None);
+        assert_eq!(
+            user_key(&headers, Some("access_key=abc&mid=0")).as_deref(),
+            Some("ak:abc")
+        );
+        assert_eq!(
+            user_key(&headers, Some("access_key=abc&mid=114514")).as_deref(),
+            Some("mid:114514")
+        );
+
+        headers.insert(X_BILI_MID, HeaderValue::from_static("1919810"));
+        assert_eq!(
+            user_key(&headers, Some("access_key=abc&mid=114514")).as_deref(),
+            Some("mid:1919810")
+        );
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let group = RateLimitGroupConfig {
+            prefix: "/x/".to_owned(),
+            key: RateLimitKey::Ip,
+            hit: None,
+            miss: Some(BucketConfig {
+                rate: 1.0,
+                burst: 1,
+            }),
+            ip_hit: None,
+            ip_miss: None,
+        };
+        let mut extensions = Extensions::new();
+        assert!(RateLimit::check(&extensions, CacheStatus::Miss).is_ok());
+
+        extensions.insert(RateLimit {
+            group: Arc::new(LimitGroup::from(&group)),
+            key: ip_key(None),
+            ip: None,
+        });
+        assert!(RateLimit::check(&extensions, CacheStatus::Hit).is_ok());
+        assert!(RateLimit::check(&extensions, CacheStatus::Hit).is_ok());
+        assert!(RateLimit::check(&extensions, CacheStatus::Miss).is_ok());
+
+        let response = RateLimit::check(&extensions, CacheStatus::Miss).unwrap_err();
+        assert!(response.headers().get(http::header::RETRY_AFTER).is_none());
+        assert!(response.extensions().get::<ErrorResponse>().is_some());
+
+        // Made-up users share the looser limit of the IP
+        let group = Arc::new(LimitGroup::from(&RateLimitGroupConfig {
+            key: RateLimitKey::AccessKey,
+            miss: Some(BucketConfig {
+                rate: 0.0,
+                burst: 1,
+            }),
+            ip_miss: Some(BucketConfig {
+                rate: 0.0,
+                burst: 2,
+            }),
+            ..group
+        }));
+        let limit = |user: &str| {
+            let mut extensions = Extensions::new();
+            extensions.insert(RateLimit {
+                group: group.clone(),
+                key: user.to_owned(),
+                ip: Some(ip_key(None)),
+            });
+            RateLimit::check(&extensions, CacheStatus::Miss)
+        };
+        assert!(limit("mid:1").is_ok());
+        // Limited by the user, costing nothing of the IP
+        assert!(limit("mid:1").is_err());
+        assert!(limit("mid:2").is_ok());
+        assert!(limit("mid:3").is_err());
+    }
+}